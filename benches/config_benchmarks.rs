@@ -1,8 +1,9 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 use scratchpad::Config;
+use std::hint::black_box;
 
 fn bench_config_creation(c: &mut Criterion) {
-    c.bench_function("config_default", |b| b.iter(|| Config::default()));
+    c.bench_function("config_default", |b| b.iter(Config::default));
 }
 
 fn bench_config_serialization(c: &mut Criterion) {
@@ -1,5 +1,6 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 use scratchpad::Scratch;
+use std::hint::black_box;
 
 fn bench_scratch_creation(c: &mut Criterion) {
     c.bench_function("scratch_new", |b| {
@@ -2,14 +2,19 @@
 //!
 //! This module provides background tasks that stream Docker events and container logs
 //! to connected WebSocket clients via the broadcast hub.
+//! Resource usage is only sampled for scratches that have stats subscribers.
 
 use crate::docker::DockerClient;
 use crate::error::Result;
+use crate::scratch;
 use std::sync::Arc;
 use tracing::{debug, error, info};
 
 use super::websocket::{ServerMessage, WsBroadcastHub};
 
+/// How often resource usage is sampled for subscribed scratches
+const STATS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
 /// Start background event streaming tasks
 ///
 /// This spawns tasks that monitor Docker events and stream them to WebSocket clients.
@@ -23,9 +28,40 @@ pub fn start_event_streaming(hub: Arc<WsBroadcastHub>, docker: Arc<DockerClient>
         }
     });
 
+    // Spawn task to sample resource usage
+    tokio::spawn(stream_stats(hub, docker));
+
     info!("Event streaming tasks started");
 }
 
+/// Broadcast the resource usage of every scratch with `stats:` subscribers
+async fn stream_stats(hub: Arc<WsBroadcastHub>, docker: Arc<DockerClient>) {
+    debug!("Starting stats stream");
+
+    let mut interval = tokio::time::interval(STATS_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        // Drop channels whose clients have gone so they aren't sampled
+        hub.cleanup().await;
+        for channel in hub.get_channels().await {
+            let Some(scratch) = channel.strip_prefix("stats:") else {
+                continue;
+            };
+
+            match scratch::scratch_stats(&docker, scratch).await {
+                Ok(stats) => {
+                    hub.broadcast(&channel, ServerMessage::Stats { stats })
+                        .await
+                }
+                Err(e) => debug!("Failed to get stats for {}: {}", scratch, e),
+            }
+        }
+    }
+}
+
 /// Stream Docker events and broadcast them to connected clients
 async fn stream_docker_events(hub: Arc<WsBroadcastHub>, docker: Arc<DockerClient>) -> Result<()> {
     use bollard::query_parameters::ListContainersOptions;
@@ -0,0 +1,320 @@
+//! Background jobs for long-running scratch operations
+//!
+//! Create, update, restart, delete, clone and bulk requests are queued as jobs so
+//! HTTP callers (and GitHub webhooks) get an answer straight away, and so are
+//! the redeploys started by the image watcher. Each job records the progress steps
+//! reported by the scratch lifecycle and publishes them to the `jobs:<id>`
+//! WebSocket channel as they happen.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::{mpsc, RwLock};
+
+use crate::error::Result;
+use crate::scratch;
+
+use super::websocket::{job_channel, ServerMessage, WsBroadcastHub};
+
+/// How many finished jobs are kept around for `GET /api/jobs`
+const MAX_FINISHED_JOBS: usize = 200;
+
+/// The operation a job performs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Create,
+    Update,
+    Restart,
+    Delete,
+    Redeploy,
+    Clone,
+    Bulk,
+}
+
+impl JobKind {
+    /// Get the kind as a string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::Create => "create",
+            JobKind::Update => "update",
+            JobKind::Restart => "restart",
+            JobKind::Delete => "delete",
+            JobKind::Redeploy => "redeploy",
+            JobKind::Clone => "clone",
+            JobKind::Bulk => "bulk",
+        }
+    }
+}
+
+/// Where a job is in its lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobStatus {
+    /// Get the status as a string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+        }
+    }
+
+    /// Whether the job has finished, successfully or not
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Succeeded | JobStatus::Failed)
+    }
+}
+
+/// A progress step reached by a job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobProgress {
+    pub step: String,
+    pub message: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A queued, running or finished scratch operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub kind: JobKind,
+    pub scratch: String,
+    pub status: JobStatus,
+    pub progress: Vec<JobProgress>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// In-memory record of jobs run by the server
+pub struct JobQueue {
+    jobs: RwLock<HashMap<String, Job>>,
+    ws_hub: Arc<WsBroadcastHub>,
+}
+
+impl JobQueue {
+    /// Create a job queue publishing to the given hub
+    pub fn new(ws_hub: Arc<WsBroadcastHub>) -> Self {
+        Self {
+            jobs: RwLock::new(HashMap::new()),
+            ws_hub,
+        }
+    }
+
+    /// Queue an operation on a scratch and run it in the background
+    ///
+    /// Returns the job as queued; poll [`JobQueue::get`] or subscribe to its
+    /// channel to follow it.
+    pub async fn spawn<F>(self: &Arc<Self>, kind: JobKind, scratch: &str, operation: F) -> Job
+    where
+        F: Future<Output = Result<()>> + Send + 'static,
+    {
+        let job = Job {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind,
+            scratch: scratch.to_string(),
+            status: JobStatus::Queued,
+            progress: Vec::new(),
+            error: None,
+            created_at: Utc::now(),
+            finished_at: None,
+        };
+        self.insert(job.clone()).await;
+
+        let queue = self.clone();
+        let id = job.id.clone();
+        let scratch_name = job.scratch.clone();
+        tokio::spawn(async move {
+            queue.set_status(&id, JobStatus::Running, None).await;
+
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let forwarder = {
+                let queue = queue.clone();
+                let id = id.clone();
+                tokio::spawn(async move {
+                    while let Some(step) = rx.recv().await {
+                        queue.record_progress(&id, step).await;
+                    }
+                })
+            };
+
+            let result = scratch::with_progress(tx, operation).await;
+            // The reporter was dropped with the scope, so this drains and ends
+            let _ = forwarder.await;
+
+            match result {
+                Ok(()) => queue.set_status(&id, JobStatus::Succeeded, None).await,
+                Err(e) => {
+                    tracing::error!(
+                        "Job {} ({} {}) failed: {}",
+                        id,
+                        kind.as_str(),
+                        scratch_name,
+                        e
+                    );
+                    queue
+                        .set_status(&id, JobStatus::Failed, Some(e.to_string()))
+                        .await
+                }
+            }
+        });
+
+        job
+    }
+
+    /// Get a job by id
+    pub async fn get(&self, id: &str) -> Option<Job> {
+        self.jobs.read().await.get(id).cloned()
+    }
+
+    /// List all known jobs, newest first
+    pub async fn list(&self) -> Vec<Job> {
+        let mut jobs: Vec<Job> = self.jobs.read().await.values().cloned().collect();
+        jobs.sort_by_key(|j| std::cmp::Reverse(j.created_at));
+        jobs
+    }
+
+    async fn insert(&self, job: Job) {
+        let mut jobs = self.jobs.write().await;
+
+        // Forget the oldest finished jobs once there are too many
+        let mut finished: Vec<(DateTime<Utc>, String)> = jobs
+            .values()
+            .filter(|j| j.status.is_finished())
+            .map(|j| (j.created_at, j.id.clone()))
+            .collect();
+        if finished.len() >= MAX_FINISHED_JOBS {
+            finished.sort();
+            for (_, id) in finished.iter().take(finished.len() + 1 - MAX_FINISHED_JOBS) {
+                jobs.remove(id);
+            }
+        }
+
+        jobs.insert(job.id.clone(), job);
+    }
+
+    async fn record_progress(&self, id: &str, step: scratch::ProgressStep) {
+        let progress = JobProgress {
+            step: step.step,
+            message: step.message,
+            timestamp: Utc::now(),
+        };
+
+        let job = {
+            let mut jobs = self.jobs.write().await;
+            let Some(job) = jobs.get_mut(id) else {
+                return;
+            };
+            job.progress.push(progress.clone());
+            job.clone()
+        };
+
+        self.publish(&job, Some(&progress)).await;
+    }
+
+    async fn set_status(&self, id: &str, status: JobStatus, error: Option<String>) {
+        let job = {
+            let mut jobs = self.jobs.write().await;
+            let Some(job) = jobs.get_mut(id) else {
+                return;
+            };
+            job.status = status;
+            job.error = error;
+            if status.is_finished() {
+                job.finished_at = Some(Utc::now());
+            }
+            job.clone()
+        };
+
+        self.publish(&job, None).await;
+    }
+
+    async fn publish(&self, job: &Job, progress: Option<&JobProgress>) {
+        let msg = ServerMessage::JobUpdate {
+            job_id: job.id.clone(),
+            scratch: job.scratch.clone(),
+            status: job.status,
+            step: progress.map(|p| p.step.clone()),
+            message: progress.map(|p| p.message.clone()),
+            error: job.error.clone(),
+            timestamp: Utc::now().to_rfc3339(),
+        };
+        self.ws_hub.broadcast(&job_channel(&job.id), msg).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    async fn wait_until_finished(queue: &JobQueue, id: &str) -> Job {
+        for _ in 0..100 {
+            let job = queue.get(id).await.unwrap();
+            if job.status.is_finished() {
+                return job;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("job {} did not finish", id);
+    }
+
+    #[tokio::test]
+    async fn test_job_records_progress_and_publishes() {
+        let hub = Arc::new(WsBroadcastHub::new());
+        let queue = Arc::new(JobQueue::new(hub.clone()));
+
+        let (tx, mut rx) = mpsc::channel(100);
+        let (start_tx, start_rx) = tokio::sync::oneshot::channel::<()>();
+        let job = queue
+            .spawn(JobKind::Create, "feature-x", async move {
+                let _ = start_rx.await;
+                scratch::report("pull", "Pulling images");
+                scratch::report("up", "Starting containers");
+                Ok(())
+            })
+            .await;
+        assert_eq!(job.kind, JobKind::Create);
+
+        hub.subscribe(&job_channel(&job.id), tx).await;
+        start_tx.send(()).unwrap();
+
+        let job = wait_until_finished(&queue, &job.id).await;
+        assert_eq!(job.status, JobStatus::Succeeded);
+        let steps: Vec<_> = job.progress.iter().map(|p| p.step.as_str()).collect();
+        assert_eq!(steps, ["pull", "up"]);
+        assert!(job.finished_at.is_some());
+
+        let mut statuses = Vec::new();
+        while let Ok(ServerMessage::JobUpdate { status, .. }) = rx.try_recv() {
+            statuses.push(status);
+        }
+        assert_eq!(statuses.last(), Some(&JobStatus::Succeeded));
+    }
+
+    #[tokio::test]
+    async fn test_failed_job_keeps_error() {
+        let queue = Arc::new(JobQueue::new(Arc::new(WsBroadcastHub::new())));
+        let job = queue
+            .spawn(JobKind::Delete, "feature-x", async {
+                Err(Error::ScratchNotFound("feature-x".to_string()))
+            })
+            .await;
+
+        let job = wait_until_finished(&queue, &job.id).await;
+        assert_eq!(job.status, JobStatus::Failed);
+        assert_eq!(job.error.as_deref(), Some("Scratch 'feature-x' not found"));
+        assert_eq!(queue.list().await.len(), 1);
+    }
+}
@@ -1,8 +1,11 @@
 //! HTTP API server
 
 pub mod events;
+pub mod jobs;
+pub mod reaper;
 pub mod routes;
 pub mod server;
+pub mod watcher;
 pub mod websocket;
 
 pub use server::*;
@@ -0,0 +1,82 @@
+//! Background reaper for expired and idle scratches
+//!
+//! Periodically deletes scratches whose TTL has passed, stops scratches that
+//! have been idle too long, and notifies WebSocket clients subscribed to the
+//! scratch status channel.
+
+use tracing::{error, info, warn};
+
+use crate::scratch;
+
+use super::server::{AppState, SharedState};
+use super::websocket::{status_channel, ServerMessage};
+
+/// Start the background task that deletes expired scratches and stops idle ones
+pub fn start_reaper(state: SharedState) {
+    tokio::spawn(async move {
+        {
+            let app_state = state.read().await;
+            if app_state.config.scratch.idle_timeout.is_some()
+                && !scratch::activity_tracked(&app_state.config)
+            {
+                warn!("scratch.idle_timeout is set but nginx.server_upstream isn't; idle scratches won't be stopped");
+            }
+        }
+
+        loop {
+            let interval = {
+                let app_state = state.read().await;
+                reaper_interval(&app_state.config.scratch.reaper_interval)
+            };
+            tokio::time::sleep(interval).await;
+
+            // Work on a copy so config updates don't wait for the reaper
+            let app_state = state.read().await.clone();
+            match scratch::reap_expired_scratches(&app_state.config, &app_state.docker).await {
+                Ok(reaped) => notify(&app_state, reaped, "expired").await,
+                Err(e) => error!("Scratch reaper error: {}", e),
+            }
+
+            match scratch::stop_idle_scratches(
+                &app_state.config,
+                &app_state.docker,
+                &app_state.activity,
+            )
+            .await
+            {
+                Ok(stopped) => notify(&app_state, stopped, "stopped").await,
+                Err(e) => error!("Idle scratch check error: {}", e),
+            }
+        }
+    });
+
+    info!("Scratch reaper started");
+}
+
+/// Broadcast a status change for each affected scratch
+async fn notify(app_state: &AppState, names: Vec<String>, status: &str) {
+    for name in names {
+        let msg = ServerMessage::StatusChange {
+            scratch: name.clone(),
+            status: status.to_string(),
+            service: None,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        };
+        app_state
+            .ws_hub
+            .broadcast(&status_channel(&name), msg)
+            .await;
+    }
+}
+
+/// Parse the configured reaper interval, falling back to five minutes
+fn reaper_interval(value: &str) -> std::time::Duration {
+    scratch::parse_duration(value)
+        .ok()
+        .and_then(|d| d.to_std().ok())
+        .filter(|d| !d.is_zero())
+        .unwrap_or_else(|| {
+            error!("Invalid scratch.reaper_interval '{}', using 5m", value);
+            std::time::Duration::from_secs(300)
+        })
+}
@@ -2,14 +2,17 @@
 
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
+use super::jobs::{Job, JobKind};
 use super::server::SharedState;
 use crate::auth;
+use crate::error::Error;
 use crate::scratch;
 use crate::services;
 
@@ -17,10 +20,107 @@ use crate::services;
 
 #[derive(Debug, Deserialize)]
 pub struct CreateScratchRequest {
-    pub branch: String,
+    /// Main branch; defaults to the first repository's branch in `branches`
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// Branch per repository, e.g. `{"api": "feat-x", "web": "main"}`
+    #[serde(default)]
+    pub branches: BTreeMap<String, String>,
     pub name: Option<String>,
     pub profile: Option<String>,
     pub template: Option<String>,
+    pub ttl: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    /// Finish the job once the containers are started, without waiting for
+    /// them to become healthy
+    #[serde(default)]
+    pub no_wait: bool,
+}
+
+impl CreateScratchRequest {
+    /// The scratch's main branch
+    fn main_branch(&self) -> Option<String> {
+        self.branch
+            .clone()
+            .or_else(|| self.branches.values().next().cloned())
+            .filter(|branch| !branch.is_empty())
+    }
+}
+
+/// Filters for `GET /api/scratches`; `tag` takes a comma separated list
+#[derive(Debug, Default, Deserialize)]
+pub struct ListScratchesQuery {
+    pub owner: Option<String>,
+    pub tag: Option<String>,
+    pub status: Option<String>,
+    pub branch: Option<String>,
+}
+
+impl From<ListScratchesQuery> for scratch::ScratchFilter {
+    fn from(query: ListScratchesQuery) -> Self {
+        let non_empty = |value: Option<String>| value.filter(|v| !v.is_empty());
+        Self {
+            owner: non_empty(query.owner),
+            tags: query
+                .tag
+                .iter()
+                .flat_map(|tags| tags.split(','))
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+            status: non_empty(query.status),
+            branch: non_empty(query.branch),
+        }
+    }
+}
+
+/// Env changes for a scratch; a null value removes the override
+#[derive(Debug, Deserialize)]
+pub struct UpdateEnvRequest {
+    pub env: HashMap<String, Option<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateScratchRequest {
+    /// Restart the scratch after re-rendering its compose file
+    #[serde(default)]
+    pub restart: bool,
+    /// Restart without downtime by switching traffic to a new ingress
+    /// container once it's healthy
+    #[serde(default)]
+    pub rolling: bool,
+}
+
+/// Body of `POST /api/scratches/bulk`
+#[derive(Debug, Deserialize)]
+pub struct BulkRequest {
+    pub action: scratch::BulkAction,
+    #[serde(default)]
+    pub filter: scratch::BulkSelector,
+    pub concurrency: Option<usize>,
+    /// Only report which scratches match
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExtendScratchRequest {
+    pub duration: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CloneScratchRequest {
+    pub name: String,
+    pub branch: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateSnapshotRequest {
+    pub name: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -136,11 +236,18 @@ pub async fn health() -> impl IntoResponse {
 
 // Scratch routes
 
-pub async fn list_scratches(State(state): State<SharedState>) -> impl IntoResponse {
+pub async fn list_scratches(
+    State(state): State<SharedState>,
+    Query(query): Query<ListScratchesQuery>,
+) -> impl IntoResponse {
     let state = state.read().await;
+    let filter = scratch::ScratchFilter::from(query);
 
     match scratch::list_scratches(&state.config, &state.docker).await {
-        Ok(scratches) => (StatusCode::OK, Json(ApiResponse::ok(scratches))),
+        Ok(mut scratches) => {
+            scratches.retain(|s| filter.matches(s));
+            (StatusCode::OK, Json(ApiResponse::ok(scratches)))
+        }
         Err(_e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ApiResponse::ok(Vec::<scratch::ScratchStatus>::new())),
@@ -150,36 +257,96 @@ pub async fn list_scratches(State(state): State<SharedState>) -> impl IntoRespon
 
 pub async fn create_scratch(
     State(state): State<SharedState>,
+    headers: HeaderMap,
     Json(req): Json<CreateScratchRequest>,
 ) -> impl IntoResponse {
-    let state = state.read().await;
+    let owner = auth::extract_user_from_headers(&headers)
+        .ok()
+        .map(|claims| claims.username);
+    let Some(branch) = req.main_branch() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::<()>::err("branch or branches is required")),
+        )
+            .into_response();
+    };
+    let name = req
+        .name
+        .clone()
+        .unwrap_or_else(|| scratch::Scratch::sanitize_name(&branch));
+    let state = {
+        let state = state.read().await;
+        if let Err(e) = scratch::ScratchLock::check(&state.config, &name).and_then(|()| {
+            scratch::check_quotas(&state.config, owner.as_deref(), req.profile.as_deref())
+        }) {
+            return scratch_error(e);
+        }
+        state.clone()
+    };
 
-    match scratch::create_scratch(
-        &state.config,
-        &state.docker,
-        &req.branch,
-        req.name,
-        req.profile,
-        req.template,
-    )
-    .await
-    {
-        Ok(scratch_instance) => (
-            StatusCode::CREATED,
-            Json(ApiResponse::ok(scratch_instance.name)),
-        ),
-        Err(e) => {
-            let error_msg = e.to_string();
-            (
-                StatusCode::BAD_REQUEST,
-                Json(ApiResponse {
-                    success: false,
-                    data: None,
-                    error: Some(error_msg),
-                }),
+    let job = state
+        .jobs
+        .clone()
+        .spawn(JobKind::Create, &name, async move {
+            scratch::create_scratch(
+                &state.config,
+                &state.docker,
+                &branch,
+                scratch::CreateScratchOptions {
+                    name: req.name,
+                    branches: req.branches,
+                    profile: req.profile,
+                    template: req.template,
+                    ttl: req.ttl,
+                    env: req.env,
+                    owner,
+                    description: req.description,
+                    tags: req.tags,
+                    no_wait: req.no_wait,
+                },
             )
+            .await
+            .map(|_| ())
+        })
+        .await;
+
+    job_accepted(job)
+}
+
+pub async fn clone_scratch(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+    Json(req): Json<CloneScratchRequest>,
+) -> impl IntoResponse {
+    let state = {
+        let state = state.read().await;
+        let checked = scratch::load_scratch_config(&state.config, &name).and_then(|source| {
+            scratch::ScratchLock::check(&state.config, &name)?;
+            scratch::ScratchLock::check(&state.config, &req.name)?;
+            scratch::check_quotas(
+                &state.config,
+                source.owner.as_deref(),
+                source.profile.as_deref(),
+            )
+        });
+        if let Err(e) = checked {
+            return scratch_error(e);
         }
-    }
+        state.clone()
+    };
+
+    let target = req.name.clone();
+    let job = state
+        .jobs
+        .clone()
+        .spawn(JobKind::Clone, &target, async move {
+            scratch::clone_scratch(&state.config, &state.docker, &name, &req.name, req.branch)
+                .await
+                .map(|_| ())
+        })
+        .await;
+
+    job_accepted(job)
 }
 
 pub async fn get_scratch(
@@ -200,23 +367,43 @@ pub async fn get_scratch(
     }
 }
 
-pub async fn delete_scratch(
+pub async fn update_scratch_metadata(
     State(state): State<SharedState>,
     Path(name): Path<String>,
+    Json(req): Json<scratch::ScratchMetadataUpdate>,
 ) -> impl IntoResponse {
     let state = state.read().await;
 
-    match scratch::delete_scratch(&state.config, &state.docker, &name, true).await {
-        Ok(()) => (StatusCode::OK, Json(ApiResponse::ok("deleted".to_string()))),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ApiResponse {
-                success: false,
-                data: None,
-                error: Some(e.to_string()),
-            }),
-        ),
+    if let Err(e) = scratch::update_scratch_metadata(&state.config, &name, req) {
+        return scratch_error(e);
     }
+    match scratch::get_scratch_status(&state.config, &state.docker, &name).await {
+        Ok(status) => (StatusCode::OK, Json(ApiResponse::ok(status))).into_response(),
+        Err(e) => error_response(e, StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+pub async fn delete_scratch(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let state = {
+        let state = state.read().await;
+        if let Err(e) = scratch::ScratchLock::check(&state.config, &name) {
+            return scratch_error(e);
+        }
+        state.clone()
+    };
+
+    let job = state
+        .jobs
+        .clone()
+        .spawn(JobKind::Delete, &name.clone(), async move {
+            scratch::delete_scratch(&state.config, &state.docker, &name, true).await
+        })
+        .await;
+
+    job_accepted(job)
 }
 
 pub async fn start_scratch(
@@ -226,15 +413,8 @@ pub async fn start_scratch(
     let state = state.read().await;
 
     match scratch::start_scratch(&state.config, &state.docker, &name).await {
-        Ok(()) => (StatusCode::OK, Json(ApiResponse::ok("started".to_string()))),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ApiResponse {
-                success: false,
-                data: None,
-                error: Some(e.to_string()),
-            }),
-        ),
+        Ok(()) => (StatusCode::OK, Json(ApiResponse::ok("started".to_string()))).into_response(),
+        Err(e) => error_response(e, StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
 
@@ -245,40 +425,282 @@ pub async fn stop_scratch(
     let state = state.read().await;
 
     match scratch::stop_scratch(&state.config, &state.docker, &name).await {
-        Ok(()) => (StatusCode::OK, Json(ApiResponse::ok("stopped".to_string()))),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ApiResponse {
-                success: false,
-                data: None,
-                error: Some(e.to_string()),
-            }),
-        ),
+        Ok(()) => (StatusCode::OK, Json(ApiResponse::ok("stopped".to_string()))).into_response(),
+        Err(e) => error_response(e, StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
 
 pub async fn restart_scratch(
     State(state): State<SharedState>,
     Path(name): Path<String>,
+) -> impl IntoResponse {
+    let state = {
+        let state = state.read().await;
+        if let Err(e) = scratch::load_scratch_config(&state.config, &name)
+            .and_then(|_| scratch::ScratchLock::check(&state.config, &name))
+        {
+            return scratch_error(e);
+        }
+        state.clone()
+    };
+
+    let job = state
+        .jobs
+        .clone()
+        .spawn(JobKind::Restart, &name.clone(), async move {
+            scratch::restart_scratch(&state.config, &state.docker, &name).await
+        })
+        .await;
+
+    job_accepted(job)
+}
+
+pub async fn update_scratch(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+    req: Option<Json<UpdateScratchRequest>>,
+) -> impl IntoResponse {
+    let req = req.map(|Json(req)| req).unwrap_or_default();
+    let state = {
+        let state = state.read().await;
+        if let Err(e) = scratch::load_scratch_config(&state.config, &name)
+            .and_then(|_| scratch::ScratchLock::check(&state.config, &name))
+        {
+            return scratch_error(e);
+        }
+        state.clone()
+    };
+
+    let job = state
+        .jobs
+        .clone()
+        .spawn(JobKind::Update, &name.clone(), async move {
+            if req.rolling {
+                return scratch::rolling_update_scratch(&state.config, &state.docker, &name).await;
+            }
+            scratch::update_scratch(&state.config, &state.docker, &name).await?;
+            if req.restart {
+                scratch::restart_scratch(&state.config, &state.docker, &name).await?;
+            }
+            Ok(())
+        })
+        .await;
+
+    job_accepted(job)
+}
+
+pub async fn bulk_scratches(
+    State(state): State<SharedState>,
+    Json(req): Json<BulkRequest>,
+) -> impl IntoResponse {
+    let state = state.read().await.clone();
+
+    let names = match scratch::select_scratches(&state.config, &state.docker, &req.filter).await {
+        Ok(names) => names,
+        Err(e) => return scratch_error(e),
+    };
+    if req.dry_run {
+        let matched: Vec<_> = names
+            .into_iter()
+            .map(|name| scratch::BulkResult {
+                scratch: name,
+                outcome: scratch::BulkOutcome::Matched,
+                error: None,
+            })
+            .collect();
+        return (StatusCode::OK, Json(ApiResponse::ok(matched))).into_response();
+    }
+
+    let concurrency = req.concurrency.unwrap_or(scratch::DEFAULT_BULK_CONCURRENCY);
+    let job = state
+        .jobs
+        .clone()
+        .spawn(JobKind::Bulk, &names.join(","), async move {
+            let results = scratch::run_bulk(
+                &state.config,
+                &state.docker,
+                req.action,
+                &names,
+                concurrency,
+            )
+            .await;
+
+            let failed: Vec<&str> = results
+                .iter()
+                .filter(|r| r.outcome == scratch::BulkOutcome::Failed)
+                .map(|r| r.scratch.as_str())
+                .collect();
+            if failed.is_empty() {
+                return Ok(());
+            }
+            Err(Error::Other(format!(
+                "Bulk {} failed on {} of {} scratch(es): {}",
+                req.action,
+                failed.len(),
+                results.len(),
+                failed.join(", ")
+            )))
+        })
+        .await;
+
+    job_accepted(job)
+}
+
+pub async fn get_scratch_drift(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let state = state.read().await;
+
+    match scratch::scratch_drift(&state.config, &name) {
+        Ok(drift) => (StatusCode::OK, Json(ApiResponse::ok(drift))).into_response(),
+        Err(e) => error_response(e, StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+pub async fn extend_scratch(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+    Json(req): Json<ExtendScratchRequest>,
+) -> impl IntoResponse {
+    let state = state.read().await;
+
+    match scratch::extend_scratch(&state.config, &name, &req.duration) {
+        Ok(expires_at) => (StatusCode::OK, Json(ApiResponse::ok(expires_at))).into_response(),
+        Err(e) => scratch_error(e),
+    }
+}
+
+pub async fn list_snapshots(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
 ) -> impl IntoResponse {
     let state = state.read().await;
 
-    match scratch::restart_scratch(&state.config, &state.docker, &name).await {
+    match scratch::list_snapshots(&state.config, &name) {
+        Ok(snapshots) => (StatusCode::OK, Json(ApiResponse::ok(snapshots))).into_response(),
+        Err(e) => scratch_error(e),
+    }
+}
+
+pub async fn create_snapshot(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+    req: Option<Json<CreateSnapshotRequest>>,
+) -> impl IntoResponse {
+    let state = state.read().await.clone();
+    let req = req.map(|Json(req)| req).unwrap_or_default();
+
+    match scratch::create_snapshot(&state.config, &state.docker, &name, req.name).await {
+        Ok(snapshot) => (StatusCode::CREATED, Json(ApiResponse::ok(snapshot))).into_response(),
+        Err(e) => scratch_error(e),
+    }
+}
+
+pub async fn restore_snapshot(
+    State(state): State<SharedState>,
+    Path((name, snapshot)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let state = state.read().await.clone();
+
+    match scratch::restore_snapshot(&state.config, &state.docker, &name, &snapshot).await {
         Ok(()) => (
             StatusCode::OK,
-            Json(ApiResponse::ok("restarted".to_string())),
-        ),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ApiResponse {
-                success: false,
-                data: None,
-                error: Some(e.to_string()),
-            }),
-        ),
+            Json(ApiResponse::ok("restored".to_string())),
+        )
+            .into_response(),
+        Err(e) => scratch_error(e),
+    }
+}
+
+pub async fn delete_snapshot(
+    State(state): State<SharedState>,
+    Path((name, snapshot)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let state = state.read().await;
+
+    match scratch::delete_snapshot(&state.config, &name, &snapshot) {
+        Ok(()) => (StatusCode::OK, Json(ApiResponse::ok("deleted".to_string()))).into_response(),
+        Err(e) => scratch_error(e),
     }
 }
 
+// Job routes
+
+pub async fn list_jobs(State(state): State<SharedState>) -> impl IntoResponse {
+    let jobs = state.read().await.jobs.clone();
+    (StatusCode::OK, Json(ApiResponse::ok(jobs.list().await)))
+}
+
+pub async fn get_job(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let jobs = state.read().await.jobs.clone();
+
+    match jobs.get(&id).await {
+        Some(job) => (StatusCode::OK, Json(ApiResponse::ok(job))).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::<()>::err(format!("Job '{}' not found", id))),
+        )
+            .into_response(),
+    }
+}
+
+/// Respond to a request that was queued as a background job
+fn job_accepted(job: Job) -> Response {
+    (StatusCode::ACCEPTED, Json(ApiResponse::ok(job))).into_response()
+}
+
+/// Map a scratch operation error to a response
+///
+/// Missing scratches are 404, scratches locked by another operation 409 and
+/// exceeded quotas 429; anything else gets `fallback`.
+fn error_response(e: Error, fallback: StatusCode) -> Response {
+    let status = match e {
+        Error::ScratchNotFound(_) | Error::SnapshotNotFound(_) => StatusCode::NOT_FOUND,
+        Error::ScratchBusy(..) => StatusCode::CONFLICT,
+        Error::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+        _ => fallback,
+    };
+    (status, Json(ApiResponse::<()>::err(e.to_string()))).into_response()
+}
+
+/// Map a scratch lookup error to a response
+fn scratch_error(e: Error) -> Response {
+    error_response(e, StatusCode::BAD_REQUEST)
+}
+
+pub async fn update_env(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+    Json(req): Json<UpdateEnvRequest>,
+) -> impl IntoResponse {
+    let state = state.read().await.clone();
+
+    let result = match scratch::update_scratch_env(&state.config, &name, req.env) {
+        Ok(env) => scratch::update_scratch(&state.config, &state.docker, &name)
+            .await
+            .map(|()| env),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(env) => (StatusCode::OK, Json(ApiResponse::ok(env))).into_response(),
+        Err(e) => scratch_error(e),
+    }
+}
+
+pub async fn record_activity(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let state = state.read().await;
+    state.activity.touch(&name).await;
+    StatusCode::NO_CONTENT
+}
+
 pub async fn get_logs(
     State(state): State<SharedState>,
     Path(name): Path<String>,
@@ -323,6 +745,33 @@ pub async fn get_logs(
     (StatusCode::OK, Json(ApiResponse::ok(all_logs)))
 }
 
+/// What the scratch's lifecycle hooks printed, oldest first
+pub async fn get_hook_logs(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+    Query(query): Query<LogsQuery>,
+) -> impl IntoResponse {
+    let state = state.read().await;
+    let tail = query.tail.unwrap_or(100);
+
+    match scratch::read_hook_log(&state.config, &name, tail) {
+        Ok(lines) => (StatusCode::OK, Json(ApiResponse::ok(lines))).into_response(),
+        Err(e) => error_response(e, StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+pub async fn get_scratch_stats(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let state = state.read().await;
+
+    match scratch::get_scratch_stats(&state.config, &state.docker, &name).await {
+        Ok(stats) => (StatusCode::OK, Json(ApiResponse::ok(stats))).into_response(),
+        Err(e) => error_response(e, StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
 // Webhook handlers
 
 #[derive(Debug, Deserialize)]
@@ -331,6 +780,12 @@ pub struct GithubWebhookPayload {
     pub ref_name: Option<String>,
     pub action: Option<String>,
     pub pull_request: Option<GithubPullRequest>,
+    pub sender: Option<GithubUser>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GithubUser {
+    pub login: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -348,8 +803,6 @@ pub async fn github_webhook(
     State(state): State<SharedState>,
     Json(payload): Json<GithubWebhookPayload>,
 ) -> impl IntoResponse {
-    let state = state.read().await;
-
     // Extract branch name
     let branch = payload
         .ref_name
@@ -365,41 +818,80 @@ pub async fn github_webhook(
     let Some(branch) = branch else {
         return (
             StatusCode::BAD_REQUEST,
-            Json(ApiResponse {
-                success: false,
-                data: None,
-                error: Some("no branch found".to_string()),
-            }),
-        );
+            Json(ApiResponse::<()>::err("no branch found")),
+        )
+            .into_response();
     };
 
     tracing::info!("GitHub webhook triggered for branch: {}", branch);
 
-    // Create or update scratch
+    // Create the scratch in the background so GitHub gets a quick response
+    let owner = payload.sender.map(|sender| sender.login);
     let name = crate::scratch::Scratch::sanitize_name(&branch);
+    let state = {
+        let state = state.read().await;
 
-    match scratch::create_scratch(
-        &state.config,
-        &state.docker,
-        &branch,
-        Some(name),
-        None,
-        None,
-    )
-    .await
-    {
-        Ok(s) => (StatusCode::OK, Json(ApiResponse::ok(s.name))),
-        Err(e) => {
-            tracing::error!("Failed to create scratch from webhook: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ApiResponse {
-                    success: false,
-                    data: None,
-                    error: Some(e.to_string()),
-                }),
-            )
+        if let Err(e) = scratch::ScratchLock::check(&state.config, &name)
+            .and_then(|()| scratch::check_quotas(&state.config, owner.as_deref(), None))
+        {
+            return scratch_error(e);
         }
+        state.clone()
+    };
+
+    let job = state
+        .jobs
+        .clone()
+        .spawn(JobKind::Create, &name.clone(), async move {
+            scratch::create_scratch(
+                &state.config,
+                &state.docker,
+                &branch,
+                scratch::CreateScratchOptions {
+                    name: Some(name),
+                    owner,
+                    ..Default::default()
+                },
+            )
+            .await
+            .map(|_| ())
+        })
+        .await;
+
+    job_accepted(job)
+}
+
+// Admin routes
+
+/// Options for `POST /api/admin/gc`
+#[derive(Debug, Default, Deserialize)]
+pub struct GcQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Reject requests that don't carry an admin token
+fn require_admin(headers: &HeaderMap) -> Result<(), (StatusCode, &'static str)> {
+    match auth::extract_user_from_headers(headers) {
+        Ok(claims) if claims.get_role() == auth::UserRole::Admin => Ok(()),
+        Ok(_) => Err((StatusCode::FORBIDDEN, "Admin access required")),
+        Err(_) => Err((StatusCode::UNAUTHORIZED, "Authentication required")),
+    }
+}
+
+pub async fn garbage_collect(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Query(query): Query<GcQuery>,
+) -> impl IntoResponse {
+    if let Err((status, message)) = require_admin(&headers) {
+        return (status, Json(ApiResponse::<()>::err(message))).into_response();
+    }
+    let state = state.read().await.clone();
+
+    match scratch::collect_garbage(&state.config, &state.docker, query.dry_run).await {
+        Ok(report) => (StatusCode::OK, Json(ApiResponse::ok(report))).into_response(),
+        Err(e) => error_response(e, StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
 
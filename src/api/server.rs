@@ -1,7 +1,7 @@
 //! HTTP API server
 
 use axum::{
-    routing::{delete, get, post},
+    routing::{delete, get, patch, post},
     Router,
 };
 use std::sync::Arc;
@@ -12,14 +12,22 @@ use tower_http::trace::TraceLayer;
 use crate::config::Config;
 use crate::docker::DockerClient;
 use crate::error::Result;
+use crate::scratch::ActivityTracker;
 
-use super::{events, routes, websocket};
+use super::{events, jobs, reaper, routes, watcher, websocket};
 
 /// Application state shared across handlers
+///
+/// Jobs and other long-running work run on a clone rather than holding the
+/// lock, so they don't hold up config updates (and the requests queued
+/// behind them).
+#[derive(Clone)]
 pub struct AppState {
     pub config: Config,
     pub docker: DockerClient,
     pub ws_hub: Arc<websocket::WsBroadcastHub>,
+    pub activity: Arc<ActivityTracker>,
+    pub jobs: Arc<jobs::JobQueue>,
 }
 
 pub type SharedState = Arc<RwLock<AppState>>;
@@ -35,11 +43,19 @@ pub async fn run_server(config: Config, host: &str, port: u16) -> Result<()> {
         config,
         docker: (*docker_arc).clone(),
         ws_hub: ws_hub.clone(),
+        activity: Arc::new(ActivityTracker::new()),
+        jobs: Arc::new(jobs::JobQueue::new(ws_hub.clone())),
     }));
 
     // Start background event streaming tasks
     events::start_event_streaming(ws_hub, docker_arc);
 
+    // Start the expired and idle scratch reaper
+    reaper::start_reaper(state.clone());
+
+    // Start redeploying scratches whose image tags move, if enabled
+    watcher::start_image_watcher(state.clone());
+
     let app = create_router(state);
 
     let addr = format!("{}:{}", host, port);
@@ -65,23 +81,62 @@ fn create_router(state: SharedState) -> Router {
         .route("/api/config", post(routes::update_config))
         .route("/api/scratches", get(routes::list_scratches))
         .route("/api/scratches", post(routes::create_scratch))
-        .route("/api/scratches/:name", get(routes::get_scratch))
-        .route("/api/scratches/:name", delete(routes::delete_scratch))
-        .route("/api/scratches/:name/start", post(routes::start_scratch))
-        .route("/api/scratches/:name/stop", post(routes::stop_scratch))
+        .route("/api/scratches/bulk", post(routes::bulk_scratches))
+        .route("/api/scratches/{name}", get(routes::get_scratch))
+        .route("/api/scratches/{name}", delete(routes::delete_scratch))
+        .route(
+            "/api/scratches/{name}",
+            patch(routes::update_scratch_metadata),
+        )
+        .route("/api/scratches/{name}/start", post(routes::start_scratch))
+        .route("/api/scratches/{name}/stop", post(routes::stop_scratch))
         .route(
-            "/api/scratches/:name/restart",
+            "/api/scratches/{name}/restart",
             post(routes::restart_scratch),
         )
-        .route("/api/scratches/:name/logs", get(routes::get_logs))
+        .route("/api/scratches/{name}/update", post(routes::update_scratch))
+        .route("/api/scratches/{name}/diff", get(routes::get_scratch_drift))
+        .route("/api/scratches/{name}/extend", post(routes::extend_scratch))
+        .route("/api/scratches/{name}/clone", post(routes::clone_scratch))
+        .route("/api/scratches/{name}/env", patch(routes::update_env))
+        .route(
+            "/api/scratches/{name}/snapshots",
+            get(routes::list_snapshots).post(routes::create_snapshot),
+        )
+        .route(
+            "/api/scratches/{name}/snapshots/{snapshot}",
+            delete(routes::delete_snapshot),
+        )
+        .route(
+            "/api/scratches/{name}/snapshots/{snapshot}/restore",
+            post(routes::restore_snapshot),
+        )
+        .route(
+            "/api/scratches/{name}/activity",
+            post(routes::record_activity),
+        )
+        .route("/api/scratches/{name}/logs", get(routes::get_logs))
+        .route(
+            "/api/scratches/{name}/hooks/logs",
+            get(routes::get_hook_logs),
+        )
+        .route(
+            "/api/scratches/{name}/stats",
+            get(routes::get_scratch_stats),
+        )
+        // Job routes
+        .route("/api/jobs", get(routes::list_jobs))
+        .route("/api/jobs/{id}", get(routes::get_job))
         // Webhook routes
         .route("/api/webhooks/github", post(routes::github_webhook))
+        // Admin routes
+        .route("/api/admin/gc", post(routes::garbage_collect))
         // Service routes
         .route("/api/services", get(routes::list_services))
         .route("/api/services/start", post(routes::start_services))
         .route("/api/services/stop", post(routes::stop_services))
-        .route("/api/services/:service/start", post(routes::start_service))
-        .route("/api/services/:service/stop", post(routes::stop_service))
+        .route("/api/services/{service}/start", post(routes::start_service))
+        .route("/api/services/{service}/stop", post(routes::stop_service))
         // WebSocket route
         .route("/ws", get(websocket::ws_handler))
         // UI routes
@@ -90,7 +145,8 @@ fn create_router(state: SharedState) -> Router {
         .route("/config", get(crate::ui::config_editor))
         .route("/services", get(crate::ui::service_manager))
         .route("/scratches/create", get(crate::ui::create_scratch))
-        .route("/scratches/:name", get(crate::ui::scratch_detail))
+        .route("/scratches/{name}", get(crate::ui::scratch_detail))
+        .route("/wake/{name}", get(crate::ui::wake_scratch))
         // Middleware
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive())
@@ -0,0 +1,146 @@
+//! Background image watcher
+//!
+//! When `watch.enabled` is set, periodically checks the digests of the image
+//! tags running scratches use, and redeploys the services whose tag has been
+//! pushed again. Each redeploy runs as a job, is announced on the scratch's
+//! status channel and is recorded in the scratch's redeploy log.
+
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info};
+
+use crate::config::DigestSource;
+use crate::scratch::{
+    self, DaemonDigestResolver, DigestResolver, ImageUpdate, ImageWatcher, RegistryDigestResolver,
+};
+
+use super::jobs::JobKind;
+use super::server::{AppState, SharedState};
+use super::websocket::{status_channel, ServerMessage};
+
+/// Start the background task that redeploys scratches when their images change
+pub fn start_image_watcher(state: SharedState) {
+    tokio::spawn(async move {
+        if !state.read().await.config.watch.enabled {
+            return;
+        }
+        info!("Image watcher started");
+
+        let mut watcher = ImageWatcher::new();
+        let (redeployed_tx, mut redeployed_rx) = mpsc::unbounded_channel();
+        loop {
+            let interval = {
+                let app_state = state.read().await;
+                watch_interval(&app_state.config.watch.interval)
+            };
+            tokio::time::sleep(interval).await;
+
+            // Failed redeploys are tried again, successful ones aren't
+            while let Ok(update) = redeployed_rx.try_recv() {
+                watcher.mark_redeployed(&update);
+            }
+
+            // Work on a copy so config updates don't wait for the watcher
+            let app_state = state.read().await.clone();
+            let updates = match app_state.config.watch.source {
+                DigestSource::Docker => {
+                    let resolver = DaemonDigestResolver::new(&app_state.docker);
+                    check(&mut watcher, &app_state, &resolver).await
+                }
+                DigestSource::Registry => {
+                    let resolver = RegistryDigestResolver::new(&app_state.config.watch);
+                    check(&mut watcher, &app_state, &resolver).await
+                }
+            };
+            for update in updates {
+                redeploy(&app_state, update, redeployed_tx.clone()).await;
+            }
+        }
+    });
+}
+
+/// Run one pass of the watcher, logging failures
+async fn check(
+    watcher: &mut ImageWatcher,
+    app_state: &AppState,
+    resolver: &impl DigestResolver,
+) -> Vec<ImageUpdate> {
+    watcher
+        .check(&app_state.config, &app_state.docker, resolver)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Image watcher error: {}", e);
+            Vec::new()
+        })
+}
+
+/// Queue the redeploy of a scratch's updated services and announce it
+///
+/// The redeploy is recorded in the scratch's redeploy log when it is queued
+/// and when it finishes, and sent back on `redeployed` if it succeeds.
+async fn redeploy(
+    app_state: &AppState,
+    update: ImageUpdate,
+    redeployed: mpsc::UnboundedSender<ImageUpdate>,
+) {
+    let job_state = app_state.clone();
+    let job_update = update.clone();
+    let (job_id_tx, job_id_rx) = oneshot::channel::<String>();
+    let job = app_state
+        .jobs
+        .spawn(JobKind::Redeploy, &update.scratch, async move {
+            let result = scratch::redeploy_services(
+                &job_state.config,
+                &job_state.docker,
+                &job_update.scratch,
+                &job_update.services,
+            )
+            .await;
+
+            let job_id = job_id_rx.await.unwrap_or_default();
+            let outcome = if result.is_ok() {
+                "succeeded"
+            } else {
+                "failed"
+            };
+            if let Err(e) =
+                scratch::record_redeploy(&job_state.config, &job_update, &job_id, outcome)
+            {
+                error!("Failed to record redeploy of {}: {}", job_update.scratch, e);
+            }
+            if result.is_ok() {
+                let _ = redeployed.send(job_update);
+            }
+            result
+        })
+        .await;
+    let _ = job_id_tx.send(job.id.clone());
+
+    if let Err(e) = scratch::record_redeploy(&app_state.config, &update, &job.id, "queued") {
+        error!("Failed to record redeploy of {}: {}", update.scratch, e);
+    }
+
+    let msg = ServerMessage::ImageUpdated {
+        scratch: update.scratch.clone(),
+        services: update.services,
+        image: update.image,
+        digest: update.digest,
+        job_id: job.id,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    };
+    app_state
+        .ws_hub
+        .broadcast(&status_channel(&update.scratch), msg)
+        .await;
+}
+
+/// Parse the configured watch interval, falling back to two minutes
+fn watch_interval(value: &str) -> std::time::Duration {
+    scratch::parse_duration(value)
+        .ok()
+        .and_then(|d| d.to_std().ok())
+        .filter(|d| !d.is_zero())
+        .unwrap_or_else(|| {
+            error!("Invalid watch.interval '{}', using 2m", value);
+            std::time::Duration::from_secs(120)
+        })
+}
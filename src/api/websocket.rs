@@ -4,6 +4,8 @@
 //! - Container logs (live streaming)
 //! - Status changes (container start/stop/restart)
 //! - Docker events (container lifecycle events)
+//! - Job progress (background scratch operations)
+//! - Resource usage (CPU, memory, network and block IO)
 
 use axum::{
     extract::{ws::*, State},
@@ -15,7 +17,9 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use super::jobs::JobStatus;
 use super::server::SharedState;
+use crate::scratch::ScratchStats;
 
 // ============================================================================
 // Message Types
@@ -51,12 +55,34 @@ pub enum ServerMessage {
         service: Option<String>,
         timestamp: String,
     },
+    /// An image tag a scratch runs points to a new digest and its services
+    /// are being redeployed
+    ImageUpdated {
+        scratch: String,
+        services: Vec<String>,
+        image: String,
+        digest: String,
+        job_id: String,
+        timestamp: String,
+    },
     /// Docker event (start, stop, die, etc.)
     ContainerEvent {
         scratch: String,
         service: String,
         action: String,
     },
+    /// Progress or status change of a background job
+    JobUpdate {
+        job_id: String,
+        scratch: String,
+        status: JobStatus,
+        step: Option<String>,
+        message: Option<String>,
+        error: Option<String>,
+        timestamp: String,
+    },
+    /// Resource usage of a scratch
+    Stats { stats: ScratchStats },
     /// Error message
     Error { message: String },
     /// Response to Ping
@@ -178,7 +204,7 @@ async fn handle_socket(socket: WebSocket, state: SharedState) {
             while let Some(msg) = rx.recv().await {
                 if let Ok(json) = serde_json::to_string(&msg) {
                     let mut s = sender.lock().await;
-                    if s.send(Message::Text(json)).await.is_err() {
+                    if s.send(Message::Text(json.into())).await.is_err() {
                         break;
                     }
                 }
@@ -266,6 +292,16 @@ pub fn events_channel() -> String {
     "events".to_string()
 }
 
+/// Build a job progress channel name
+pub fn job_channel(id: &str) -> String {
+    format!("jobs:{}", id)
+}
+
+/// Build a resource usage channel name
+pub fn stats_channel(scratch: &str) -> String {
+    format!("stats:{}", scratch)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -277,6 +313,8 @@ mod tests {
         assert_eq!(status_channel("test"), "status:test");
         assert_eq!(status_channel_all(), "status:*");
         assert_eq!(events_channel(), "events");
+        assert_eq!(job_channel("abc"), "jobs:abc");
+        assert_eq!(stats_channel("test"), "stats:test");
     }
 
     #[tokio::test]
@@ -297,7 +335,7 @@ mod tests {
         // Now drop tx
         drop(tx);
 
-        let channels = hub.get_channels().await;
+        let _channels = hub.get_channels().await;
         // After unsubscribe removes closed senders and the channel is empty,
         // the channel itself should be removed (but tx is still alive here, so it won't be removed yet)
         // Let's actually test that unsubscribe works by dropping the tx before checking
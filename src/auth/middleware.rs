@@ -2,12 +2,17 @@
 
 use crate::auth::{validate_token, Claims};
 use crate::error::{Error, Result};
-use axum::{extract::Request, middleware::Next, response::Response};
+use axum::{extract::Request, http::HeaderMap, middleware::Next, response::Response};
 
 /// Extract user claims from request
 pub fn extract_user_from_request(req: &Request) -> Result<Claims> {
+    extract_user_from_headers(req.headers())
+}
+
+/// Extract user claims from request headers
+pub fn extract_user_from_headers(headers: &HeaderMap) -> Result<Claims> {
     // Try to get token from Authorization header
-    if let Some(auth_header) = req.headers().get("Authorization") {
+    if let Some(auth_header) = headers.get("Authorization") {
         if let Ok(auth_str) = auth_header.to_str() {
             if let Some(token) = auth_str.strip_prefix("Bearer ") {
                 return validate_token(token);
@@ -16,7 +21,7 @@ pub fn extract_user_from_request(req: &Request) -> Result<Claims> {
     }
 
     // Try to get token from cookie
-    if let Some(cookie_header) = req.headers().get("Cookie") {
+    if let Some(cookie_header) = headers.get("Cookie") {
         if let Ok(cookie_str) = cookie_header.to_str() {
             for cookie in cookie_str.split(';') {
                 if let Some(token) = cookie.trim().strip_prefix("scratchpad_token=") {
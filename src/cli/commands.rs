@@ -3,10 +3,14 @@
 use anyhow::Result;
 use colored::Colorize;
 use std::fs;
+use std::path::PathBuf;
 
+use crate::api::jobs::Job;
 use crate::cli::{
-    confirm, error, info, print_scratch_detail, print_scratch_table, success, warn, ConfigAction,
-    NginxAction, OutputFormat, ServicesAction,
+    confirm, error, format_status, info, print_bulk_results, print_orphans_table,
+    print_scratch_detail, print_scratch_drift, print_scratch_stats, print_scratch_table,
+    print_stats_table, success, warn, ConfigAction, DbAction, DbTemplateAction, JobsAction,
+    NginxAction, OutputFormat, ServicesAction, SnapshotAction, TemplateAction,
 };
 use crate::config::{self, Config};
 use crate::docker::DockerClient;
@@ -33,18 +37,30 @@ pub async fn init() -> Result<()> {
 }
 
 /// Create a new scratch environment
+///
+/// The env overrides are read from `env` and `env_file`, and the owner
+/// defaults to the git author (or the current user).
 pub async fn create(
-    branch: &str,
-    name: Option<String>,
-    profile: Option<String>,
-    template: Option<String>,
+    branch: &[String],
+    env: Vec<String>,
+    env_file: Option<PathBuf>,
+    mut options: scratch::CreateScratchOptions,
 ) -> Result<()> {
     let config = load_config()?;
     let docker = get_docker_client(&config).await?;
+    let (branch, branches) = scratch::Scratch::parse_branch_args(branch)?;
+    options.branches = branches;
+    options.env = scratch::load_env_overrides(&env, env_file.as_deref())?;
+    if options.owner.is_none() {
+        options.owner = local_user();
+    }
 
     info(&format!("Creating scratch from branch: {}", branch));
+    for (repo, repo_branch) in &options.branches {
+        info(&format!("  {}: {}", repo, repo_branch));
+    }
 
-    match scratch::create_scratch(&config, &docker, branch, name, profile, template).await {
+    match scratch::create_scratch(&config, &docker, &branch, options).await {
         Ok(scratch_instance) => {
             success(&format!("Created scratch: {}", scratch_instance.name));
             if config.nginx.enabled {
@@ -53,6 +69,12 @@ pub async fn create(
                     scratch_instance.name, config.nginx.domain
                 ));
             }
+            if let Some(expires_at) = scratch_instance.expires_at {
+                info(&format!(
+                    "Expires at: {}",
+                    expires_at.format("%Y-%m-%d %H:%M UTC")
+                ));
+            }
             Ok(())
         }
         Err(e) => {
@@ -62,12 +84,45 @@ pub async fn create(
     }
 }
 
+/// Clone an existing scratch environment
+pub async fn clone(source: &str, name: &str, branch: Option<String>) -> Result<()> {
+    let config = load_config()?;
+    let docker = get_docker_client(&config).await?;
+
+    info(&format!("Cloning scratch '{}' to '{}'", source, name));
+    warn(&format!(
+        "Connections to the database of '{}' will be closed while it is copied",
+        source
+    ));
+
+    match scratch::clone_scratch(&config, &docker, source, name, branch).await {
+        Ok(scratch_instance) => {
+            success(&format!(
+                "Created scratch: {} (branch: {})",
+                scratch_instance.name, scratch_instance.branch
+            ));
+            if config.nginx.enabled {
+                info(&format!(
+                    "Access at: http://{}.{}",
+                    scratch_instance.name, config.nginx.domain
+                ));
+            }
+            Ok(())
+        }
+        Err(e) => {
+            error(&format!("Failed to clone scratch: {}", e));
+            Err(e.into())
+        }
+    }
+}
+
 /// List all scratch environments
-pub async fn list(format: OutputFormat) -> Result<()> {
+pub async fn list(format: OutputFormat, filter: scratch::ScratchFilter) -> Result<()> {
     let config = load_config()?;
     let docker = get_docker_client(&config).await?;
 
-    let scratches = scratch::list_scratches(&config, &docker).await?;
+    let mut scratches = scratch::list_scratches(&config, &docker).await?;
+    scratches.retain(|s| filter.matches(s));
 
     match format {
         OutputFormat::Table => {
@@ -86,6 +141,29 @@ pub async fn list(format: OutputFormat) -> Result<()> {
     Ok(())
 }
 
+/// Show compose drift of one scratch, or of all of them
+pub async fn diff(name: Option<&str>, all: bool) -> Result<()> {
+    let config = load_config()?;
+
+    let drifts = match name {
+        Some(name) if !all => vec![scratch::scratch_drift(&config, name)?],
+        _ => scratch::all_scratch_drift(&config)?,
+    };
+    if drifts.is_empty() {
+        info("No scratches found");
+        return Ok(());
+    }
+
+    for drift in &drifts {
+        print_scratch_drift(drift);
+    }
+    let outdated = drifts.iter().filter(|d| d.is_outdated()).count();
+    if outdated > 0 {
+        info("Run 'scratchpad update <name>' to apply the current config");
+    }
+    Ok(())
+}
+
 /// Start a scratch environment
 pub async fn start(name: &str) -> Result<()> {
     let config = load_config()?;
@@ -173,6 +251,103 @@ pub async fn delete(name: &str, force: bool) -> Result<()> {
     }
 }
 
+/// Run an action on every scratch matching a selector
+pub async fn bulk(
+    action: scratch::BulkAction,
+    selector: scratch::BulkSelector,
+    concurrency: usize,
+    dry_run: bool,
+    force: bool,
+) -> Result<()> {
+    let config = load_config()?;
+    let docker = get_docker_client(&config).await?;
+
+    let names = scratch::select_scratches(&config, &docker, &selector).await?;
+    if names.is_empty() {
+        info("No scratches match");
+        return Ok(());
+    }
+
+    if dry_run {
+        let matched: Vec<_> = names
+            .iter()
+            .map(|name| scratch::BulkResult {
+                scratch: name.clone(),
+                outcome: scratch::BulkOutcome::Matched,
+                error: None,
+            })
+            .collect();
+        print_bulk_results(&matched);
+        info(&format!(
+            "Dry run: {} would run on {} scratch(es)",
+            action,
+            names.len()
+        ));
+        return Ok(());
+    }
+
+    if !force {
+        let mut message = format!(
+            "Run {} on {} scratch(es) ({})?",
+            action,
+            names.len(),
+            names.join(", ")
+        );
+        if action == scratch::BulkAction::Delete {
+            message.push_str(" This will also delete their databases.");
+        }
+        if !confirm(&message)? {
+            info("Cancelled");
+            return Ok(());
+        }
+    }
+
+    info(&format!(
+        "Running {} on {} scratch(es), {} at a time",
+        action,
+        names.len(),
+        concurrency.max(1)
+    ));
+    let results = scratch::run_bulk(&config, &docker, action, &names, concurrency).await;
+    print_bulk_results(&results);
+
+    let failed = results
+        .iter()
+        .filter(|r| r.outcome == scratch::BulkOutcome::Failed)
+        .count();
+    if failed == 0 {
+        success(&format!("Ran {} on {} scratch(es)", action, results.len()));
+        Ok(())
+    } else {
+        anyhow::bail!(
+            "Bulk {} failed on {} of {} scratch(es)",
+            action,
+            failed,
+            results.len()
+        )
+    }
+}
+
+/// Extend the expiry of a scratch environment
+pub async fn extend(name: &str, duration: &str) -> Result<()> {
+    let config = load_config()?;
+
+    match scratch::extend_scratch(&config, name, duration) {
+        Ok(expires_at) => {
+            success(&format!(
+                "Scratch '{}' now expires at {}",
+                name,
+                expires_at.format("%Y-%m-%d %H:%M UTC")
+            ));
+            Ok(())
+        }
+        Err(e) => {
+            error(&format!("Failed to extend scratch: {}", e));
+            Err(e.into())
+        }
+    }
+}
+
 /// View logs from a scratch
 pub async fn logs(name: &str, service: Option<String>, follow: bool, tail: usize) -> Result<()> {
     let config = load_config()?;
@@ -261,6 +436,91 @@ pub async fn status(name: &str) -> Result<()> {
     }
 }
 
+/// Show resource usage of one or all scratches
+pub async fn stats(name: Option<String>, watch: bool) -> Result<()> {
+    let config = load_config()?;
+    let docker = get_docker_client(&config).await?;
+
+    loop {
+        // Sample before clearing so the screen isn't blank while waiting
+        let sample = match &name {
+            Some(name) => scratch::get_scratch_stats(&config, &docker, name)
+                .await
+                .map(|stats| vec![stats]),
+            None => scratch::list_scratch_stats(&docker).await,
+        };
+        let sample = match sample {
+            Ok(sample) => sample,
+            Err(e) => {
+                error(&format!("Failed to get stats: {}", e));
+                return Err(e.into());
+            }
+        };
+
+        if watch {
+            // Clear the screen and move the cursor home
+            print!("\x1B[2J\x1B[H");
+            println!(
+                "{}",
+                format!("Every 2s: {}", chrono::Local::now().format("%H:%M:%S")).dimmed()
+            );
+        }
+        match (&name, sample.first()) {
+            (Some(_), Some(stats)) => print_scratch_stats(stats),
+            _ => print_stats_table(&sample),
+        }
+
+        if !watch {
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    }
+}
+
+/// Find and remove resources left behind by deleted scratches
+pub async fn gc(dry_run: bool, force: bool) -> Result<()> {
+    let config = load_config()?;
+    let docker = get_docker_client(&config).await?;
+
+    let report = scratch::find_orphans(&config, &docker).await?;
+    for message in &report.errors {
+        warn(message);
+    }
+    if report.orphans.is_empty() {
+        success("Nothing to clean up");
+        return Ok(());
+    }
+
+    print_orphans_table(&report.orphans);
+    if dry_run {
+        info(&format!(
+            "Dry run: {} orphan(s) would be removed",
+            report.orphans.len()
+        ));
+        return Ok(());
+    }
+
+    if !force {
+        let message = format!("Remove these {} orphan(s)?", report.orphans.len());
+        if !confirm(&message)? {
+            info("Cancelled");
+            return Ok(());
+        }
+    }
+
+    let errors = scratch::remove_orphans(&config, &docker, &report.orphans).await;
+    for message in &errors {
+        error(message);
+    }
+    let removed = report.orphans.len() - errors.len();
+    if errors.is_empty() {
+        success(&format!("Removed {} orphan(s)", removed));
+        Ok(())
+    } else {
+        anyhow::bail!("Removed {} orphan(s), {} failed", removed, errors.len())
+    }
+}
+
 /// Start the HTTP API server
 pub async fn serve(host: &str, port: u16) -> Result<()> {
     let config = load_config()?;
@@ -271,6 +531,215 @@ pub async fn serve(host: &str, port: u16) -> Result<()> {
     Ok(())
 }
 
+/// Snapshot management commands
+pub async fn snapshot(action: SnapshotAction) -> Result<()> {
+    let config = load_config()?;
+
+    match action {
+        SnapshotAction::Create { scratch, name } => {
+            let docker = get_docker_client(&config).await?;
+            info(&format!("Snapshotting scratch: {}", scratch));
+            let snapshot = scratch::create_snapshot(&config, &docker, &scratch, name).await?;
+            success(&format!("Created snapshot: {}", snapshot.name));
+        }
+        SnapshotAction::List { scratch } => {
+            let snapshots = scratch::list_snapshots(&config, &scratch)?;
+            if snapshots.is_empty() {
+                info(&format!("No snapshots found for scratch: {}", scratch));
+            } else {
+                println!("Snapshots of {}:", scratch.bold());
+                for snapshot in snapshots {
+                    println!(
+                        "  {} {} ({} database(s))",
+                        snapshot.name.cyan(),
+                        snapshot.created_at.format("%Y-%m-%d %H:%M UTC"),
+                        snapshot.databases.len()
+                    );
+                }
+            }
+        }
+        SnapshotAction::Restore {
+            scratch,
+            snapshot,
+            force,
+        } => {
+            if !force {
+                let message = format!(
+                    "Restore scratch '{}' to snapshot '{}'? Current data will be lost.",
+                    scratch, snapshot
+                );
+                if !confirm(&message)? {
+                    info("Cancelled");
+                    return Ok(());
+                }
+            }
+
+            let docker = get_docker_client(&config).await?;
+            info(&format!(
+                "Restoring scratch '{}' to '{}'",
+                scratch, snapshot
+            ));
+            scratch::restore_snapshot(&config, &docker, &scratch, &snapshot).await?;
+            success(&format!("Restored scratch: {}", scratch));
+        }
+        SnapshotAction::Delete { scratch, snapshot } => {
+            scratch::delete_snapshot(&config, &scratch, &snapshot)?;
+            success(&format!("Deleted snapshot: {}", snapshot));
+        }
+    }
+
+    Ok(())
+}
+
+/// Background job commands, run against a `scratchpad serve` instance
+pub async fn jobs(server: Option<String>, action: JobsAction) -> Result<()> {
+    let server = match server {
+        Some(server) => server.trim_end_matches('/').to_string(),
+        None => {
+            let config = load_config()?;
+            let host = match config.server.host.as_str() {
+                "0.0.0.0" | "::" => "127.0.0.1",
+                host => host,
+            };
+            format!("http://{}:{}", host, config.server.port)
+        }
+    };
+    let client = reqwest::Client::new();
+
+    match action {
+        JobsAction::List => {
+            let jobs: Vec<Job> = fetch_api(&client, &format!("{}/api/jobs", server)).await?;
+            if jobs.is_empty() {
+                info("No jobs found");
+            }
+            for job in jobs {
+                println!(
+                    "  {} {:<8} {:<24} {} {}",
+                    job.id.cyan(),
+                    job.kind.as_str(),
+                    job.scratch,
+                    job.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                    format_status(job.status.as_str())
+                );
+            }
+        }
+        JobsAction::Show { id, wait } => {
+            let url = format!("{}/api/jobs/{}", server, id);
+            let mut job: Job = fetch_api(&client, &url).await?;
+            println!(
+                "Job {} ({} {})",
+                job.id.bold(),
+                job.kind.as_str(),
+                job.scratch
+            );
+
+            let mut shown = 0;
+            loop {
+                for progress in &job.progress[shown..] {
+                    println!(
+                        "  {} {:<9} {}",
+                        progress.timestamp.format("%H:%M:%S"),
+                        progress.step.cyan(),
+                        progress.message
+                    );
+                }
+                shown = job.progress.len();
+
+                if !wait || job.status.is_finished() {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                job = fetch_api(&client, &url).await?;
+            }
+
+            let status = job.status.as_str();
+            match job.error {
+                Some(e) => {
+                    error(&format!("Job {}: {}", status, e));
+                    if wait {
+                        anyhow::bail!("Job {} failed", job.id);
+                    }
+                }
+                None => println!("Status: {}", format_status(status)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetch the data of a scratchpad API response
+async fn fetch_api<T: serde::de::DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<T> {
+    #[derive(serde::Deserialize)]
+    struct Response<T> {
+        data: Option<T>,
+        error: Option<String>,
+    }
+
+    let response: Response<T> = client.get(url).send().await?.json().await?;
+    match (response.data, response.error) {
+        (Some(data), _) => Ok(data),
+        (None, error) => anyhow::bail!(error.unwrap_or_else(|| format!("No data from {}", url))),
+    }
+}
+
+/// Template inspection commands
+pub async fn template(action: TemplateAction) -> Result<()> {
+    let config = load_config()?;
+    let registry = scratch::TemplateRegistry::new(&config);
+
+    match action {
+        TemplateAction::List => {
+            println!("Templates:");
+            for name in registry.list()? {
+                if name == scratch::DEFAULT_TEMPLATE_NAME {
+                    println!("  {} {}", name.cyan(), "(built-in)".dimmed());
+                } else {
+                    println!("  {}", name.cyan());
+                }
+            }
+        }
+        TemplateAction::Show { name } => {
+            println!("{}", registry.source(&name)?);
+        }
+        TemplateAction::Render { name, scratch } => {
+            let scratch = scratch::load_scratch(&config, &scratch)?;
+            println!("{}", registry.render(&name, &config, &scratch)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Seed database commands
+pub async fn db(action: DbAction) -> Result<()> {
+    let config = load_config()?;
+
+    match action {
+        DbAction::Template {
+            action: DbTemplateAction::Refresh { profile },
+        } => {
+            if let Some(profile) = profile.as_deref() {
+                if config.get_profile(profile).is_none() {
+                    return Err(anyhow::anyhow!("Profile '{}' not found", profile));
+                }
+            }
+            let docker = get_docker_client(&config).await?;
+            services::ensure_shared_service_running(&config, &docker, "postgres").await?;
+
+            info("Loading seed dump into template database...");
+            let template =
+                services::refresh_template_database(&config, &docker, profile.as_deref()).await?;
+            success(&format!("Rebuilt template database: {}", template));
+        }
+    }
+
+    Ok(())
+}
+
 /// Nginx management commands
 pub async fn nginx(action: NginxAction) -> Result<()> {
     let config = load_config()?;
@@ -400,10 +869,36 @@ pub async fn services(action: ServicesAction) -> Result<()> {
 }
 
 /// Update an existing scratch environment
-pub async fn update(name: &str, restart: bool) -> Result<()> {
+pub async fn update(
+    name: &str,
+    restart: bool,
+    rolling: bool,
+    env: Vec<String>,
+    env_file: Option<PathBuf>,
+) -> Result<()> {
     let config = load_config()?;
     let docker = get_docker_client(&config).await?;
 
+    let env = scratch::load_env_overrides(&env, env_file.as_deref())?;
+    if !env.is_empty() {
+        let changes = env.into_iter().map(|(k, v)| (k, Some(v))).collect();
+        scratch::update_scratch_env(&config, name, changes)?;
+    }
+
+    if rolling {
+        info(&format!("Rolling update of scratch: {}", name));
+        return match scratch::rolling_update_scratch(&config, &docker, name).await {
+            Ok(()) => {
+                success(&format!("Updated scratch: {}", name));
+                Ok(())
+            }
+            Err(e) => {
+                error(&format!("Failed to update scratch: {}", e));
+                Err(e.into())
+            }
+        };
+    }
+
     info(&format!("Updating scratch: {}", name));
 
     match scratch::update_scratch(&config, &docker, name).await {
@@ -477,8 +972,17 @@ pub async fn config(action: ConfigAction) -> Result<()> {
                     // Validate services have required fields
                     let mut warnings = Vec::new();
                     for (name, svc) in &cfg.services {
-                        if svc.image.is_empty() {
-                            warnings.push(format!("Service '{}' has no image specified", name));
+                        if svc.image.is_empty() && svc.build.is_none() {
+                            warnings.push(format!(
+                                "Service '{}' has no image or build specified",
+                                name
+                            ));
+                        }
+                        if svc.shared && svc.build.is_some() {
+                            warnings.push(format!(
+                                "Shared service '{}' has a build section, which only per-scratch services use",
+                                name
+                            ));
                         }
                         if !svc.shared && svc.port.is_none() {
                             warnings.push(format!(
@@ -488,6 +992,17 @@ pub async fn config(action: ConfigAction) -> Result<()> {
                         }
                     }
 
+                    let profiles = cfg.scratch.profiles.values().map(|profile| &profile.hooks);
+                    for hooks in std::iter::once(&cfg.hooks).chain(profiles) {
+                        for point in scratch::HookPoint::ALL {
+                            for hook in point.hooks(hooks) {
+                                if let Err(e) = scratch::hook_action(&cfg, hook) {
+                                    warnings.push(format!("Invalid {} hook: {}", point, e));
+                                }
+                            }
+                        }
+                    }
+
                     if !warnings.is_empty() {
                         println!();
                         warn("Warnings:");
@@ -650,7 +1165,25 @@ pub async fn doctor() -> Result<()> {
                 }
             }
 
-            // 4. Check nginx (if enabled)
+            // 4. Check quotas
+            println!();
+            println!("{}  Checking quotas...", "→".blue());
+            match scratch::quota_report(&config) {
+                Ok(items) if items.is_empty() => info("No quotas configured"),
+                Ok(items) => {
+                    for item in items {
+                        let line = format!("{}: {}", item.name, item.display());
+                        if item.is_full() {
+                            warn(&format!("{} (limit reached)", line));
+                        } else {
+                            success(&line);
+                        }
+                    }
+                }
+                Err(e) => error(&format!("Failed to read quota usage: {}", e)),
+            }
+
+            // 5. Check nginx (if enabled)
             if config.nginx.enabled {
                 println!();
                 println!("{}  Checking nginx...", "→".blue());
@@ -674,3 +1207,15 @@ pub async fn doctor() -> Result<()> {
     println!();
     Ok(())
 }
+
+/// Name of the person running the CLI: the git author, else `$USER`
+fn local_user() -> Option<String> {
+    std::process::Command::new("git")
+        .args(["config", "user.name"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| std::env::var("USER").ok().filter(|user| !user.is_empty()))
+}
@@ -7,6 +7,7 @@ pub mod setup;
 pub use output::*;
 
 use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "scratchpad")]
@@ -32,9 +33,10 @@ pub enum Commands {
 
     /// Create a new scratch environment from a branch
     Create {
-        /// The branch name to create the scratch from
-        #[arg(short, long)]
-        branch: String,
+        /// The branch to create the scratch from, or REPO=BRANCH for the
+        /// services of one repository (repeatable)
+        #[arg(short, long = "branch", value_name = "[REPO=]BRANCH", required = true)]
+        branch: Vec<String>,
 
         /// Optional custom name for the scratch (defaults to sanitized branch name)
         #[arg(short, long)]
@@ -47,6 +49,45 @@ pub enum Commands {
         /// Override the template to use
         #[arg(short, long)]
         template: Option<String>,
+
+        /// Delete the scratch automatically after this long (e.g. "12h", "7d", "never")
+        #[arg(long)]
+        ttl: Option<String>,
+
+        /// Set an environment variable for the scratch's services (KEY=VALUE, repeatable)
+        #[arg(short, long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+
+        /// Read environment variables from a dotenv file
+        #[arg(long)]
+        env_file: Option<PathBuf>,
+
+        /// What the scratch is for
+        #[arg(short, long)]
+        description: Option<String>,
+
+        /// Tag the scratch (repeatable or comma separated)
+        #[arg(long = "tag", value_delimiter = ',')]
+        tags: Vec<String>,
+
+        /// Don't wait for the scratch's services to become healthy
+        #[arg(long)]
+        no_wait: bool,
+    },
+
+    /// Create a new scratch as a copy of an existing one, including its database
+    ///
+    /// Open connections to the source's database are closed while it is copied.
+    Clone {
+        /// Name of the scratch to copy
+        source: String,
+
+        /// Name for the new scratch
+        name: String,
+
+        /// Branch for the new scratch (defaults to the source's branch)
+        #[arg(short, long)]
+        branch: Option<String>,
     },
 
     /// Update an existing scratch environment (regenerate compose from current config)
@@ -57,6 +98,19 @@ pub enum Commands {
         /// Restart services after updating
         #[arg(short, long)]
         restart: bool,
+
+        /// Restart without downtime: start the new ingress container and switch
+        /// traffic to it once it's healthy (implies --restart)
+        #[arg(long)]
+        rolling: bool,
+
+        /// Set an environment variable for the scratch's services (KEY=VALUE, repeatable)
+        #[arg(short, long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+
+        /// Read environment variables from a dotenv file
+        #[arg(long)]
+        env_file: Option<PathBuf>,
     },
 
     /// List all scratch environments
@@ -64,6 +118,33 @@ pub enum Commands {
         /// Output format
         #[arg(short, long, default_value = "table")]
         format: OutputFormat,
+
+        /// Only show scratches owned by this user
+        #[arg(long)]
+        owner: Option<String>,
+
+        /// Only show scratches with this tag (repeatable or comma separated)
+        #[arg(long = "tag", value_delimiter = ',')]
+        tags: Vec<String>,
+
+        /// Only show scratches with this status (e.g. running, stopped, failed)
+        #[arg(long)]
+        status: Option<String>,
+
+        /// Only show scratches of this branch
+        #[arg(long)]
+        branch: Option<String>,
+    },
+
+    /// Show how a scratch's compose file differs from what the current config renders
+    Diff {
+        /// Name of the scratch
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        name: Option<String>,
+
+        /// Check every scratch
+        #[arg(short, long)]
+        all: bool,
     },
 
     /// Start a stopped scratch environment
@@ -94,6 +175,56 @@ pub enum Commands {
         force: bool,
     },
 
+    /// Delete, stop, start, restart or update every scratch matching a pattern or filter
+    Bulk {
+        /// What to do to each matching scratch
+        action: crate::scratch::BulkAction,
+
+        /// Glob matched against scratch names (e.g. "feature-*")
+        pattern: Option<String>,
+
+        /// Only scratches with this status (e.g. running, stopped, failed)
+        #[arg(long)]
+        status: Option<String>,
+
+        /// Only scratches created at least this long ago (e.g. "14d")
+        #[arg(long)]
+        older_than: Option<String>,
+
+        /// Only scratches whose branch matches this regex
+        #[arg(long)]
+        branch_regex: Option<String>,
+
+        /// Only scratches owned by this user
+        #[arg(long)]
+        owner: Option<String>,
+
+        /// Only scratches with this tag (repeatable or comma separated)
+        #[arg(long = "tag", value_delimiter = ',')]
+        tags: Vec<String>,
+
+        /// How many scratches to work on at once
+        #[arg(short = 'j', long, default_value_t = crate::scratch::DEFAULT_BULK_CONCURRENCY)]
+        concurrency: usize,
+
+        /// Only list the scratches that would be affected
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Push out the expiry deadline of a scratch environment
+    Extend {
+        /// Name of the scratch to extend
+        name: String,
+
+        /// How long to extend by (e.g. "12h", "7d")
+        duration: String,
+    },
+
     /// View logs from a scratch environment
     Logs {
         /// Name of the scratch
@@ -112,6 +243,28 @@ pub enum Commands {
         tail: usize,
     },
 
+    /// Show CPU, memory, network and block IO usage of scratches
+    Stats {
+        /// Name of the scratch (all running scratches if omitted)
+        name: Option<String>,
+
+        /// Keep refreshing the stats
+        #[arg(short, long)]
+        watch: bool,
+    },
+
+    /// Find and remove containers, volumes, databases and directories left
+    /// behind by scratches that no longer exist
+    Gc {
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+
     /// Start the HTTP API server and web UI
     Serve {
         /// Host to bind to
@@ -129,6 +282,34 @@ pub enum Commands {
         name: String,
     },
 
+    /// Manage point-in-time snapshots of a scratch environment
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
+    },
+
+    /// Manage the databases scratches are seeded from
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+
+    /// Follow background jobs queued on a running server
+    Jobs {
+        /// URL of the scratchpad server (defaults to the configured host and port)
+        #[arg(long, env = "SCRATCHPAD_SERVER", global = true)]
+        server: Option<String>,
+
+        #[command(subcommand)]
+        action: JobsAction,
+    },
+
+    /// Inspect compose templates
+    Template {
+        #[command(subcommand)]
+        action: TemplateAction,
+    },
+
     /// Manage nginx configuration
     Nginx {
         #[command(subcommand)]
@@ -151,6 +332,104 @@ pub enum Commands {
     Doctor,
 }
 
+#[derive(Subcommand)]
+pub enum SnapshotAction {
+    /// Snapshot a scratch's databases, data directory and config
+    Create {
+        /// Name of the scratch
+        scratch: String,
+
+        /// Name for the snapshot (defaults to the current timestamp)
+        #[arg(short, long)]
+        name: Option<String>,
+    },
+
+    /// List the snapshots of a scratch
+    List {
+        /// Name of the scratch
+        scratch: String,
+    },
+
+    /// Roll a scratch back to a snapshot
+    Restore {
+        /// Name of the scratch
+        scratch: String,
+
+        /// Name of the snapshot to restore
+        snapshot: String,
+
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Delete a snapshot
+    Delete {
+        /// Name of the scratch
+        scratch: String,
+
+        /// Name of the snapshot to delete
+        snapshot: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DbAction {
+    /// Manage the template databases loaded from `seed_from` dumps
+    Template {
+        #[command(subcommand)]
+        action: DbTemplateAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DbTemplateAction {
+    /// Rebuild a template database from its dump
+    Refresh {
+        /// Profile whose template to rebuild (defaults to the postgres service's)
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum JobsAction {
+    /// List recent jobs
+    List,
+
+    /// Show a job and its progress
+    Show {
+        /// Job id
+        id: String,
+
+        /// Follow the job's progress until it finishes
+        #[arg(short, long)]
+        wait: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TemplateAction {
+    /// List available templates
+    List,
+
+    /// Show the source of a template
+    Show {
+        /// Name of the template
+        name: String,
+    },
+
+    /// Render a template for an existing scratch without applying it
+    Render {
+        /// Name of the template
+        name: String,
+
+        /// Scratch to render the template for
+        #[arg(short, long)]
+        scratch: String,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum NginxAction {
     /// Regenerate nginx configuration
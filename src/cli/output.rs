@@ -3,7 +3,9 @@
 use colored::Colorize;
 use comfy_table::{presets::UTF8_FULL, Cell, Color, ContentArrangement, Table};
 
-use crate::scratch::ScratchStatus;
+use crate::config::format_memory;
+use crate::docker::ResourceUsage;
+use crate::scratch::{BulkOutcome, BulkResult, Orphan, ScratchDrift, ScratchStats, ScratchStatus};
 
 /// Print a success message
 pub fn success(message: &str) {
@@ -28,9 +30,9 @@ pub fn info(message: &str) {
 /// Format scratch status as a colored string
 pub fn format_status(status: &str) -> String {
     match status.to_lowercase().as_str() {
-        "running" => status.green().to_string(),
-        "stopped" | "exited" => status.red().to_string(),
-        "starting" | "restarting" => status.yellow().to_string(),
+        "running" | "succeeded" => status.green().to_string(),
+        "stopped" | "exited" | "failed" => status.red().to_string(),
+        "starting" | "restarting" | "creating" | "queued" => status.yellow().to_string(),
         _ => status.to_string(),
     }
 }
@@ -49,16 +51,18 @@ pub fn print_scratch_table(scratches: &[ScratchStatus]) {
         .set_header(vec![
             Cell::new("Name").fg(Color::Cyan),
             Cell::new("Branch").fg(Color::Cyan),
+            Cell::new("Owner").fg(Color::Cyan),
             Cell::new("Status").fg(Color::Cyan),
             Cell::new("Services").fg(Color::Cyan),
             Cell::new("URL").fg(Color::Cyan),
             Cell::new("Created").fg(Color::Cyan),
+            Cell::new("Expires").fg(Color::Cyan),
         ]);
 
     for scratch in scratches {
         let status_color = match scratch.status.as_str() {
             "running" => Color::Green,
-            "stopped" | "exited" => Color::Red,
+            "stopped" | "exited" | "failed" => Color::Red,
             _ => Color::Yellow,
         };
 
@@ -80,13 +84,26 @@ pub fn print_scratch_table(scratches: &[ScratchStatus]) {
             .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
             .unwrap_or_else(|| "unknown".to_string());
 
+        let expires = scratch
+            .expires_at
+            .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "never".to_string());
+
+        let status = if scratch.outdated {
+            format!("{} (outdated)", scratch.status)
+        } else {
+            scratch.status.clone()
+        };
+
         table.add_row(vec![
             Cell::new(&scratch.name),
             Cell::new(&scratch.branch),
-            Cell::new(&scratch.status).fg(status_color),
+            Cell::new(scratch.owner.as_deref().unwrap_or("-")),
+            Cell::new(status).fg(status_color),
             Cell::new(services),
             Cell::new(scratch.url.as_deref().unwrap_or("-")),
             Cell::new(created),
+            Cell::new(expires),
         ]);
     }
 
@@ -99,8 +116,29 @@ pub fn print_scratch_detail(scratch: &ScratchStatus) {
     println!();
     println!("  {} {}", "Name:".bold(), scratch.name);
     println!("  {} {}", "Branch:".bold(), scratch.branch);
+    if !scratch.branches.is_empty() {
+        let branches: Vec<String> = scratch
+            .branches
+            .iter()
+            .map(|(repo, branch)| format!("{}={}", repo, branch))
+            .collect();
+        println!("  {} {}", "Branches:".bold(), branches.join(", "));
+    }
     println!("  {} {}", "Status:".bold(), format_status(&scratch.status));
 
+    if let Some(owner) = &scratch.owner {
+        println!("  {} {}", "Owner:".bold(), owner);
+    }
+
+    if let Some(description) = &scratch.description {
+        println!("  {} {}", "Description:".bold(), description);
+    }
+
+    if !scratch.tags.is_empty() {
+        let tags: Vec<&str> = scratch.tags.iter().map(String::as_str).collect();
+        println!("  {} {}", "Tags:".bold(), tags.join(", "));
+    }
+
     if let Some(url) = &scratch.url {
         println!("  {} {}", "URL:".bold(), url.cyan());
     }
@@ -113,6 +151,18 @@ pub fn print_scratch_detail(scratch: &ScratchStatus) {
         );
     }
 
+    if let Some(expires) = scratch.expires_at {
+        println!(
+            "  {} {}",
+            "Expires:".bold(),
+            expires.format("%Y-%m-%d %H:%M:%S UTC")
+        );
+    }
+
+    if let Some(error) = &scratch.error {
+        println!("  {} {}", "Error:".bold(), error.red());
+    }
+
     println!();
     println!("  {}", "Services:".bold());
     for (name, status) in &scratch.services {
@@ -121,7 +171,16 @@ pub fn print_scratch_detail(scratch: &ScratchStatus) {
         } else {
             "○".red()
         };
-        println!("    {} {} ({})", status_icon, name, status);
+        match scratch.images.get(name) {
+            Some(image) => println!(
+                "    {} {} ({}) {}",
+                status_icon,
+                name,
+                status,
+                image.dimmed()
+            ),
+            None => println!("    {} {} ({})", status_icon, name, status),
+        }
     }
 
     if !scratch.databases.is_empty() {
@@ -133,6 +192,165 @@ pub fn print_scratch_detail(scratch: &ScratchStatus) {
     }
 }
 
+/// Print a scratch's resource usage by service, with a total row
+pub fn print_scratch_stats(stats: &ScratchStats) {
+    if stats.services.is_empty() {
+        info(&format!(
+            "No running containers for scratch: {}",
+            stats.scratch
+        ));
+        return;
+    }
+
+    let mut table = stats_table("Service");
+    for (service, usage) in &stats.services {
+        table.add_row(stats_row(Cell::new(service), usage));
+    }
+    table.add_row(stats_row(Cell::new("TOTAL").fg(Color::Cyan), &stats.total));
+
+    println!("{table}");
+}
+
+/// Print the total resource usage of each scratch
+pub fn print_stats_table(stats: &[ScratchStats]) {
+    if stats.is_empty() {
+        info("No running scratches");
+        return;
+    }
+
+    let mut table = stats_table("Scratch");
+    for scratch in stats {
+        table.add_row(stats_row(Cell::new(&scratch.scratch), &scratch.total));
+    }
+
+    println!("{table}");
+}
+
+fn stats_table(first_column: &str) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new(first_column).fg(Color::Cyan),
+            Cell::new("CPU %").fg(Color::Cyan),
+            Cell::new("Memory").fg(Color::Cyan),
+            Cell::new("Mem %").fg(Color::Cyan),
+            Cell::new("Net RX / TX").fg(Color::Cyan),
+            Cell::new("Block R / W").fg(Color::Cyan),
+        ]);
+    table
+}
+
+fn stats_row(name: Cell, usage: &ResourceUsage) -> Vec<Cell> {
+    vec![
+        name,
+        Cell::new(format!("{:.1}%", usage.cpu_percent)),
+        Cell::new(format!(
+            "{} / {}",
+            format_memory(usage.memory_used),
+            format_memory(usage.memory_limit)
+        )),
+        Cell::new(format!("{:.1}%", usage.memory_percent())),
+        Cell::new(format!(
+            "{} / {}",
+            format_memory(usage.net_rx),
+            format_memory(usage.net_tx)
+        )),
+        Cell::new(format!(
+            "{} / {}",
+            format_memory(usage.block_read),
+            format_memory(usage.block_write)
+        )),
+    ]
+}
+
+/// Print the per-scratch results of a bulk operation
+pub fn print_bulk_results(results: &[BulkResult]) {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("Scratch").fg(Color::Cyan),
+            Cell::new("Result").fg(Color::Cyan),
+            Cell::new("Error").fg(Color::Cyan),
+        ]);
+
+    for result in results {
+        let outcome = match result.outcome {
+            BulkOutcome::Matched => "matched",
+            BulkOutcome::Succeeded => "succeeded",
+            BulkOutcome::Failed => "failed",
+        };
+        table.add_row(vec![
+            Cell::new(&result.scratch),
+            Cell::new(format_status(outcome)),
+            Cell::new(result.error.as_deref().unwrap_or("")),
+        ]);
+    }
+
+    println!("{table}");
+}
+
+/// Print how a scratch's compose file differs from a fresh render
+pub fn print_scratch_drift(drift: &ScratchDrift) {
+    if let Some(e) = &drift.error {
+        warn(&format!("{}: can't check for drift: {}", drift.scratch, e));
+        return;
+    }
+    if !drift.is_outdated() {
+        success(&format!("{} is up to date", drift.scratch));
+        return;
+    }
+
+    println!(
+        "{} {} is outdated ({} change(s))",
+        "⚠".yellow(),
+        drift.scratch.bold(),
+        drift.changes.len()
+    );
+    let mut service = None;
+    for change in &drift.changes {
+        if service != Some(&change.service) {
+            println!("  {}", change.service.cyan());
+            service = Some(&change.service);
+        }
+        let line = match (&change.old, &change.new) {
+            (Some(old), Some(new)) => format!("~ {}: {} → {}", change.field, old, new).yellow(),
+            (None, Some(new)) => format!("+ {}: {}", change.field, new).green(),
+            (Some(old), None) => format!("- {}: {}", change.field, old).red(),
+            (None, None) => format!("~ {}", change.field).normal(),
+        };
+        println!("    {}", line);
+    }
+}
+
+/// Print a table of orphaned resources
+pub fn print_orphans_table(orphans: &[Orphan]) {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("Kind").fg(Color::Cyan),
+            Cell::new("Name").fg(Color::Cyan),
+            Cell::new("Scratch").fg(Color::Cyan),
+            Cell::new("Reason").fg(Color::Cyan),
+        ]);
+
+    for orphan in orphans {
+        table.add_row(vec![
+            Cell::new(orphan.kind),
+            Cell::new(&orphan.name),
+            Cell::new(&orphan.scratch),
+            Cell::new(&orphan.reason),
+        ]);
+    }
+
+    println!("{table}");
+}
+
 /// Confirm an action with the user
 pub fn confirm(message: &str) -> crate::error::Result<bool> {
     use std::io::{self, Write};
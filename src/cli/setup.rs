@@ -17,8 +17,8 @@ use std::path::Path;
 use std::time::Duration;
 
 use crate::config::{
-    Config, DockerConfig, NginxConfig, NginxRouting, ScratchDefaults, ScratchProfile, ServerConfig,
-    ServiceConfig,
+    Config, DockerConfig, HooksConfig, NginxConfig, NginxRouting, QuotaConfig, ResourceLimits,
+    ScratchDefaults, ScratchProfile, ServerConfig, ServiceConfig, StoreConfig, WatchConfig,
 };
 use crate::docker::DockerClient;
 
@@ -351,6 +351,10 @@ fn gather_config(theme: &ColorfulTheme) -> Result<Config> {
             "postgres" => ServiceConfig {
                 image: "postgres:18".to_string(),
                 shared: true,
+                fallback_images: vec![],
+                seed_from: None,
+                build: None,
+                repo: None,
                 port: Some(5432),
                 internal_port: None, // derived from image
                 env: HashMap::from([
@@ -359,52 +363,78 @@ fn gather_config(theme: &ColorfulTheme) -> Result<Config> {
                 ]),
                 volumes: vec![],
                 healthcheck: Some("pg_isready -U postgres".to_string()),
+                health_timeout: None,
                 auto_create_db: true,
                 connection: None,
+                resources: ResourceLimits::default(),
             },
             "redis" => ServiceConfig {
                 image: "redis:7-alpine".to_string(),
                 shared: true,
+                fallback_images: vec![],
+                seed_from: None,
+                build: None,
+                repo: None,
                 port: Some(6379),
                 internal_port: None,
                 env: HashMap::new(),
                 volumes: vec![],
                 healthcheck: Some("redis-cli ping".to_string()),
+                health_timeout: None,
                 auto_create_db: false,
                 connection: None,
+                resources: ResourceLimits::default(),
             },
             "nginx" => ServiceConfig {
                 image: "nginx:alpine".to_string(),
                 shared: true,
+                fallback_images: vec![],
+                seed_from: None,
+                build: None,
+                repo: None,
                 port: Some(80),
                 internal_port: Some(80),
                 env: HashMap::new(),
                 volumes: vec![],
                 healthcheck: None,
+                health_timeout: None,
                 auto_create_db: false,
                 connection: None,
+                resources: ResourceLimits::default(),
             },
             "mysql" => ServiceConfig {
                 image: "mysql:8".to_string(),
                 shared: true,
+                fallback_images: vec![],
+                seed_from: None,
+                build: None,
+                repo: None,
                 port: Some(3306),
                 internal_port: None,
                 env: HashMap::from([("MYSQL_ROOT_PASSWORD".to_string(), "mysql".to_string())]),
                 volumes: vec![],
                 healthcheck: Some("mysqladmin ping -h localhost".to_string()),
+                health_timeout: None,
                 auto_create_db: false,
                 connection: None,
+                resources: ResourceLimits::default(),
             },
             "mongodb" => ServiceConfig {
                 image: "mongo:7".to_string(),
                 shared: true,
+                fallback_images: vec![],
+                seed_from: None,
+                build: None,
+                repo: None,
                 port: Some(27017),
                 internal_port: None,
                 env: HashMap::new(),
                 volumes: vec![],
                 healthcheck: Some("mongosh --eval 'db.runCommand(\"ping\").ok'".to_string()),
+                health_timeout: None,
                 auto_create_db: false,
                 connection: None,
+                resources: ResourceLimits::default(),
             },
             _ => continue,
         };
@@ -430,6 +460,7 @@ fn gather_config(theme: &ColorfulTheme) -> Result<Config> {
             host: "0.0.0.0".to_string(),
             port,
             releases_dir: "./releases".into(),
+            templates_dir: "./templates".into(),
         },
         docker: DockerConfig::default(),
         nginx: NginxConfig {
@@ -441,6 +472,7 @@ fn gather_config(theme: &ColorfulTheme) -> Result<Config> {
             container: None,
             dynamic: Some(true),
             ingress_service: None, // Will be set after service selection
+            server_upstream: None,
         },
         github: None,
         services,
@@ -455,6 +487,10 @@ fn gather_config(theme: &ColorfulTheme) -> Result<Config> {
                         template: None,
                         services: default_services.iter().take(1).cloned().collect(),
                         env: HashMap::new(),
+                        ttl: None,
+                        resources: ResourceLimits::default(),
+                        hooks: HooksConfig::default(),
+                        seed_from: None,
                     },
                 ),
                 (
@@ -463,10 +499,23 @@ fn gather_config(theme: &ColorfulTheme) -> Result<Config> {
                         template: None,
                         services: default_services,
                         env: HashMap::new(),
+                        ttl: None,
+                        resources: ResourceLimits::default(),
+                        hooks: HooksConfig::default(),
+                        seed_from: None,
                     },
                 ),
             ]),
+            ttl: None,
+            reaper_interval: "5m".to_string(),
+            idle_timeout: None,
+            health_timeout: "2m".to_string(),
         },
+        store: StoreConfig::default(),
+        quotas: QuotaConfig::default(),
+        watch: WatchConfig::default(),
+        hooks: HooksConfig::default(),
+        base_dir: None,
     };
 
     Ok(config)
@@ -557,9 +606,10 @@ async fn create_demo_scratch(config: &Config) -> Result<()> {
         config,
         &docker,
         "demo",
-        Some("demo".to_string()),
-        None,
-        None,
+        crate::scratch::CreateScratchOptions {
+            name: Some("demo".to_string()),
+            ..Default::default()
+        },
     )
     .await
     {
@@ -622,6 +672,10 @@ async fn run_quick_setup() -> Result<()> {
         ServiceConfig {
             image: "postgres:18".to_string(),
             shared: true,
+            fallback_images: vec![],
+            seed_from: None,
+            build: None,
+            repo: None,
             port: Some(5432),
             internal_port: None,
             env: HashMap::from([
@@ -630,8 +684,10 @@ async fn run_quick_setup() -> Result<()> {
             ]),
             volumes: vec![],
             healthcheck: Some("pg_isready -U postgres".to_string()),
+            health_timeout: None,
             auto_create_db: true,
             connection: None,
+            resources: ResourceLimits::default(),
         },
     );
     services.insert(
@@ -639,13 +695,19 @@ async fn run_quick_setup() -> Result<()> {
         ServiceConfig {
             image: "redis:8-alpine".to_string(),
             shared: false,
+            fallback_images: vec![],
+            seed_from: None,
+            build: None,
+            repo: None,
             port: None,
             internal_port: None,
             env: HashMap::new(),
             volumes: vec![],
             healthcheck: Some("redis-cli ping".to_string()),
+            health_timeout: None,
             auto_create_db: false,
             connection: None,
+            resources: ResourceLimits::default(),
         },
     );
 
@@ -666,6 +728,10 @@ async fn run_quick_setup() -> Result<()> {
                         template: None,
                         services: vec!["postgres".to_string()],
                         env: HashMap::new(),
+                        ttl: None,
+                        resources: ResourceLimits::default(),
+                        hooks: HooksConfig::default(),
+                        seed_from: None,
                     },
                 ),
                 (
@@ -674,10 +740,23 @@ async fn run_quick_setup() -> Result<()> {
                         template: None,
                         services: vec!["postgres".to_string(), "redis".to_string()],
                         env: HashMap::new(),
+                        ttl: None,
+                        resources: ResourceLimits::default(),
+                        hooks: HooksConfig::default(),
+                        seed_from: None,
                     },
                 ),
             ]),
+            ttl: None,
+            reaper_interval: "5m".to_string(),
+            idle_timeout: None,
+            health_timeout: "2m".to_string(),
         },
+        store: StoreConfig::default(),
+        quotas: QuotaConfig::default(),
+        watch: WatchConfig::default(),
+        hooks: HooksConfig::default(),
+        base_dir: None,
     };
 
     write_config(&config)?;
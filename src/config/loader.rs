@@ -26,7 +26,12 @@ pub fn get_config_path() -> Result<PathBuf> {
 pub fn load_config_from_path(path: &Path) -> Result<Config> {
     let content = fs::read_to_string(path).map_err(|_| Error::ConfigNotFound)?;
     let content = interpolate_env_vars(&content);
-    let config: Config = toml::from_str(&content)?;
+    let mut config: Config = toml::from_str(&content)?;
+    config.base_dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(Path::to_path_buf);
+    crate::services::check_template_databases(&config)?;
     Ok(config)
 }
 
@@ -83,6 +88,7 @@ pub fn default_config_content() -> &'static str {
 host = "0.0.0.0"
 port = 3456
 releases_dir = "./releases"
+templates_dir = "./templates"  # custom compose templates
 
 [docker]
 socket = "/var/run/docker.sock"
@@ -95,6 +101,30 @@ domain = "scratches.localhost"
 routing = "subdomain"  # or "path"
 # container = "nginx"  # Container name for reload
 # reload_command = "docker exec nginx nginx -s reload"
+# server_upstream = "host.docker.internal:3456"  # Lets nginx report activity and wake stopped scratches
+
+# [store]
+# path = "./releases/scratchpad.db"  # Defaults to <releases_dir>/scratchpad.db
+# write_toml = true                  # Also keep each scratch's .scratchpad.toml up to date
+
+# [quotas]
+# max_scratches = 20
+# max_per_owner = 5
+# max_memory = "32g"     # Memory reserved by running scratches
+# scratch_memory = "2g"  # Counted per running scratch whose services set no memory limits
+
+# Lifecycle hooks: pre_create, post_create, post_start, pre_delete, post_update
+# [[hooks.post_create]]
+# service = "api"
+# exec_command = "bin/migrate"  # Or `command` to run a shell command on the host
+# timeout = "5m"
+# fail_on_error = true
+
+# [watch]
+# enabled = true     # Redeploy services when their image tag is pushed again
+# interval = "2m"
+# source = "docker"  # or "registry" to query registries directly
+# insecure_registries = ["localhost:5000"]
 
 # GitHub configuration (optional, for webhooks)
 # [github]
@@ -108,6 +138,7 @@ image = "postgres:18"
 shared = true
 port = 5432
 auto_create_db = true
+# seed_from = "./seeds/dev.sql"  # Copy new databases from a dump or an existing database
 env = { POSTGRES_PASSWORD = "postgres", POSTGRES_USER = "postgres" }
 healthcheck = "pg_isready -U postgres"
 
@@ -116,6 +147,18 @@ image = "redis:8-alpine"
 shared = false  # Each scratch gets its own instance
 healthcheck = "redis-cli ping"
 
+# Per-scratch services built from your own repos; `repo` picks which of a
+# scratch's branches (`create --branch api=feat-x --branch web=main`) they run
+# [services.api]
+# image = "myorg/api:{{ scratch.branch | sanitize }}"
+# fallback_images = ["myorg/api:main"]  # used when the branch image can't be pulled
+# repo = "api"
+#
+# Or build the image from the scratch's branch instead of pulling it
+# [services.web]
+# repo = "web"
+# build = { git = "https://github.com/myorg/web.git", dockerfile = "Dockerfile" }
+
 # Uncomment to add Kafka support
 # [services.kafka]
 # image = "bitnami/kafka:3.6"
@@ -126,10 +169,12 @@ healthcheck = "redis-cli ping"
 [scratch.defaults]
 template = "default"
 services = ["postgres", "redis"]
+# health_timeout = "2m"  # how long create waits for each service to become healthy
 
 # Custom profiles for different use cases
 [scratch.profiles.minimal]
 services = ["postgres"]
+# ttl = "1d"  # delete scratches using this profile after a day
 
 [scratch.profiles.full]
 services = ["postgres", "redis", "kafka"]
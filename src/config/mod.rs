@@ -1,7 +1,9 @@
 //! Configuration management for Scratchpad
 
 pub mod loader;
+mod resources;
 mod schema;
 
 pub use loader::{load_config, save_config};
+pub use resources::*;
 pub use schema::*;
@@ -0,0 +1,173 @@
+//! Resource limits for service containers
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::error::{Error, Result};
+
+/// CPU, memory and process limits for a service's containers
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// Number of CPUs the container may use (e.g. 0.5)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<f64>,
+
+    /// Hard memory limit (e.g. "512m")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mem_limit: Option<String>,
+
+    /// Memory the container is guaranteed (e.g. "256m")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mem_reservation: Option<String>,
+
+    /// Maximum number of processes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pids_limit: Option<i64>,
+
+    /// Ulimits by name (e.g. `nofile = { soft = 1024, hard = 4096 }`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ulimits: BTreeMap<String, Ulimit>,
+}
+
+/// A ulimit, either one value for both soft and hard limits or a pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Ulimit {
+    Single(i64),
+    Range { soft: i64, hard: i64 },
+}
+
+impl Ulimit {
+    /// The (soft, hard) limits
+    pub fn soft_hard(&self) -> (i64, i64) {
+        match *self {
+            Ulimit::Single(value) => (value, value),
+            Ulimit::Range { soft, hard } => (soft, hard),
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// Whether no limits are set
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Fill unset limits from `defaults`
+    ///
+    /// Ulimits are merged by name, with the ones set here winning.
+    pub fn or(&self, defaults: &ResourceLimits) -> ResourceLimits {
+        let mut ulimits = defaults.ulimits.clone();
+        ulimits.extend(self.ulimits.clone());
+
+        ResourceLimits {
+            cpus: self.cpus.or(defaults.cpus),
+            mem_limit: self.mem_limit.clone().or(defaults.mem_limit.clone()),
+            mem_reservation: self
+                .mem_reservation
+                .clone()
+                .or(defaults.mem_reservation.clone()),
+            pids_limit: self.pids_limit.or(defaults.pids_limit),
+            ulimits,
+        }
+    }
+
+    /// Memory the container reserves: its reservation, else its limit
+    pub fn reserved_memory(&self) -> Result<Option<u64>> {
+        self.mem_reservation
+            .as_deref()
+            .or(self.mem_limit.as_deref())
+            .map(parse_memory)
+            .transpose()
+    }
+}
+
+/// Parse a memory size such as "512m" or "2g" into bytes
+pub fn parse_memory(input: &str) -> Result<u64> {
+    let input = input.trim().to_lowercase();
+    let digits = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(digits);
+
+    let value: u64 = number
+        .parse()
+        .map_err(|_| Error::Config(format!("Invalid memory size: {}", input)))?;
+    let multiplier: u64 = match unit.trim_end_matches('b') {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        "t" => 1 << 40,
+        _ => return Err(Error::Config(format!("Invalid memory size: {}", input))),
+    };
+
+    // Docker takes sizes as an i64
+    value
+        .checked_mul(multiplier)
+        .filter(|bytes| i64::try_from(*bytes).is_ok())
+        .ok_or_else(|| Error::Config(format!("Memory size is too large: {}", input)))
+}
+
+/// Format a number of bytes for display (e.g. "1.5G")
+pub fn format_memory(bytes: u64) -> String {
+    const UNITS: [(&str, u64); 3] = [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+
+    for (unit, size) in UNITS {
+        if bytes >= size {
+            let value = bytes as f64 / size as f64;
+            return if value.fract() == 0.0 {
+                format!("{}{}", value, unit)
+            } else {
+                format!("{:.1}{}", value, unit)
+            };
+        }
+    }
+    format!("{}B", bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format_memory() {
+        assert_eq!(parse_memory("512").unwrap(), 512);
+        assert_eq!(parse_memory("512m").unwrap(), 512 << 20);
+        assert_eq!(parse_memory("2G").unwrap(), 2 << 30);
+        assert_eq!(parse_memory("4gb").unwrap(), 4 << 30);
+        assert!(parse_memory("lots").is_err());
+        assert!(parse_memory("2x").is_err());
+        assert!(matches!(
+            parse_memory("99999999999t"),
+            Err(Error::Config(_))
+        ));
+        assert!(parse_memory("8388608t").is_err());
+        assert_eq!(parse_memory("8388607t").unwrap(), 8388607 << 40);
+
+        assert_eq!(format_memory(2 << 30), "2G");
+        assert_eq!(format_memory(3 << 29), "1.5G");
+        assert_eq!(format_memory(512 << 20), "512M");
+        assert_eq!(format_memory(10), "10B");
+    }
+
+    #[test]
+    fn test_limits_fall_back_to_defaults() {
+        let defaults: ResourceLimits = toml::from_str(
+            "cpus = 1\nmem_limit = \"1g\"\npids_limit = 200\n\
+             [ulimits]\nnofile = { soft = 1024, hard = 4096 }\nnproc = 512",
+        )
+        .unwrap();
+        let service: ResourceLimits =
+            toml::from_str("mem_limit = \"512m\"\n[ulimits]\nnofile = 8192").unwrap();
+
+        let merged = service.or(&defaults);
+        assert_eq!(merged.cpus, Some(1.0));
+        assert_eq!(merged.mem_limit.as_deref(), Some("512m"));
+        assert_eq!(merged.pids_limit, Some(200));
+        assert_eq!(merged.ulimits["nofile"], Ulimit::Single(8192));
+        assert_eq!(merged.ulimits["nproc"].soft_hard(), (512, 512));
+        assert_eq!(merged.reserved_memory().unwrap(), Some(512 << 20));
+        assert!(ResourceLimits::default().is_empty());
+    }
+}
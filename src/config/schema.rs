@@ -1,9 +1,11 @@
 //! Configuration schema definitions
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::path::PathBuf;
 
+use super::ResourceLimits;
+
 /// Main configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
@@ -24,6 +26,23 @@ pub struct Config {
 
     #[serde(default)]
     pub scratch: ScratchDefaults,
+
+    #[serde(default)]
+    pub store: StoreConfig,
+
+    #[serde(default)]
+    pub quotas: QuotaConfig,
+
+    #[serde(default)]
+    pub watch: WatchConfig,
+
+    #[serde(default)]
+    pub hooks: HooksConfig,
+
+    /// Directory of the config file, which relative paths in it are
+    /// resolved against
+    #[serde(skip)]
+    pub base_dir: Option<PathBuf>,
 }
 
 /// Server configuration for the HTTP API
@@ -37,6 +56,10 @@ pub struct ServerConfig {
 
     #[serde(default = "default_releases_dir")]
     pub releases_dir: PathBuf,
+
+    /// Directory holding custom compose templates
+    #[serde(default = "default_templates_dir")]
+    pub templates_dir: PathBuf,
 }
 
 fn default_host() -> String {
@@ -51,16 +74,127 @@ fn default_releases_dir() -> PathBuf {
     PathBuf::from("./releases")
 }
 
+fn default_templates_dir() -> PathBuf {
+    PathBuf::from("./templates")
+}
+
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             host: default_host(),
             port: default_port(),
             releases_dir: default_releases_dir(),
+            templates_dir: default_templates_dir(),
+        }
+    }
+}
+
+/// State store configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreConfig {
+    /// Path to the SQLite database (defaults to `<releases_dir>/scratchpad.db`)
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+
+    /// Also write each scratch's `.scratchpad.toml` for tools that read it
+    #[serde(default = "default_write_toml")]
+    pub write_toml: bool,
+}
+
+fn default_write_toml() -> bool {
+    true
+}
+
+/// Limits on how many scratches (and how much memory) may be in use
+///
+/// Unset limits are not enforced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuotaConfig {
+    /// Maximum number of scratches in total
+    #[serde(default)]
+    pub max_scratches: Option<usize>,
+
+    /// Maximum number of scratches a single owner may have
+    #[serde(default)]
+    pub max_per_owner: Option<usize>,
+
+    /// Maximum number of scratches created from each profile
+    #[serde(default)]
+    pub max_per_profile: HashMap<String, usize>,
+
+    /// Maximum memory reserved by running scratches (e.g. "32g")
+    #[serde(default)]
+    pub max_memory: Option<String>,
+
+    /// Memory each scratch reserves against `max_memory` (e.g. "2g")
+    #[serde(default)]
+    pub scratch_memory: Option<String>,
+}
+
+/// Redeploying scratches when the image tag a service runs is pushed again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchConfig {
+    /// Watch the images of running scratches from the server
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// How often image digests are checked
+    #[serde(default = "default_watch_interval")]
+    pub interval: String,
+
+    /// Where the current digest of a tag is looked up
+    #[serde(default)]
+    pub source: DigestSource,
+
+    /// Registries queried over plain HTTP by the `registry` source
+    #[serde(default)]
+    pub insecure_registries: Vec<String>,
+}
+
+fn default_watch_interval() -> String {
+    "2m".to_string()
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: default_watch_interval(),
+            source: DigestSource::default(),
+            insecure_registries: Vec::new(),
+        }
+    }
+}
+
+/// Where the watcher looks up the digest of an image tag
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestSource {
+    /// Ask the Docker daemon, which uses its own registry credentials
+    #[default]
+    Docker,
+    /// Query the registry's HTTP API directly (anonymous access only)
+    Registry,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            write_toml: default_write_toml(),
         }
     }
 }
 
+impl StoreConfig {
+    /// Resolve the database path for the given releases directory
+    pub fn resolve_path(&self, releases_dir: &std::path::Path) -> PathBuf {
+        self.path
+            .clone()
+            .unwrap_or_else(|| releases_dir.join("scratchpad.db"))
+    }
+}
+
 /// Docker configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DockerConfig {
@@ -129,6 +263,11 @@ pub struct NginxConfig {
     /// e.g., "api" means requests route to <scratch>-api container
     #[serde(default)]
     pub ingress_service: Option<String>,
+
+    /// Address nginx uses to reach the scratchpad server (e.g. "host.docker.internal:3456")
+    /// Enables request activity tracking and wake-on-request for stopped scratches
+    #[serde(default)]
+    pub server_upstream: Option<String>,
 }
 
 fn default_nginx_enabled() -> bool {
@@ -158,6 +297,7 @@ impl Default for NginxConfig {
             container: None,
             dynamic: None,
             ingress_service: None,
+            server_upstream: None,
         }
     }
 }
@@ -185,11 +325,28 @@ pub struct GithubConfig {
 /// Shared service configuration (postgres, redis, kafka, etc.)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceConfig {
+    /// Image to run; services with a `build` section can leave it out.
+    /// Per-scratch services may use expressions such as
+    /// `myorg/api:{{ scratch.branch | sanitize }}`
+    #[serde(default)]
     pub image: String,
 
+    /// Images tried in order when `image` can't be pulled
+    #[serde(default)]
+    pub fallback_images: Vec<String>,
+
+    /// Build the image from a git repository instead of pulling it
+    #[serde(default)]
+    pub build: Option<ServiceBuild>,
+
     #[serde(default)]
     pub shared: bool,
 
+    /// Repository the service is built from (e.g. "api" or "web"); picks
+    /// which of a scratch's `branches` the service runs
+    #[serde(default)]
+    pub repo: Option<String>,
+
     /// Host port to expose the service on
     #[serde(default)]
     pub port: Option<u16>,
@@ -207,13 +364,53 @@ pub struct ServiceConfig {
     #[serde(default)]
     pub healthcheck: Option<String>,
 
+    /// How long a new scratch waits for this service to become healthy
+    /// (e.g. "5m"); defaults to `scratch.health_timeout`
+    #[serde(default)]
+    pub health_timeout: Option<String>,
+
     /// For postgres: automatically create databases
     #[serde(default)]
     pub auto_create_db: bool,
 
+    /// For postgres: database new scratch databases are copied from, or a
+    /// SQL dump loaded into a template database once
+    #[serde(default)]
+    pub seed_from: Option<String>,
+
     /// Connection parameters for DB provisioning
     #[serde(default)]
     pub connection: Option<ServiceConnection>,
+
+    /// CPU, memory and process limits
+    #[serde(flatten)]
+    pub resources: ResourceLimits,
+}
+
+/// How a per-scratch service's image is built from source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceBuild {
+    /// Git repository to check out: a local path or a URL
+    pub git: String,
+
+    /// Directory in the repository to build from (defaults to its root)
+    #[serde(default)]
+    pub context: Option<String>,
+
+    /// Dockerfile, relative to the context
+    #[serde(default = "default_dockerfile")]
+    pub dockerfile: String,
+
+    #[serde(default)]
+    pub args: HashMap<String, String>,
+
+    /// Build stage to stop at
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+fn default_dockerfile() -> String {
+    "Dockerfile".to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -238,12 +435,36 @@ pub struct ScratchDefaults {
 
     #[serde(default)]
     pub profiles: HashMap<String, ScratchProfile>,
+
+    /// Time-to-live for new scratches (e.g. "7d", "12h"); unset means never expire
+    #[serde(default)]
+    pub ttl: Option<String>,
+
+    /// How often the server checks for expired and idle scratches
+    #[serde(default = "default_reaper_interval")]
+    pub reaper_interval: String,
+
+    /// Stop scratches that have received no requests for this long (e.g. "2h")
+    #[serde(default)]
+    pub idle_timeout: Option<String>,
+
+    /// How long a new scratch waits for each service to become healthy
+    #[serde(default = "default_health_timeout")]
+    pub health_timeout: String,
 }
 
 fn default_template() -> String {
     "default".to_string()
 }
 
+fn default_reaper_interval() -> String {
+    "5m".to_string()
+}
+
+fn default_health_timeout() -> String {
+    "2m".to_string()
+}
+
 impl Default for ScratchDefaults {
     fn default() -> Self {
         Self {
@@ -251,6 +472,10 @@ impl Default for ScratchDefaults {
             services: Vec::new(),
             env: HashMap::new(),
             profiles: HashMap::new(),
+            ttl: None,
+            reaper_interval: default_reaper_interval(),
+            idle_timeout: None,
+            health_timeout: default_health_timeout(),
         }
     }
 }
@@ -266,6 +491,81 @@ pub struct ScratchProfile {
 
     #[serde(default)]
     pub env: HashMap<String, String>,
+
+    /// Overrides the default scratch TTL for this profile
+    #[serde(default)]
+    pub ttl: Option<String>,
+
+    /// Resource limits for services that don't set their own
+    #[serde(default)]
+    pub resources: ResourceLimits,
+
+    /// Hooks run after the global ones for scratches with this profile
+    #[serde(default)]
+    pub hooks: HooksConfig,
+
+    /// Overrides the postgres service's `seed_from` for this profile
+    #[serde(default)]
+    pub seed_from: Option<String>,
+}
+
+/// Commands run at points of a scratch's lifecycle
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HooksConfig {
+    /// Before the scratch's containers are created
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_create: Vec<Hook>,
+
+    /// Once a new scratch is running
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_create: Vec<Hook>,
+
+    /// After a stopped scratch is started again
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_start: Vec<Hook>,
+
+    /// Before a scratch is deleted, while its containers still run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_delete: Vec<Hook>,
+
+    /// After a scratch's compose file has been updated
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_update: Vec<Hook>,
+}
+
+/// A command run at a point of a scratch's lifecycle
+///
+/// Either `command` runs on the host, or `exec_command` runs in the
+/// container of `service`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hook {
+    /// Shell command run on the host, in the scratch directory
+    #[serde(default)]
+    pub command: Option<String>,
+
+    /// Service whose container runs `exec_command`
+    #[serde(default)]
+    pub service: Option<String>,
+
+    /// Shell command run in the service's container
+    #[serde(default)]
+    pub exec_command: Option<String>,
+
+    /// How long the command may run
+    #[serde(default = "default_hook_timeout")]
+    pub timeout: String,
+
+    /// Abort the operation if the command fails or times out
+    #[serde(default = "default_fail_on_error")]
+    pub fail_on_error: bool,
+}
+
+fn default_hook_timeout() -> String {
+    "5m".to_string()
+}
+
+fn default_fail_on_error() -> bool {
+    true
 }
 
 /// Runtime scratch instance configuration (stored per-scratch)
@@ -274,10 +574,93 @@ pub struct ScratchConfig {
     pub name: String,
     pub branch: String,
     pub template: String,
+
+    /// Branch per repository, for services that don't run `branch`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub branches: BTreeMap<String, String>,
+
+    /// Profile the scratch was created with, used to resolve its env
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// Lifecycle state as last recorded by scratchpad
+    #[serde(default)]
+    pub state: ScratchState,
+
+    /// Who the scratch belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+
+    /// Free-text note on what the scratch is for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Labels used to group and filter scratches
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+
     pub services: Vec<String>,
     pub databases: HashMap<String, Vec<String>>,
     pub env: HashMap<String, String>,
+
+    /// Image each per-scratch service runs, as resolved or built
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub images: BTreeMap<String, String>,
+
     pub created_at: chrono::DateTime<chrono::Utc>,
+
+    /// When the scratch will be deleted by the reaper (None = never)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Last request seen through the ingress (or last start)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_activity_at: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Why creation failed; set when the scratch is in the `failed` state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Lifecycle state of a scratch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScratchState {
+    /// Resources are being provisioned
+    Creating,
+    /// Containers were started
+    #[default]
+    Running,
+    /// Containers were stopped
+    Stopped,
+    /// Creation failed and was rolled back
+    Failed,
+}
+
+impl ScratchState {
+    /// Get the state as a string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScratchState::Creating => "creating",
+            ScratchState::Running => "running",
+            ScratchState::Stopped => "stopped",
+            ScratchState::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for ScratchState {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "creating" => Ok(ScratchState::Creating),
+            "running" => Ok(ScratchState::Running),
+            "stopped" => Ok(ScratchState::Stopped),
+            "failed" => Ok(ScratchState::Failed),
+            _ => Err(format!("Unknown scratch state: {}", s)),
+        }
+    }
 }
 
 impl Config {
@@ -290,4 +673,24 @@ impl Config {
     pub fn get_profile(&self, name: &str) -> Option<&ScratchProfile> {
         self.scratch.profiles.get(name)
     }
+
+    /// Repositories the services are mapped to
+    pub fn repos(&self) -> BTreeSet<&str> {
+        self.services
+            .values()
+            .filter_map(|s| s.repo.as_deref())
+            .collect()
+    }
+
+    /// Resource limits for a service, with the profile's defaults filled in
+    pub fn service_resources(
+        &self,
+        service: &ServiceConfig,
+        profile: Option<&str>,
+    ) -> ResourceLimits {
+        match profile.and_then(|p| self.get_profile(p)) {
+            Some(profile) => service.resources.or(&profile.resources),
+            None => service.resources.clone(),
+        }
+    }
 }
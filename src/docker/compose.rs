@@ -1,9 +1,10 @@
 //! Docker Compose file handling
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::path::Path;
 
+use crate::config::Ulimit;
 use crate::error::Result;
 
 /// Docker Compose file structure
@@ -83,8 +84,24 @@ pub struct ComposeService {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mem_limit: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mem_reservation: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pids_limit: Option<i64>,
+
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ulimits: BTreeMap<String, Ulimit>,
 }
 
+/// How compose builds a service's image
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ComposeBuild {
@@ -95,6 +112,8 @@ pub enum ComposeBuild {
         dockerfile: Option<String>,
         #[serde(default, skip_serializing_if = "HashMap::is_empty")]
         args: HashMap<String, String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<String>,
     },
 }
 
@@ -140,9 +159,41 @@ pub struct ComposeVolume {
     pub name: Option<String>,
 }
 
+/// A difference between two versions of a compose file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComposeChange {
+    pub service: String,
+    /// `service` when the whole service was added or removed, otherwise the
+    /// setting: `image`, `environment.<KEY>`, `ports`, `volumes`,
+    /// `labels.<KEY>` or any other compose key
+    pub field: String,
+    /// Value in the old file, or `None` if it was added
+    pub old: Option<String>,
+    /// Value in the new file, or `None` if it was removed
+    pub new: Option<String>,
+}
+
+impl ComposeChange {
+    fn new(
+        service: &str,
+        field: impl Into<String>,
+        old: Option<String>,
+        new: Option<String>,
+    ) -> Self {
+        Self {
+            service: service.to_string(),
+            field: field.into(),
+            old,
+            new,
+        }
+    }
+}
+
+/// Service keys compared entry by entry rather than as a whole
+const DIFFED_KEYS: [&str; 5] = ["image", "environment", "ports", "volumes", "labels"];
+
 impl ComposeFile {
     /// Load a compose file from disk
-    #[allow(dead_code)]
     pub fn load(path: &Path) -> Result<Self> {
         let content = std::fs::read_to_string(path)?;
         let compose: ComposeFile = serde_yaml::from_str(&content)?;
@@ -188,4 +239,215 @@ impl ComposeFile {
             }
         }
     }
+
+    /// List how the services of `new` differ from those of this file
+    ///
+    /// Images, environment variables, ports, volumes and labels are compared
+    /// one entry at a time; other settings are compared as a whole.
+    pub fn diff(&self, new: &ComposeFile) -> Vec<ComposeChange> {
+        let names: BTreeSet<&String> = self.services.keys().chain(new.services.keys()).collect();
+
+        let mut changes = Vec::new();
+        for name in names {
+            match (self.services.get(name), new.services.get(name)) {
+                (Some(old), Some(new)) => changes.extend(old.diff(name, new)),
+                (old, new) => changes.push(ComposeChange::new(
+                    name,
+                    "service",
+                    old.map(ComposeService::describe),
+                    new.map(ComposeService::describe),
+                )),
+            }
+        }
+        changes
+    }
+}
+
+impl ComposeService {
+    /// Short description of the service for added/removed changes
+    fn describe(&self) -> String {
+        match (&self.image, &self.build) {
+            (Some(image), _) => image.clone(),
+            (None, Some(_)) => "build".to_string(),
+            (None, None) => "defined".to_string(),
+        }
+    }
+
+    fn diff(&self, name: &str, new: &ComposeService) -> Vec<ComposeChange> {
+        let mut changes = Vec::new();
+
+        if self.image != new.image {
+            changes.push(ComposeChange::new(
+                name,
+                "image",
+                self.image.clone(),
+                new.image.clone(),
+            ));
+        }
+        diff_maps(
+            &mut changes,
+            name,
+            "environment",
+            &self.environment,
+            &new.environment,
+        );
+        diff_lists(&mut changes, name, "ports", &self.ports, &new.ports);
+        diff_lists(&mut changes, name, "volumes", &self.volumes, &new.volumes);
+        diff_maps(&mut changes, name, "labels", &self.labels, &new.labels);
+
+        // Everything else, key by key
+        let old = other_settings(self);
+        let new = other_settings(new);
+        let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        for key in keys {
+            let (old, new) = (old.get(key), new.get(key));
+            if old != new {
+                changes.push(ComposeChange::new(
+                    name,
+                    key.as_str(),
+                    old.map(yaml_to_string),
+                    new.map(yaml_to_string),
+                ));
+            }
+        }
+
+        changes
+    }
+}
+
+/// Compare two maps key by key, as `<field>.<KEY>` changes
+fn diff_maps(
+    changes: &mut Vec<ComposeChange>,
+    service: &str,
+    field: &str,
+    old: &HashMap<String, String>,
+    new: &HashMap<String, String>,
+) {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    for key in keys {
+        let (old, new) = (old.get(key), new.get(key));
+        if old != new {
+            changes.push(ComposeChange::new(
+                service,
+                format!("{}.{}", field, key),
+                old.cloned(),
+                new.cloned(),
+            ));
+        }
+    }
+}
+
+/// Compare two lists ignoring order, as one change per removed or added entry
+fn diff_lists(
+    changes: &mut Vec<ComposeChange>,
+    service: &str,
+    field: &str,
+    old: &[String],
+    new: &[String],
+) {
+    for entry in old.iter().filter(|entry| !new.contains(entry)) {
+        changes.push(ComposeChange::new(
+            service,
+            field,
+            Some(entry.clone()),
+            None,
+        ));
+    }
+    for entry in new.iter().filter(|entry| !old.contains(entry)) {
+        changes.push(ComposeChange::new(
+            service,
+            field,
+            None,
+            Some(entry.clone()),
+        ));
+    }
+}
+
+/// The settings of a service other than those diffed entry by entry
+fn other_settings(service: &ComposeService) -> BTreeMap<String, serde_yaml::Value> {
+    let Ok(serde_yaml::Value::Mapping(mapping)) = serde_yaml::to_value(service) else {
+        return BTreeMap::new();
+    };
+    mapping
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.as_str()?.to_string(), value)))
+        .filter(|(key, _)| !DIFFED_KEYS.contains(&key.as_str()))
+        .collect()
+}
+
+/// Render a compose value on one line
+fn yaml_to_string(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::String(s) => s.clone(),
+        value => serde_json::to_string(value).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compose(yaml: &str) -> ComposeFile {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_diff_identical_files() {
+        let file = compose(
+            "services:\n  api:\n    image: api:1\n    ports: [\"80:80\"]\n    mem_limit: 512m\n",
+        );
+        assert!(file.diff(&file.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = compose(
+            "services:
+  api:
+    image: api:1
+    environment: { A: \"1\", B: \"2\" }
+    ports: [\"8080:80\"]
+    volumes: [\"./data:/data\"]
+    labels: { team: web }
+    mem_limit: 512m
+  cache:
+    image: redis:7
+",
+        );
+        let new = compose(
+            "services:
+  api:
+    image: api:2
+    environment: { A: \"1\", B: \"3\", C: \"4\" }
+    ports: [\"8081:80\"]
+    volumes: [\"./data:/data\"]
+    mem_limit: 1g
+  worker:
+    build: .
+",
+        );
+
+        let change = |service: &str, field: &str, old: Option<&str>, new: Option<&str>| {
+            ComposeChange::new(
+                service,
+                field,
+                old.map(str::to_string),
+                new.map(str::to_string),
+            )
+        };
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                change("api", "image", Some("api:1"), Some("api:2")),
+                change("api", "environment.B", Some("2"), Some("3")),
+                change("api", "environment.C", None, Some("4")),
+                change("api", "ports", Some("8080:80"), None),
+                change("api", "ports", None, Some("8081:80")),
+                change("api", "labels.team", Some("web"), None),
+                change("api", "mem_limit", Some("512m"), Some("1g")),
+                change("cache", "service", Some("redis:7"), None),
+                change("worker", "service", None, Some("build")),
+            ]
+        );
+    }
 }
@@ -1,7 +1,9 @@
 //! Container management operations
 
 use bollard::container::LogOutput;
-use bollard::models::{ContainerCreateBody, ContainerSummary, HostConfig, PortBinding};
+use bollard::models::{
+    ContainerCreateBody, ContainerSummary, HostConfig, PortBinding, ResourcesUlimits,
+};
 use bollard::query_parameters::{
     CreateContainerOptions, CreateImageOptions, ListContainersOptions, LogsOptions,
     RemoveContainerOptions, StartContainerOptions, StopContainerOptions,
@@ -11,7 +13,8 @@ use std::collections::HashMap;
 use tokio::io::AsyncWriteExt;
 
 use super::DockerClient;
-use crate::error::Result;
+use crate::config::{parse_memory, ResourceLimits};
+use crate::error::{Error, Result};
 
 /// Container status information
 #[derive(Debug, Clone)]
@@ -19,11 +22,33 @@ pub struct ContainerStatus {
     pub id: String,
     pub name: String,
     pub image: String,
+    /// ID of the image the container was created from
+    pub image_id: String,
     pub state: String,
     pub status: String,
     pub labels: HashMap<String, String>,
 }
 
+/// Options for [`DockerClient::exec`]
+#[derive(Debug, Default)]
+pub struct ExecOptions<'a> {
+    /// Written to the command's stdin, which is closed afterwards
+    pub input: Option<&'a [u8]>,
+    /// Extra environment variables, as `KEY=value`
+    pub env: Vec<String>,
+    /// Keep stderr out of the output (it's still used in errors)
+    pub stdout_only: bool,
+    /// Fail if the command exits with a non-zero status
+    pub check_exit_code: bool,
+}
+
+/// What a command run with [`DockerClient::exec`] printed, and how it exited
+#[derive(Debug, Clone)]
+pub struct ExecOutput {
+    pub output: String,
+    pub exit_code: i64,
+}
+
 impl From<ContainerSummary> for ContainerStatus {
     fn from(container: ContainerSummary) -> Self {
         Self {
@@ -35,6 +60,7 @@ impl From<ContainerSummary> for ContainerStatus {
                 .trim_start_matches('/')
                 .to_string(),
             image: container.image.unwrap_or_default(),
+            image_id: container.image_id.unwrap_or_default(),
             state: container.state.map(|s| s.to_string()).unwrap_or_default(),
             status: container.status.unwrap_or_default(),
             labels: container.labels.unwrap_or_default(),
@@ -92,6 +118,24 @@ impl DockerClient {
         Ok(containers.into_iter().map(ContainerStatus::from).collect())
     }
 
+    /// List all containers started by docker compose
+    pub async fn list_compose_containers(&self) -> Result<Vec<ContainerStatus>> {
+        let mut filters: HashMap<String, Vec<String>> = HashMap::new();
+        filters.insert(
+            "label".to_string(),
+            vec![super::COMPOSE_PROJECT_LABEL.to_string()],
+        );
+
+        let options = ListContainersOptions {
+            all: true,
+            filters: Some(filters),
+            ..Default::default()
+        };
+
+        let containers: Vec<ContainerSummary> = self.inner().list_containers(Some(options)).await?;
+        Ok(containers.into_iter().map(ContainerStatus::from).collect())
+    }
+
     /// Create and start a container
     #[allow(clippy::too_many_arguments)]
     pub async fn create_container(
@@ -104,6 +148,7 @@ impl DockerClient {
         volumes: Vec<String>,
         network: Option<&str>,
         healthcheck_cmd: Option<&str>,
+        resources: &ResourceLimits,
     ) -> Result<String> {
         // Pull image if not present
         self.pull_image_if_missing(image).await?;
@@ -131,6 +176,12 @@ impl DockerClient {
         let exposed_ports: Vec<String> = port_keys.clone();
 
         // Host config
+        let memory = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|v| parse_memory(v).map(|bytes| bytes as i64))
+                .transpose()
+        };
         let host_config = HostConfig {
             binds: Some(volumes),
             port_bindings: Some(port_bindings),
@@ -139,6 +190,24 @@ impl DockerClient {
                 name: Some(bollard::models::RestartPolicyNameEnum::UNLESS_STOPPED),
                 maximum_retry_count: None,
             }),
+            nano_cpus: resources.cpus.map(|cpus| (cpus * 1e9) as i64),
+            memory: memory(&resources.mem_limit)?,
+            memory_reservation: memory(&resources.mem_reservation)?,
+            pids_limit: resources.pids_limit,
+            ulimits: (!resources.ulimits.is_empty()).then(|| {
+                resources
+                    .ulimits
+                    .iter()
+                    .map(|(name, limit)| {
+                        let (soft, hard) = limit.soft_hard();
+                        ResourcesUlimits {
+                            name: Some(name.clone()),
+                            soft: Some(soft),
+                            hard: Some(hard),
+                        }
+                    })
+                    .collect()
+            }),
             ..Default::default()
         };
 
@@ -304,7 +373,21 @@ impl DockerClient {
     }
 
     /// Execute a command in a running container
+    ///
+    /// Returns what the command printed on stdout and stderr, whatever its
+    /// exit code.
     pub async fn exec_command(&self, container_id: &str, cmd: Vec<&str>) -> Result<String> {
+        let output = self.exec(container_id, cmd, ExecOptions::default()).await?;
+        Ok(output.output)
+    }
+
+    /// Execute a command in a running container
+    pub async fn exec(
+        &self,
+        container_id: &str,
+        cmd: Vec<&str>,
+        options: ExecOptions<'_>,
+    ) -> Result<ExecOutput> {
         use bollard::exec::{CreateExecOptions, StartExecResults};
 
         let exec = self
@@ -312,9 +395,11 @@ impl DockerClient {
             .create_exec(
                 container_id,
                 CreateExecOptions {
+                    attach_stdin: Some(options.input.is_some()),
                     attach_stdout: Some(true),
                     attach_stderr: Some(true),
                     cmd: Some(cmd),
+                    env: Some(options.env.iter().map(String::as_str).collect()),
                     ..Default::default()
                 },
             )
@@ -323,12 +408,45 @@ impl DockerClient {
         let output = self.inner().start_exec(&exec.id, None).await?;
 
         let mut result = String::new();
-        if let StartExecResults::Attached { mut output, .. } = output {
+        let mut stderr = String::new();
+        if let StartExecResults::Attached {
+            mut output,
+            input: mut stdin,
+        } = output
+        {
+            if let Some(input) = options.input {
+                stdin.write_all(input).await?;
+                stdin.shutdown().await?;
+            }
+
             while let Some(Ok(msg)) = output.next().await {
-                result.push_str(&msg.to_string());
+                match msg {
+                    LogOutput::StdErr { message } if options.stdout_only => {
+                        stderr.push_str(&String::from_utf8_lossy(&message));
+                    }
+                    msg => result.push_str(&msg.to_string()),
+                }
             }
         }
 
-        Ok(result)
+        let inspect = self.inner().inspect_exec(&exec.id).await?;
+        let exit_code = inspect.exit_code.unwrap_or_default();
+        if options.check_exit_code && exit_code != 0 {
+            let printed = if options.stdout_only {
+                &stderr
+            } else {
+                &result
+            };
+            return Err(Error::Other(format!(
+                "Command exited with status {}: {}",
+                exit_code,
+                printed.trim()
+            )));
+        }
+
+        Ok(ExecOutput {
+            output: result,
+            exit_code,
+        })
     }
 }
@@ -0,0 +1,258 @@
+//! Container readiness checks
+
+use bollard::errors::Error as BollardError;
+use bollard::models::{ContainerInspectResponse, ContainerStateStatusEnum, HealthStatusEnum};
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+use super::DockerClient;
+use crate::error::Result;
+
+/// How often a container is inspected while waiting for it
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a container without a healthcheck has to keep running, without
+/// restarting, before it counts as ready
+const SETTLE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Readiness of a container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContainerHealth {
+    /// Healthy, settled without a healthcheck, or exited successfully
+    Ready,
+    /// Not ready yet, but may still become ready
+    Waiting(String),
+    /// Stopped and won't become ready on its own
+    Failed(String),
+}
+
+impl ContainerHealth {
+    /// Work out readiness from an inspected container
+    pub fn from_inspect(info: &ContainerInspectResponse) -> Self {
+        let Some(state) = &info.state else {
+            return ContainerHealth::Waiting("unknown".to_string());
+        };
+        let restarts = info.restart_count.unwrap_or(0);
+
+        if state.restarting == Some(true) {
+            return ContainerHealth::Waiting(format!(
+                "restarting (exit code {}, {} restarts)",
+                state.exit_code.unwrap_or(0),
+                restarts
+            ));
+        }
+
+        match state.status {
+            Some(ContainerStateStatusEnum::RUNNING) => {}
+            // One-off containers such as migrations are done once they exit cleanly
+            Some(ContainerStateStatusEnum::EXITED) if state.exit_code == Some(0) => {
+                return ContainerHealth::Ready;
+            }
+            Some(ContainerStateStatusEnum::EXITED | ContainerStateStatusEnum::DEAD) => {
+                let oom = if state.oom_killed == Some(true) {
+                    ", out of memory"
+                } else {
+                    ""
+                };
+                return ContainerHealth::Failed(format!(
+                    "exited (exit code {}{})",
+                    state.exit_code.unwrap_or(0),
+                    oom
+                ));
+            }
+            Some(status) => return ContainerHealth::Waiting(status.to_string()),
+            None => return ContainerHealth::Waiting("unknown".to_string()),
+        }
+
+        let Some(health) = &state.health else {
+            // No healthcheck configured, so running is as good as it gets;
+            // `wait_for_ready` also waits for it to settle
+            return ContainerHealth::Ready;
+        };
+        match health.status {
+            Some(HealthStatusEnum::HEALTHY) => ContainerHealth::Ready,
+            Some(HealthStatusEnum::UNHEALTHY) => {
+                let output = health
+                    .log
+                    .as_ref()
+                    .and_then(|log| log.last())
+                    .and_then(|result| result.output.as_deref())
+                    .map(str::trim)
+                    .filter(|output| !output.is_empty());
+                match output {
+                    Some(output) => ContainerHealth::Waiting(format!("unhealthy: {}", output)),
+                    None => ContainerHealth::Waiting("unhealthy".to_string()),
+                }
+            }
+            _ => ContainerHealth::Waiting("starting".to_string()),
+        }
+    }
+
+    /// Whether the container is ready
+    pub fn is_ready(&self) -> bool {
+        *self == ContainerHealth::Ready
+    }
+}
+
+impl std::fmt::Display for ContainerHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContainerHealth::Ready => write!(f, "ready"),
+            ContainerHealth::Waiting(state) | ContainerHealth::Failed(state) => {
+                write!(f, "{}", state)
+            }
+        }
+    }
+}
+
+/// Whether a container is running without a healthcheck
+fn running_without_healthcheck(info: &ContainerInspectResponse) -> bool {
+    info.state.as_ref().is_some_and(|state| {
+        state.status == Some(ContainerStateStatusEnum::RUNNING) && state.health.is_none()
+    })
+}
+
+/// Tracks a container without a healthcheck until it has settled
+///
+/// A process that crashes shortly after starting still shows as running
+/// until it exits, so the container is only ready once its restart count
+/// has stayed the same for [`SETTLE_PERIOD`].
+#[derive(Default)]
+struct Settling {
+    since: Option<(i64, Instant)>,
+}
+
+impl Settling {
+    fn check(&mut self, restarts: i64, now: Instant) -> ContainerHealth {
+        match self.since {
+            Some((count, since)) if count == restarts => {
+                if now.duration_since(since) >= SETTLE_PERIOD {
+                    ContainerHealth::Ready
+                } else {
+                    ContainerHealth::Waiting("running, no healthcheck".to_string())
+                }
+            }
+            _ => {
+                self.since = Some((restarts, now));
+                ContainerHealth::Waiting("running, no healthcheck".to_string())
+            }
+        }
+    }
+}
+
+impl DockerClient {
+    /// Get the readiness of a container
+    pub async fn container_health(&self, container: &str) -> Result<ContainerHealth> {
+        let info = self.inner().inspect_container(container, None).await?;
+        Ok(ContainerHealth::from_inspect(&info))
+    }
+
+    /// Whether a container exists and is running
+    pub async fn container_running(&self, container: &str) -> Result<bool> {
+        match self.inner().inspect_container(container, None).await {
+            Ok(info) => Ok(info.state.and_then(|s| s.running).unwrap_or(false)),
+            Err(BollardError::DockerResponseServerError {
+                status_code: 404, ..
+            }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Wait until a container is ready, it fails, or the timeout passes
+    ///
+    /// Returns the last readiness seen, so anything other than
+    /// [`ContainerHealth::Ready`] means the container didn't make it.
+    pub async fn wait_for_ready(
+        &self,
+        container: &str,
+        timeout: Duration,
+    ) -> Result<ContainerHealth> {
+        let deadline = Instant::now() + timeout;
+        let mut settling = Settling::default();
+
+        loop {
+            let info = self.inner().inspect_container(container, None).await?;
+            let mut health = ContainerHealth::from_inspect(&info);
+            if running_without_healthcheck(&info) {
+                health = settling.check(info.restart_count.unwrap_or(0), Instant::now());
+            }
+            if !matches!(health, ContainerHealth::Waiting(_)) || Instant::now() >= deadline {
+                return Ok(health);
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inspect(state: serde_json::Value) -> ContainerInspectResponse {
+        serde_json::from_value(serde_json::json!({ "State": state, "RestartCount": 3 })).unwrap()
+    }
+
+    #[test]
+    fn test_health_from_inspect() {
+        let running = inspect(serde_json::json!({ "Status": "running", "Running": true }));
+        assert_eq!(
+            ContainerHealth::from_inspect(&running),
+            ContainerHealth::Ready
+        );
+
+        let starting = inspect(serde_json::json!({
+            "Status": "running",
+            "Health": { "Status": "starting" }
+        }));
+        assert_eq!(
+            ContainerHealth::from_inspect(&starting).to_string(),
+            "starting"
+        );
+
+        let unhealthy = inspect(serde_json::json!({
+            "Status": "running",
+            "Health": { "Status": "unhealthy", "Log": [{ "ExitCode": 1, "Output": "refused\n" }] }
+        }));
+        assert_eq!(
+            ContainerHealth::from_inspect(&unhealthy),
+            ContainerHealth::Waiting("unhealthy: refused".to_string())
+        );
+
+        let crash_looping = inspect(serde_json::json!({
+            "Status": "restarting",
+            "Restarting": true,
+            "ExitCode": 1
+        }));
+        assert_eq!(
+            ContainerHealth::from_inspect(&crash_looping).to_string(),
+            "restarting (exit code 1, 3 restarts)"
+        );
+
+        let crashed =
+            inspect(serde_json::json!({ "Status": "exited", "ExitCode": 137, "OOMKilled": true }));
+        assert_eq!(
+            ContainerHealth::from_inspect(&crashed),
+            ContainerHealth::Failed("exited (exit code 137, out of memory)".to_string())
+        );
+
+        let completed = inspect(serde_json::json!({ "Status": "exited", "ExitCode": 0 }));
+        assert!(ContainerHealth::from_inspect(&completed).is_ready());
+    }
+
+    #[test]
+    fn test_container_without_healthcheck_settles() {
+        let running = inspect(serde_json::json!({ "Status": "running", "Running": true }));
+        assert!(running_without_healthcheck(&running));
+
+        let start = Instant::now();
+        let mut settling = Settling::default();
+        assert!(!settling.check(3, start).is_ready());
+        assert!(!settling.check(3, start + Duration::from_secs(2)).is_ready());
+
+        // A restart in between starts the grace period over
+        let restarted = start + Duration::from_secs(4);
+        assert!(!settling.check(4, restarted).is_ready());
+        assert!(!settling.check(4, start + SETTLE_PERIOD).is_ready());
+        assert!(settling.check(4, restarted + SETTLE_PERIOD).is_ready());
+    }
+}
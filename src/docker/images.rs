@@ -0,0 +1,87 @@
+//! Image builds and digests
+
+use bollard::errors::Error as BollardError;
+use bollard::query_parameters::{BuildImageOptions, RemoveImageOptions};
+use futures_util::StreamExt;
+use std::collections::HashMap;
+
+use super::DockerClient;
+use crate::config::ServiceBuild;
+use crate::error::{Error, Result};
+
+impl DockerClient {
+    /// Whether an image exists locally
+    pub async fn image_exists(&self, image: &str) -> bool {
+        self.inner().inspect_image(image).await.is_ok()
+    }
+
+    /// Build an image from a tar archive of its context
+    ///
+    /// Each line the build prints is passed to `on_output` as it arrives.
+    pub async fn build_image(
+        &self,
+        tag: &str,
+        build: &ServiceBuild,
+        labels: HashMap<String, String>,
+        context: Vec<u8>,
+        mut on_output: impl FnMut(&str),
+    ) -> Result<()> {
+        let options = BuildImageOptions {
+            dockerfile: build.dockerfile.clone(),
+            t: Some(tag.to_string()),
+            buildargs: Some(build.args.clone()),
+            labels: Some(labels),
+            target: build.target.clone().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        let body = bollard::body_full(bytes::Bytes::from(context));
+        let mut stream = self.inner().build_image(options, None, Some(body));
+        while let Some(info) = stream.next().await {
+            let info = info?;
+            if let Some(detail) = info.error_detail {
+                return Err(Error::Other(format!(
+                    "Building image {} failed: {}",
+                    tag,
+                    detail.message.unwrap_or_default().trim()
+                )));
+            }
+            if let Some(output) = info.stream {
+                output
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .for_each(&mut on_output);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The `repo@digest` references a local image was pulled as
+    pub async fn image_repo_digests(&self, image: &str) -> Result<Vec<String>> {
+        let inspect = self.inner().inspect_image(image).await?;
+        Ok(inspect.repo_digests.unwrap_or_default())
+    }
+
+    /// Digest the registry currently serves for an image reference
+    ///
+    /// Asks the daemon, so its registry credentials apply. `None` if the
+    /// registry doesn't have the image.
+    pub async fn registry_digest(&self, image: &str) -> Result<Option<String>> {
+        match self.inner().inspect_registry_image(image, None).await {
+            Ok(inspect) => Ok(inspect.descriptor.digest),
+            Err(BollardError::DockerResponseServerError {
+                status_code: 404, ..
+            }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove an image, unless a container still uses it
+    pub async fn remove_image(&self, image: &str) -> Result<()> {
+        self.inner()
+            .remove_image(image, None::<RemoveImageOptions>, None)
+            .await?;
+        Ok(())
+    }
+}
@@ -3,9 +3,22 @@
 mod client;
 mod compose;
 mod containers;
+mod health;
+mod images;
 mod networks;
+mod stats;
+mod volumes;
 
 pub use client::DockerClient;
-pub use compose::ComposeFile;
+pub use compose::{ComposeBuild, ComposeChange, ComposeFile};
 #[allow(unused_imports)]
-pub use containers::ContainerStatus;
+pub use containers::{ContainerStatus, ExecOptions, ExecOutput};
+pub use health::ContainerHealth;
+pub use stats::ResourceUsage;
+pub use volumes::ComposeVolume;
+
+/// Label docker compose puts on the containers and volumes of a project
+pub const COMPOSE_PROJECT_LABEL: &str = "com.docker.compose.project";
+
+/// Label docker compose puts on containers with the project's directory
+pub const COMPOSE_WORKING_DIR_LABEL: &str = "com.docker.compose.project.working_dir";
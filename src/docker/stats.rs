@@ -0,0 +1,186 @@
+//! Container resource usage
+
+use bollard::models::ContainerStatsResponse;
+use bollard::query_parameters::StatsOptions;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use super::DockerClient;
+use crate::error::{Error, Result};
+
+/// Resource usage of one or more containers
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// CPU usage, where 100% is one full CPU
+    pub cpu_percent: f64,
+    /// Memory in use, excluding the page cache, in bytes
+    pub memory_used: u64,
+    /// Memory limit in bytes (the host's memory when unlimited)
+    pub memory_limit: u64,
+    pub net_rx: u64,
+    pub net_tx: u64,
+    pub block_read: u64,
+    pub block_write: u64,
+}
+
+impl ResourceUsage {
+    /// Add another container's usage to this one
+    pub fn add(&mut self, other: &ResourceUsage) {
+        self.cpu_percent += other.cpu_percent;
+        self.memory_used += other.memory_used;
+        self.memory_limit += other.memory_limit;
+        self.net_rx += other.net_rx;
+        self.net_tx += other.net_tx;
+        self.block_read += other.block_read;
+        self.block_write += other.block_write;
+    }
+
+    /// Memory in use as a percentage of the limit
+    pub fn memory_percent(&self) -> f64 {
+        if self.memory_limit == 0 {
+            return 0.0;
+        }
+        self.memory_used as f64 / self.memory_limit as f64 * 100.0
+    }
+}
+
+impl From<&ContainerStatsResponse> for ResourceUsage {
+    /// Compute usage the same way `docker stats` does
+    fn from(stats: &ContainerStatsResponse) -> Self {
+        let mut usage = ResourceUsage::default();
+
+        if let (Some(cpu), Some(precpu)) = (&stats.cpu_stats, &stats.precpu_stats) {
+            let total = |s: &bollard::models::ContainerCpuStats| {
+                s.cpu_usage
+                    .as_ref()
+                    .and_then(|u| u.total_usage)
+                    .unwrap_or(0)
+            };
+            let cpu_delta = total(cpu).saturating_sub(total(precpu));
+            let system_delta = cpu
+                .system_cpu_usage
+                .unwrap_or(0)
+                .saturating_sub(precpu.system_cpu_usage.unwrap_or(0));
+            let online_cpus = cpu.online_cpus.map(u64::from).unwrap_or_else(|| {
+                cpu.cpu_usage
+                    .as_ref()
+                    .and_then(|u| u.percpu_usage.as_ref())
+                    .map(|p| p.len() as u64)
+                    .unwrap_or(1)
+            });
+
+            if cpu_delta > 0 && system_delta > 0 {
+                usage.cpu_percent =
+                    cpu_delta as f64 / system_delta as f64 * online_cpus as f64 * 100.0;
+            }
+        }
+
+        if let Some(memory) = &stats.memory_stats {
+            // Page cache can be reclaimed, so it isn't counted as used
+            let cache = memory
+                .stats
+                .as_ref()
+                .and_then(|s| s.get("inactive_file").or_else(|| s.get("cache")))
+                .copied()
+                .unwrap_or(0);
+            usage.memory_used = memory.usage.unwrap_or(0).saturating_sub(cache);
+            usage.memory_limit = memory.limit.unwrap_or(0);
+        }
+
+        for network in stats.networks.iter().flat_map(|n| n.values()) {
+            usage.net_rx += network.rx_bytes.unwrap_or(0);
+            usage.net_tx += network.tx_bytes.unwrap_or(0);
+        }
+
+        let io = stats
+            .blkio_stats
+            .as_ref()
+            .and_then(|b| b.io_service_bytes_recursive.as_ref());
+        for entry in io.into_iter().flatten() {
+            let value = entry.value.unwrap_or(0);
+            match entry.op.as_deref().map(str::to_lowercase).as_deref() {
+                Some("read") => usage.block_read += value,
+                Some("write") => usage.block_write += value,
+                _ => {}
+            }
+        }
+
+        usage
+    }
+}
+
+impl DockerClient {
+    /// Get a snapshot of a container's resource usage
+    pub async fn container_stats(&self, container_id: &str) -> Result<ResourceUsage> {
+        let options = StatsOptions {
+            stream: false,
+            // Docker needs a second sample to fill in precpu_stats
+            one_shot: false,
+        };
+
+        let mut stream = self.inner().stats(container_id, Some(options));
+        match stream.next().await {
+            Some(stats) => Ok(ResourceUsage::from(&stats?)),
+            None => Err(Error::Other(format!(
+                "No stats returned for container {}",
+                container_id
+            ))),
+        }
+    }
+
+    /// Total memory of the Docker host in bytes, if it reports it
+    pub async fn host_memory(&self) -> Option<u64> {
+        let info = self.inner().info().await.ok()?;
+        info.mem_total.and_then(|m| u64::try_from(m).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_from_stats() {
+        let stats: ContainerStatsResponse = serde_json::from_value(serde_json::json!({
+            "cpu_stats": {
+                "cpu_usage": { "total_usage": 400 },
+                "system_cpu_usage": 2000,
+                "online_cpus": 2
+            },
+            "precpu_stats": {
+                "cpu_usage": { "total_usage": 200 },
+                "system_cpu_usage": 1000
+            },
+            "memory_stats": {
+                "usage": 300,
+                "limit": 1000,
+                "stats": { "inactive_file": 100 }
+            },
+            "networks": {
+                "eth0": { "rx_bytes": 10, "tx_bytes": 20 },
+                "eth1": { "rx_bytes": 1, "tx_bytes": 2 }
+            },
+            "blkio_stats": {
+                "io_service_bytes_recursive": [
+                    { "major": 8, "minor": 0, "op": "read", "value": 5 },
+                    { "major": 8, "minor": 0, "op": "Write", "value": 7 },
+                    { "major": 8, "minor": 0, "op": "Total", "value": 12 }
+                ]
+            }
+        }))
+        .unwrap();
+
+        let usage = ResourceUsage::from(&stats);
+        assert_eq!(usage.cpu_percent, 40.0);
+        assert_eq!(usage.memory_used, 200);
+        assert_eq!(usage.memory_percent(), 20.0);
+        assert_eq!((usage.net_rx, usage.net_tx), (11, 22));
+        assert_eq!((usage.block_read, usage.block_write), (5, 7));
+
+        let mut total = ResourceUsage::default();
+        total.add(&usage);
+        total.add(&usage);
+        assert_eq!(total.cpu_percent, 80.0);
+        assert_eq!(total.memory_limit, 2000);
+    }
+}
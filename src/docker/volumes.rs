@@ -0,0 +1,51 @@
+//! Docker volume management
+
+use bollard::query_parameters::{ListVolumesOptions, RemoveVolumeOptions};
+use std::collections::HashMap;
+
+use super::{DockerClient, COMPOSE_PROJECT_LABEL};
+use crate::error::Result;
+
+/// A volume created by docker compose
+#[derive(Debug, Clone)]
+pub struct ComposeVolume {
+    pub name: String,
+    /// Compose project the volume belongs to
+    pub project: String,
+}
+
+impl DockerClient {
+    /// List all volumes created by docker compose
+    pub async fn list_compose_volumes(&self) -> Result<Vec<ComposeVolume>> {
+        let mut filters: HashMap<String, Vec<String>> = HashMap::new();
+        filters.insert("label".to_string(), vec![COMPOSE_PROJECT_LABEL.to_string()]);
+
+        let response = self
+            .inner()
+            .list_volumes(Some(ListVolumesOptions {
+                filters: Some(filters),
+            }))
+            .await?;
+
+        Ok(response
+            .volumes
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|volume| {
+                let project = volume.labels.get(COMPOSE_PROJECT_LABEL)?.clone();
+                Some(ComposeVolume {
+                    name: volume.name,
+                    project,
+                })
+            })
+            .collect())
+    }
+
+    /// Remove a volume
+    pub async fn remove_volume(&self, name: &str) -> Result<()> {
+        self.inner()
+            .remove_volume(name, Some(RemoveVolumeOptions { force: true }))
+            .await?;
+        Ok(())
+    }
+}
@@ -28,12 +28,30 @@ pub enum Error {
     #[error("Database error: {0}")]
     Database(#[from] tokio_postgres::Error),
 
+    #[error("State store error: {0}")]
+    Store(#[from] rusqlite::Error),
+
     #[error("Scratch '{0}' not found")]
     ScratchNotFound(String),
 
     #[error("Scratch '{0}' already exists")]
     ScratchAlreadyExists(String),
 
+    #[error("Scratch '{0}' is busy: {1} in progress")]
+    ScratchBusy(String, String),
+
+    #[error("Scratch '{0}' did not become healthy:\n{1}")]
+    ScratchUnhealthy(String, String),
+
+    #[error("{0} hook failed: {1}")]
+    HookFailed(String, String),
+
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("Snapshot '{0}' not found")]
+    SnapshotNotFound(String),
+
     #[error("Service '{0}' not found")]
     ServiceNotFound(String),
 
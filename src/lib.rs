@@ -12,6 +12,7 @@ pub mod error;
 pub mod nginx;
 pub mod scratch;
 pub mod services;
+pub mod store;
 pub mod ui;
 
 pub use config::Config;
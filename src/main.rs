@@ -10,6 +10,7 @@ mod error;
 mod nginx;
 mod scratch;
 mod services;
+mod store;
 
 pub mod api;
 pub mod ui;
@@ -37,21 +38,108 @@ async fn main() -> Result<()> {
             name,
             profile,
             template,
-        } => cli::commands::create(&branch, name, profile, template).await,
-        Commands::Update { name, restart } => cli::commands::update(&name, restart).await,
-        Commands::List { format } => cli::commands::list(format).await,
+            ttl,
+            env,
+            env_file,
+            description,
+            tags,
+            no_wait,
+        } => {
+            cli::commands::create(
+                &branch,
+                env,
+                env_file,
+                scratch::CreateScratchOptions {
+                    name,
+                    profile,
+                    template,
+                    ttl,
+                    description,
+                    tags: tags.into_iter().collect(),
+                    no_wait,
+                    ..Default::default()
+                },
+            )
+            .await
+        }
+        Commands::Clone {
+            source,
+            name,
+            branch,
+        } => cli::commands::clone(&source, &name, branch).await,
+        Commands::Update {
+            name,
+            restart,
+            rolling,
+            env,
+            env_file,
+        } => cli::commands::update(&name, restart, rolling, env, env_file).await,
+        Commands::List {
+            format,
+            owner,
+            tags,
+            status,
+            branch,
+        } => {
+            cli::commands::list(
+                format,
+                scratch::ScratchFilter {
+                    owner,
+                    tags,
+                    status,
+                    branch,
+                },
+            )
+            .await
+        }
         Commands::Start { name } => cli::commands::start(&name).await,
         Commands::Stop { name } => cli::commands::stop(&name).await,
         Commands::Restart { name } => cli::commands::restart(&name).await,
         Commands::Delete { name, force } => cli::commands::delete(&name, force).await,
+        Commands::Extend { name, duration } => cli::commands::extend(&name, &duration).await,
         Commands::Logs {
             name,
             service,
             follow,
             tail,
         } => cli::commands::logs(&name, service, follow, tail).await,
+        Commands::Stats { name, watch } => cli::commands::stats(name, watch).await,
+        Commands::Bulk {
+            action,
+            pattern,
+            status,
+            older_than,
+            branch_regex,
+            owner,
+            tags,
+            concurrency,
+            dry_run,
+            force,
+        } => {
+            cli::commands::bulk(
+                action,
+                scratch::BulkSelector {
+                    name: pattern,
+                    status,
+                    older_than,
+                    branch_regex,
+                    owner,
+                    tags,
+                },
+                concurrency,
+                dry_run,
+                force,
+            )
+            .await
+        }
+        Commands::Diff { name, all } => cli::commands::diff(name.as_deref(), all).await,
+        Commands::Gc { dry_run, force } => cli::commands::gc(dry_run, force).await,
         Commands::Serve { host, port } => cli::commands::serve(&host, port).await,
         Commands::Status { name } => cli::commands::status(&name).await,
+        Commands::Snapshot { action } => cli::commands::snapshot(action).await,
+        Commands::Db { action } => cli::commands::db(action).await,
+        Commands::Jobs { server, action } => cli::commands::jobs(server, action).await,
+        Commands::Template { action } => cli::commands::template(action).await,
         Commands::Nginx { action } => cli::commands::nginx(action).await,
         Commands::Services { action } => cli::commands::services(action).await,
         Commands::Config { action } => cli::commands::config(action).await,
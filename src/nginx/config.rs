@@ -1,5 +1,6 @@
 //! Nginx configuration generation
 
+use std::collections::HashMap;
 use std::fs;
 
 use crate::config::{Config, NginxRouting};
@@ -30,6 +31,10 @@ server {
 
     location / {
         set $upstream ${scratch}-{{ ingress_service }}:{{ upstream_port }};
+{% if server_upstream %}
+        mirror /_scratchpad_activity;
+        mirror_request_body off;
+{% endif %}
         proxy_pass http://$upstream;
         proxy_http_version 1.1;
         proxy_set_header Upgrade $http_upgrade;
@@ -46,9 +51,26 @@ server {
     }
     
     location @scratch_not_found {
+{% if server_upstream %}
+        # Let scratchpad wake the scratch if it was stopped
+        proxy_pass http://{{ server_upstream }}/wake/$scratch;
+        proxy_set_header Host $host;
+{% else %}
         return 404 'Scratch "$scratch" not found or not running\n';
         add_header Content-Type text/plain;
+{% endif %}
     }
+{% if server_upstream %}
+
+    # Report request activity to scratchpad (used for idle auto-stop)
+    location = /_scratchpad_activity {
+        internal;
+        proxy_method POST;
+        proxy_pass_request_body off;
+        proxy_set_header Content-Length "";
+        proxy_pass http://{{ server_upstream }}/api/scratches/$scratch/activity;
+    }
+{% endif %}
 }
 
 {% else %}
@@ -60,6 +82,10 @@ server {
     # Extract scratch name from path and proxy
     location ~ ^/(?<scratch>[^/]+)(?:/(?<path>.*))?$ {
         set $upstream ${scratch}-{{ ingress_service }}:{{ upstream_port }};
+{% if server_upstream %}
+        mirror /_scratchpad_activity;
+        mirror_request_body off;
+{% endif %}
         
         # Rewrite to remove scratch prefix
         rewrite ^/[^/]+/?(.*)$ /$1 break;
@@ -81,9 +107,26 @@ server {
     }
     
     location @scratch_not_found {
+{% if server_upstream %}
+        # Let scratchpad wake the scratch if it was stopped
+        proxy_pass http://{{ server_upstream }}/wake/$scratch;
+        proxy_set_header Host $host;
+{% else %}
         return 404 'Scratch not found or not running\n';
         add_header Content-Type text/plain;
+{% endif %}
     }
+{% if server_upstream %}
+
+    # Report request activity to scratchpad (used for idle auto-stop)
+    location = /_scratchpad_activity {
+        internal;
+        proxy_method POST;
+        proxy_pass_request_body off;
+        proxy_set_header Content-Length "";
+        proxy_pass http://{{ server_upstream }}/api/scratches/$scratch/activity;
+    }
+{% endif %}
     
     location = / {
         return 200 'Scratchpad is running. Access scratches at: {{ domain }}/<scratch-name>/\n';
@@ -104,7 +147,7 @@ const NGINX_STATIC_TEMPLATE: &str = r#"
 
 {% for scratch in scratches %}
 upstream scratch_{{ scratch.name }} {
-    server {{ scratch.name }}-{{ ingress_service }}:{{ upstream_port }};
+    server {{ scratch.host }}:{{ upstream_port }};
 }
 
 {% endfor %}
@@ -116,6 +159,10 @@ server {
     server_name {{ scratch.name }}.{{ domain }};
 
     location / {
+{% if server_upstream %}
+        mirror /_scratchpad_activity;
+        mirror_request_body off;
+{% endif %}
         proxy_pass http://scratch_{{ scratch.name }};
         proxy_http_version 1.1;
         proxy_set_header Upgrade $http_upgrade;
@@ -125,7 +172,29 @@ server {
         proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
         proxy_set_header X-Forwarded-Proto $scheme;
         proxy_cache_bypass $http_upgrade;
+{% if server_upstream %}
+
+        # Let scratchpad wake the scratch if it was stopped
+        proxy_intercept_errors on;
+        error_page 502 503 504 = @scratch_stopped;
+{% endif %}
     }
+{% if server_upstream %}
+
+    location @scratch_stopped {
+        proxy_pass http://{{ server_upstream }}/wake/{{ scratch.name }};
+        proxy_set_header Host $host;
+    }
+
+    # Report request activity to scratchpad (used for idle auto-stop)
+    location = /_scratchpad_activity {
+        internal;
+        proxy_method POST;
+        proxy_pass_request_body off;
+        proxy_set_header Content-Length "";
+        proxy_pass http://{{ server_upstream }}/api/scratches/{{ scratch.name }}/activity;
+    }
+{% endif %}
 }
 
 {% endfor %}
@@ -136,6 +205,10 @@ server {
 
 {% for scratch in scratches %}
     location /{{ scratch.name }}/ {
+{% if server_upstream %}
+        mirror /_scratchpad_activity/{{ scratch.name }};
+        mirror_request_body off;
+{% endif %}
         rewrite ^/{{ scratch.name }}/(.*) /$1 break;
         proxy_pass http://scratch_{{ scratch.name }};
         proxy_http_version 1.1;
@@ -146,7 +219,29 @@ server {
         proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
         proxy_set_header X-Forwarded-Proto $scheme;
         proxy_cache_bypass $http_upgrade;
+{% if server_upstream %}
+
+        # Let scratchpad wake the scratch if it was stopped
+        proxy_intercept_errors on;
+        error_page 502 503 504 = @scratch_{{ scratch.name }}_stopped;
+{% endif %}
     }
+{% if server_upstream %}
+
+    location @scratch_{{ scratch.name }}_stopped {
+        proxy_pass http://{{ server_upstream }}/wake/{{ scratch.name }};
+        proxy_set_header Host $host;
+    }
+
+    # Report request activity to scratchpad (used for idle auto-stop)
+    location = /_scratchpad_activity/{{ scratch.name }} {
+        internal;
+        proxy_method POST;
+        proxy_pass_request_body off;
+        proxy_set_header Content-Length "";
+        proxy_pass http://{{ server_upstream }}/api/scratches/{{ scratch.name }}/activity;
+    }
+{% endif %}
 
 {% endfor %}
 }
@@ -155,6 +250,20 @@ server {
 
 /// Regenerate the nginx configuration file
 pub async fn regenerate_config(config: &Config, docker: &DockerClient) -> Result<()> {
+    regenerate_config_with_upstreams(config, docker, &HashMap::new()).await
+}
+
+/// Regenerate the nginx configuration, routing some scratches to other hosts
+///
+/// `upstreams` maps a scratch name to the container that should serve it
+/// instead of `<scratch>-<ingress_service>`. Only static configs route by
+/// container name; dynamic configs resolve the ingress through Docker DNS and
+/// ignore it.
+pub async fn regenerate_config_with_upstreams(
+    config: &Config,
+    docker: &DockerClient,
+    upstreams: &HashMap<String, String>,
+) -> Result<()> {
     if !config.nginx.enabled {
         return Ok(());
     }
@@ -193,6 +302,7 @@ pub async fn regenerate_config(config: &Config, docker: &DockerClient) -> Result
             domain_escaped => domain_escaped,
             ingress_service => ingress_service,
             upstream_port => upstream_port,
+            server_upstream => config.nginx.server_upstream,
             routing => match config.nginx.routing {
                 NginxRouting::Subdomain => "subdomain",
                 NginxRouting::Path => "path",
@@ -200,7 +310,18 @@ pub async fn regenerate_config(config: &Config, docker: &DockerClient) -> Result
         })?
     } else {
         // Static config - needs scratch list
-        let scratches = scratch::list_scratches(config, docker).await?;
+        let scratches: Vec<_> = scratch::list_scratches(config, docker)
+            .await?
+            .into_iter()
+            .filter(|s| s.status != "failed")
+            .map(|s| {
+                let host = upstreams
+                    .get(&s.name)
+                    .cloned()
+                    .unwrap_or_else(|| format!("{}-{}", s.name, ingress_service));
+                context! { name => s.name, host => host }
+            })
+            .collect();
 
         env.add_template("nginx", NGINX_STATIC_TEMPLATE)?;
         let template = env.get_template("nginx")?;
@@ -210,6 +331,7 @@ pub async fn regenerate_config(config: &Config, docker: &DockerClient) -> Result
             domain => config.nginx.domain,
             ingress_service => ingress_service,
             upstream_port => upstream_port,
+            server_upstream => config.nginx.server_upstream,
             routing => match config.nginx.routing {
                 NginxRouting::Subdomain => "subdomain",
                 NginxRouting::Path => "path",
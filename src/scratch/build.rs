@@ -0,0 +1,299 @@
+//! Building per-scratch service images from git
+//!
+//! Services with a `build` section don't need a pushed image. On create, the
+//! branch the scratch runs for the service's repo is checked out into
+//! `<releases_dir>/<scratch>/src/<service>`, the files tracked at that commit
+//! are sent to Docker as the build context, and the image is tagged
+//! `<scratch>-<service>:<sha>` so the compose file can refer to it.
+
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::process::Command;
+
+use crate::config::{Config, ServiceBuild};
+use crate::docker::{ComposeBuild, DockerClient};
+use crate::error::{Error, Result};
+
+use super::{report, Scratch};
+
+/// Directory of a scratch that services are checked out into
+pub const SOURCE_DIR: &str = "src";
+
+/// Length of the commit hash in built image tags
+const SHA_LENGTH: usize = 12;
+
+/// Build the images of a scratch's services that are built from source
+///
+/// Built images are recorded in `scratch.images` as they finish. An image
+/// that already exists for the checked out commit is reused.
+pub async fn build_scratch_images(
+    config: &Config,
+    docker: &DockerClient,
+    scratch: &mut Scratch,
+    scratch_dir: &Path,
+) -> Result<()> {
+    for service_name in scratch.services.clone() {
+        let Some(service) = config.get_service(&service_name) else {
+            continue;
+        };
+        let Some(build) = service.build.as_ref().filter(|_| !service.shared) else {
+            continue;
+        };
+
+        let branch = scratch.branch_for(service.repo.as_deref()).to_string();
+        let source = scratch_dir.join(SOURCE_DIR).join(&service_name);
+        report(
+            "checkout",
+            format!("Checking out {} for {}", branch, service_name),
+        );
+        let sha = checkout(&build.git, &branch, &source).await?;
+
+        let tag = format!("{}:{}", image_name(&scratch.name, &service_name), sha);
+        if docker.image_exists(&tag).await {
+            tracing::debug!("Image {} already exists, not building it", tag);
+        } else {
+            tracing::info!("Building image {}", tag);
+            report("build", format!("Building {}", tag));
+            let context = archive(&source, build.context.as_deref()).await?;
+            let prefix = &config.docker.label_prefix;
+            let labels = HashMap::from([
+                (format!("{}.scratch", prefix), scratch.name.clone()),
+                (format!("{}.service", prefix), service_name.clone()),
+            ]);
+            docker
+                .build_image(&tag, build, labels, context, |line| {
+                    tracing::info!("[{}] {}", service_name, line);
+                    report("build", line);
+                })
+                .await?;
+        }
+
+        scratch.images.insert(service_name, tag);
+    }
+
+    Ok(())
+}
+
+/// Remove the images built for a scratch
+///
+/// Only tags this scratch built are removed. Pulled images can be shared with
+/// other scratches, and Docker refuses to remove an image a container still
+/// uses, which is left in place.
+pub async fn remove_scratch_images(
+    config: &Config,
+    docker: &DockerClient,
+    scratch: &Scratch,
+) -> Result<()> {
+    for image in built_images(config, scratch) {
+        if docker.image_exists(image).await {
+            tracing::debug!("Removing image {}", image);
+            docker.remove_image(image).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The images in `scratch.images` that were built for the scratch
+fn built_images<'a>(config: &Config, scratch: &'a Scratch) -> Vec<&'a String> {
+    scratch
+        .images
+        .iter()
+        .filter(|(service_name, image)| {
+            let built = config
+                .get_service(service_name)
+                .is_some_and(|service| service.build.is_some() && !service.shared);
+            let prefix = format!("{}:", image_name(&scratch.name, service_name));
+            built && image.starts_with(&prefix)
+        })
+        .map(|(_, image)| image)
+        .collect()
+}
+
+/// Name (without tag) of the image built for a scratch's service
+pub fn image_name(scratch: &str, service: &str) -> String {
+    format!("{}-{}", scratch, service)
+}
+
+/// The compose `build` section for a service built from source
+///
+/// Compose only uses it when the tagged image has gone missing, e.g. after
+/// an image prune, to build it again from the checked out sources.
+pub(super) fn compose_build(service: &str, build: &ServiceBuild) -> ComposeBuild {
+    let mut context = format!("./{}/{}", SOURCE_DIR, service);
+    if let Some(subdir) = build.context.as_deref().map(|c| c.trim_matches('/')) {
+        if !subdir.is_empty() && subdir != "." {
+            context = format!("{}/{}", context, subdir);
+        }
+    }
+
+    ComposeBuild::Full {
+        context,
+        dockerfile: Some(build.dockerfile.clone()),
+        args: build.args.clone(),
+        target: build.target.clone(),
+    }
+}
+
+/// Check out a branch of a repository into `dir`, returning its commit
+///
+/// The repository is cloned the first time and fetched after that, so
+/// rebuilding a scratch only transfers new commits.
+async fn checkout(git: &str, branch: &str, dir: &Path) -> Result<String> {
+    // Branches come from requests and webhooks, so make sure git can't take
+    // one for an option
+    let args = ["check-ref-format", "--branch", branch];
+    let output = Command::new("git").args(args).output().await?;
+    if !output.status.success() {
+        return Err(Error::Other(format!("Invalid branch name: {}", branch)));
+    }
+
+    if dir.join(".git").exists() {
+        git_output(dir, &["fetch", "--depth", "1", "--", "origin", branch]).await?;
+        git_output(dir, &["checkout", "--force", "--detach", "FETCH_HEAD"]).await?;
+    } else {
+        if let Some(parent) = dir.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let target = dir.to_string_lossy();
+        let args = [
+            "clone", "--depth", "1", "--branch", branch, "--", git, &target,
+        ];
+        let output = Command::new("git").args(args).output().await?;
+        if !output.status.success() {
+            return Err(git_error(&args, &output.stderr));
+        }
+    }
+
+    let sha = git_output(dir, &["rev-parse", "HEAD"]).await?;
+    let sha = String::from_utf8_lossy(&sha);
+    Ok(sha.trim().chars().take(SHA_LENGTH).collect())
+}
+
+/// Tar archive of the files tracked at `HEAD`, rooted at `context`
+async fn archive(dir: &Path, context: Option<&str>) -> Result<Vec<u8>> {
+    let tree = match context.map(|c| c.trim_matches('/')) {
+        Some(subdir) if !subdir.is_empty() && subdir != "." => format!("HEAD:{}", subdir),
+        _ => "HEAD".to_string(),
+    };
+    git_output(dir, &["archive", "--format=tar", &tree]).await
+}
+
+/// Run git in a repository and return what it printed
+async fn git_output(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(git_error(args, &output.stderr));
+    }
+    Ok(output.stdout)
+}
+
+fn git_error(args: &[&str], stderr: &[u8]) -> Error {
+    Error::Other(format!(
+        "git {} failed: {}",
+        args[0],
+        String::from_utf8_lossy(stderr).trim()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn git(dir: &Path, args: &[&str]) {
+        git_output(dir, args).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_checkout_and_archive() {
+        let temp = tempfile::tempdir().unwrap();
+        let repo = temp.path().join("repo");
+        std::fs::create_dir_all(repo.join("app")).unwrap();
+        std::fs::write(repo.join("app/Dockerfile"), "FROM scratch\n").unwrap();
+        git(&repo, &["init", "--quiet", "--initial-branch", "main"]).await;
+        git(&repo, &["add", "."]).await;
+        git(
+            &repo,
+            &[
+                "-c",
+                "user.name=t",
+                "-c",
+                "user.email=t@t",
+                "commit",
+                "-qm",
+                "init",
+            ],
+        )
+        .await;
+
+        let source = temp.path().join("scratch/src/api");
+        let sha = checkout(repo.to_str().unwrap(), "main", &source)
+            .await
+            .unwrap();
+        assert_eq!(sha.len(), SHA_LENGTH);
+        assert!(source.join("app/Dockerfile").exists());
+
+        // A second checkout fetches into the existing clone
+        let again = checkout(repo.to_str().unwrap(), "main", &source)
+            .await
+            .unwrap();
+        assert_eq!(again, sha);
+        assert!(checkout(repo.to_str().unwrap(), "missing", &source)
+            .await
+            .is_err());
+        assert!(
+            checkout(repo.to_str().unwrap(), "--upload-pack=touch", &source)
+                .await
+                .is_err()
+        );
+
+        let tar = archive(&source, Some("app/")).await.unwrap();
+        // The first entry of the archive is the Dockerfile, at the root
+        assert!(tar.starts_with(b"Dockerfile\0"));
+    }
+
+    #[test]
+    fn test_compose_build() {
+        let build: ServiceBuild =
+            toml::from_str("git = \"../api\"\ncontext = \"app\"\ntarget = \"dev\"").unwrap();
+        match compose_build("api", &build) {
+            ComposeBuild::Full {
+                context,
+                dockerfile,
+                target,
+                ..
+            } => {
+                assert_eq!(context, "./src/api/app");
+                assert_eq!(dockerfile.as_deref(), Some("Dockerfile"));
+                assert_eq!(target.as_deref(), Some("dev"));
+            }
+            ComposeBuild::Simple(_) => panic!("expected a full build section"),
+        }
+    }
+
+    #[test]
+    fn test_built_images_skip_pulled_images() {
+        let config: Config = toml::from_str(
+            "[services.api]\nimage = \"myorg/api:main\"\n\
+             [services.web]\nimage = \"web\"\nbuild = { git = \"../web\" }\n\
+             [services.redis]\nimage = \"redis:7\"\nshared = true",
+        )
+        .unwrap();
+        let mut scratch = Scratch::new("feat-x".into(), "main".into(), "default".into());
+        // The pulled image is shared with every other scratch on main
+        scratch.images.insert("api".into(), "myorg/api:main".into());
+        scratch
+            .images
+            .insert("web".into(), "feat-x-web:0123456789ab".into());
+        scratch.images.insert("redis".into(), "redis:7".into());
+
+        assert_eq!(built_images(&config, &scratch), ["feat-x-web:0123456789ab"]);
+
+        // A built service whose image was recorded some other way is kept
+        scratch.images.insert("web".into(), "web:latest".into());
+        assert!(built_images(&config, &scratch).is_empty());
+    }
+}
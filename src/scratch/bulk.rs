@@ -0,0 +1,304 @@
+//! Running an operation on many scratches at once
+//!
+//! Scratches are picked with a [`BulkSelector`] (a name glob, a status, a
+//! minimum age, a branch regex, and the owner/tag filters of
+//! [`ScratchFilter`]) and the operation runs on a few of them at a time.
+//! Each scratch takes its own lock, so one that's busy fails on its own
+//! without stopping the rest.
+
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, StreamExt};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::docker::DockerClient;
+use crate::error::{Error, Result};
+
+use super::{
+    delete_scratch, list_scratches, parse_duration, report, restart_scratch, start_scratch,
+    stop_scratch, update_scratch, ScratchFilter, ScratchStatus,
+};
+
+/// How many scratches are worked on at once unless told otherwise
+pub const DEFAULT_BULK_CONCURRENCY: usize = 4;
+
+/// An operation that can be run on many scratches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkAction {
+    Delete,
+    Stop,
+    Start,
+    Restart,
+    Update,
+}
+
+impl BulkAction {
+    /// Get the action as a string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BulkAction::Delete => "delete",
+            BulkAction::Stop => "stop",
+            BulkAction::Start => "start",
+            BulkAction::Restart => "restart",
+            BulkAction::Update => "update",
+        }
+    }
+
+    async fn run(self, config: &Config, docker: &DockerClient, name: &str) -> Result<()> {
+        match self {
+            BulkAction::Delete => delete_scratch(config, docker, name, true).await,
+            BulkAction::Stop => stop_scratch(config, docker, name).await,
+            BulkAction::Start => start_scratch(config, docker, name).await,
+            BulkAction::Restart => restart_scratch(config, docker, name).await,
+            BulkAction::Update => update_scratch(config, docker, name).await,
+        }
+    }
+}
+
+impl std::fmt::Display for BulkAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Which scratches a bulk operation applies to
+///
+/// Unset criteria match everything, but at least one must be set so a
+/// missing argument can't turn into "every scratch"; use a `*` glob for that.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BulkSelector {
+    /// Glob matched against the scratch name (`*` and `?` wildcards)
+    pub name: Option<String>,
+    pub status: Option<String>,
+    /// Minimum age, e.g. "14d"
+    pub older_than: Option<String>,
+    /// Regex searched for in the branch
+    pub branch_regex: Option<String>,
+    pub owner: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl BulkSelector {
+    /// Whether no criteria are set
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.status.is_none()
+            && self.older_than.is_none()
+            && self.branch_regex.is_none()
+            && self.owner.is_none()
+            && self.tags.is_empty()
+    }
+
+    /// Keep the scratches matching every criterion, as of `now`
+    pub fn select(
+        &self,
+        scratches: Vec<ScratchStatus>,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<ScratchStatus>> {
+        if self.is_empty() {
+            return Err(Error::Other(
+                "No scratches selected: give a name pattern or a filter ('*' matches all)"
+                    .to_string(),
+            ));
+        }
+
+        let name = self.name.as_deref().map(glob_regex).transpose()?;
+        let branch = self
+            .branch_regex
+            .as_deref()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .map_err(|e| Error::Other(format!("Invalid branch regex: {}", e)))
+            })
+            .transpose()?;
+        let created_before = self
+            .older_than
+            .as_deref()
+            .map(|age| parse_duration(age).map(|age| now - age))
+            .transpose()?;
+        let filter = ScratchFilter {
+            owner: self.owner.clone(),
+            tags: self.tags.clone(),
+            status: self.status.clone(),
+            branch: None,
+        };
+
+        Ok(scratches
+            .into_iter()
+            .filter(|s| filter.matches(s))
+            .filter(|s| name.as_ref().is_none_or(|re| re.is_match(&s.name)))
+            .filter(|s| branch.as_ref().is_none_or(|re| re.is_match(&s.branch)))
+            .filter(|s| {
+                created_before
+                    .is_none_or(|before| s.created_at.is_some_and(|created| created <= before))
+            })
+            .collect())
+    }
+}
+
+/// Outcome of a bulk operation on one scratch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkOutcome {
+    /// Selected, but nothing was run (dry run)
+    Matched,
+    Succeeded,
+    Failed,
+}
+
+/// Result of a bulk operation on one scratch
+#[derive(Debug, Clone, Serialize)]
+pub struct BulkResult {
+    pub scratch: String,
+    pub outcome: BulkOutcome,
+    pub error: Option<String>,
+}
+
+/// Find the scratches a selector matches
+pub async fn select_scratches(
+    config: &Config,
+    docker: &DockerClient,
+    selector: &BulkSelector,
+) -> Result<Vec<String>> {
+    let scratches = list_scratches(config, docker).await?;
+    Ok(selector
+        .select(scratches, Utc::now())?
+        .into_iter()
+        .map(|s| s.name)
+        .collect())
+}
+
+/// Run an action on scratches, `concurrency` at a time
+///
+/// Results come back in the order the scratches were given.
+pub async fn run_bulk(
+    config: &Config,
+    docker: &DockerClient,
+    action: BulkAction,
+    names: &[String],
+    concurrency: usize,
+) -> Vec<BulkResult> {
+    // Owned names keep the futures free of higher-ranked lifetimes, which
+    // axum handlers need to be Send
+    stream::iter(names.to_vec())
+        .map(|name| async move {
+            let result = action.run(config, docker, &name).await;
+            match &result {
+                Ok(()) => report("bulk", format!("{} {}: succeeded", action, name)),
+                Err(e) => {
+                    tracing::warn!("Bulk {} of scratch '{}' failed: {}", action, name, e);
+                    report("bulk", format!("{} {}: {}", action, name, e));
+                }
+            }
+            BulkResult {
+                scratch: name,
+                outcome: if result.is_ok() {
+                    BulkOutcome::Succeeded
+                } else {
+                    BulkOutcome::Failed
+                },
+                error: result.err().map(|e| e.to_string()),
+            }
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Compile a name glob into an anchored regex
+fn glob_regex(glob: &str) -> Result<Regex> {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).map_err(|e| Error::Other(format!("Invalid name pattern: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn status(name: &str, branch: &str, state: &str, age_days: i64) -> ScratchStatus {
+        let mut scratch = ScratchStatus::new(name.to_string(), branch.to_string());
+        scratch.status = state.to_string();
+        scratch.created_at = Some(Utc::now() - Duration::days(age_days));
+        scratch
+    }
+
+    fn names(selector: &BulkSelector, scratches: &[ScratchStatus]) -> Vec<String> {
+        selector
+            .select(scratches.to_vec(), Utc::now())
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_select_scratches() {
+        let scratches = vec![
+            status("feature-login", "feature/login", "running", 20),
+            status("feature-cart", "feature/cart", "stopped", 3),
+            status("fix-123", "fix/123-crash", "stopped", 30),
+        ];
+
+        let by_glob = BulkSelector {
+            name: Some("feature-*".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&by_glob, &scratches),
+            ["feature-login", "feature-cart"]
+        );
+
+        let by_status_and_age = BulkSelector {
+            status: Some("stopped".to_string()),
+            older_than: Some("14d".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(names(&by_status_and_age, &scratches), ["fix-123"]);
+
+        let by_branch = BulkSelector {
+            branch_regex: Some(r"^fix/\d+".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(names(&by_branch, &scratches), ["fix-123"]);
+
+        let everything = BulkSelector {
+            name: Some("*".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(names(&everything, &scratches).len(), 3);
+    }
+
+    #[test]
+    fn test_select_requires_criteria() {
+        let scratches = vec![status("a", "a", "running", 1)];
+        assert!(BulkSelector::default()
+            .select(scratches.clone(), Utc::now())
+            .is_err());
+
+        let bad_regex = BulkSelector {
+            branch_regex: Some("(".to_string()),
+            ..Default::default()
+        };
+        assert!(bad_regex.select(scratches, Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_glob_regex() {
+        let re = glob_regex("feat?re-*.x").unwrap();
+        assert!(re.is_match("feature-a.x"));
+        assert!(!re.is_match("feature-a-x"));
+        assert!(!re.is_match("my-feature-a.x"));
+    }
+}
@@ -0,0 +1,101 @@
+//! Drift between a scratch's compose file and the current config
+//!
+//! A scratch's `compose.yml` is rendered when it's created or updated, so
+//! later edits to `scratchpad.toml` or the templates only reach it on the next
+//! `update`. Rendering it again and diffing against the saved file shows
+//! which scratches are outdated, and how.
+
+use serde::Serialize;
+
+use crate::config::{Config, ScratchConfig, ScratchState};
+use crate::docker::{ComposeChange, ComposeFile};
+use crate::error::Result;
+use crate::store::StateStore;
+
+use super::{load_scratch_config, render_compose_file, Scratch};
+
+/// How a scratch's saved compose file differs from a fresh render
+#[derive(Debug, Clone, Serialize)]
+pub struct ScratchDrift {
+    pub scratch: String,
+    /// Changes `update` would make to `compose.yml`
+    pub changes: Vec<ComposeChange>,
+    /// Why the scratch couldn't be checked
+    pub error: Option<String>,
+}
+
+impl ScratchDrift {
+    /// Whether the scratch needs an update
+    pub fn is_outdated(&self) -> bool {
+        !self.changes.is_empty()
+    }
+}
+
+/// Compare a scratch's compose file with a fresh render
+pub fn scratch_drift(config: &Config, name: &str) -> Result<ScratchDrift> {
+    let scratch_config = load_scratch_config(config, name)?;
+    Ok(ScratchDrift {
+        scratch: name.to_string(),
+        changes: compose_drift(config, &scratch_config)?,
+        error: None,
+    })
+}
+
+/// Compare the compose file of every created scratch with a fresh render
+///
+/// Scratches that can't be rendered or read are reported with an error
+/// rather than failing the whole check.
+pub fn all_scratch_drift(config: &Config) -> Result<Vec<ScratchDrift>> {
+    let scratches = StateStore::for_config(config)?.list()?;
+    Ok(scratches
+        .iter()
+        .filter(|s| has_compose_file(s))
+        .map(|s| match compose_drift(config, s) {
+            Ok(changes) => ScratchDrift {
+                scratch: s.name.clone(),
+                changes,
+                error: None,
+            },
+            Err(e) => ScratchDrift {
+                scratch: s.name.clone(),
+                changes: Vec::new(),
+                error: Some(e.to_string()),
+            },
+        })
+        .collect())
+}
+
+/// Whether a scratch's compose file is out of date, for status listings
+///
+/// Errors count as up to date; `scratchpad diff` reports them.
+pub(super) fn is_outdated(config: &Config, scratch_config: &ScratchConfig) -> bool {
+    if !has_compose_file(scratch_config) {
+        return false;
+    }
+    match compose_drift(config, scratch_config) {
+        Ok(changes) => !changes.is_empty(),
+        Err(e) => {
+            tracing::debug!("Can't check drift of '{}': {}", scratch_config.name, e);
+            false
+        }
+    }
+}
+
+/// Scratches being created or rolled back don't have a compose file to compare
+fn has_compose_file(scratch_config: &ScratchConfig) -> bool {
+    matches!(
+        scratch_config.state,
+        ScratchState::Running | ScratchState::Stopped
+    )
+}
+
+fn compose_drift(config: &Config, scratch_config: &ScratchConfig) -> Result<Vec<ComposeChange>> {
+    let path = config
+        .server
+        .releases_dir
+        .join(&scratch_config.name)
+        .join("compose.yml");
+    let saved = ComposeFile::load(&path)?;
+    let rendered = render_compose_file(config, &Scratch::from(scratch_config.clone()))?;
+    Ok(saved.diff(&rendered))
+}
@@ -0,0 +1,238 @@
+//! Scratch expiry (TTL) handling and the expired scratch reaper
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::Config;
+use crate::docker::DockerClient;
+use crate::error::{Error, Result};
+use crate::store::StateStore;
+
+use super::{delete_scratch, load_scratch_config, save_scratch_config, ScratchLock};
+
+/// Parse a human friendly duration such as "30m", "12h", "7d" or "1d12h"
+///
+/// Supported units are `s`, `m`, `h`, `d` and `w`. A bare number is treated as seconds.
+/// Durations must be positive.
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(Error::Config("Duration cannot be empty".to_string()));
+    }
+
+    let total = match input.parse::<i64>() {
+        Ok(secs) => Duration::try_seconds(secs).ok_or_else(|| too_long(input))?,
+        Err(_) => parse_units(input)?,
+    };
+    if total <= Duration::zero() {
+        return Err(Error::Config(format!(
+            "Duration must be positive: {}",
+            input
+        )));
+    }
+
+    Ok(total)
+}
+
+/// Sum the parts of a duration with units, e.g. "1d12h"
+fn parse_units(input: &str) -> Result<Duration> {
+    let mut total = Duration::zero();
+    let mut number = String::new();
+
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        if number.is_empty() {
+            return Err(Error::Config(format!("Invalid duration: {}", input)));
+        }
+        let value: i64 = number.parse().map_err(|_| too_long(input))?;
+        number.clear();
+
+        let part = match c.to_ascii_lowercase() {
+            's' => Duration::try_seconds(value),
+            'm' => Duration::try_minutes(value),
+            'h' => Duration::try_hours(value),
+            'd' => Duration::try_days(value),
+            'w' => Duration::try_weeks(value),
+            _ => return Err(Error::Config(format!("Invalid duration: {}", input))),
+        };
+        total = part
+            .and_then(|part| total.checked_add(&part))
+            .ok_or_else(|| too_long(input))?;
+    }
+
+    // Trailing digits without a unit (e.g. "1h30")
+    if !number.is_empty() {
+        return Err(Error::Config(format!("Invalid duration: {}", input)));
+    }
+
+    Ok(total)
+}
+
+fn too_long(input: &str) -> Error {
+    Error::Config(format!("Duration is too long: {}", input))
+}
+
+/// Resolve the TTL for a new scratch
+///
+/// Precedence is: explicit override, then profile, then global default.
+/// A value of "never" disables expiry.
+pub fn resolve_ttl(
+    config: &Config,
+    profile: Option<&str>,
+    ttl: Option<&str>,
+) -> Result<Option<Duration>> {
+    let ttl = ttl.map(|t| t.to_string()).or_else(|| {
+        profile
+            .and_then(|p| config.get_profile(p))
+            .and_then(|p| p.ttl.clone())
+            .or_else(|| config.scratch.ttl.clone())
+    });
+
+    match ttl.as_deref() {
+        None | Some("never") => Ok(None),
+        Some(value) => parse_duration(value).map(Some),
+    }
+}
+
+/// The expiry of a scratch that lives for `ttl` from `start`
+pub fn expires_after(start: DateTime<Utc>, ttl: Duration) -> Result<DateTime<Utc>> {
+    start
+        .checked_add_signed(ttl)
+        .ok_or_else(|| Error::Config(format!("Expiry is too far in the future: {}", ttl)))
+}
+
+/// Push the expiry of a scratch out by the given duration
+///
+/// The new deadline is calculated from the current expiry, or from now if
+/// the scratch has already expired or never had one.
+pub fn extend_scratch(config: &Config, name: &str, duration: &str) -> Result<DateTime<Utc>> {
+    let extension = parse_duration(duration)?;
+    let _lock = ScratchLock::acquire(config, name, "extend")?;
+    let mut scratch_config = load_scratch_config(config, name)?;
+
+    let now = Utc::now();
+    let base = scratch_config
+        .expires_at
+        .filter(|expires_at| *expires_at > now)
+        .unwrap_or(now);
+    let expires_at = expires_after(base, extension)?;
+
+    scratch_config.expires_at = Some(expires_at);
+    save_scratch_config(config, &scratch_config)?;
+
+    tracing::info!("Extended scratch '{}' until {}", name, expires_at);
+    Ok(expires_at)
+}
+
+/// Find scratches whose expiry has passed
+pub fn find_expired_scratches(config: &Config) -> Result<Vec<String>> {
+    let now = Utc::now();
+
+    Ok(StateStore::for_config(config)?
+        .list()?
+        .into_iter()
+        .filter(|s| s.expires_at.is_some_and(|e| e <= now))
+        .map(|s| s.name)
+        .collect())
+}
+
+/// Delete every scratch whose expiry has passed, returning the deleted names
+pub async fn reap_expired_scratches(config: &Config, docker: &DockerClient) -> Result<Vec<String>> {
+    let mut reaped = Vec::new();
+
+    for name in find_expired_scratches(config)? {
+        tracing::info!("Scratch '{}' has expired, deleting", name);
+        match delete_scratch(config, docker, &name, true).await {
+            Ok(()) => reaped.push(name),
+            Err(e) => tracing::error!("Failed to delete expired scratch {}: {}", name, e),
+        }
+    }
+
+    Ok(reaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::seconds(90));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::minutes(30));
+        assert_eq!(parse_duration("12h").unwrap(), Duration::hours(12));
+        assert_eq!(parse_duration("7d").unwrap(), Duration::days(7));
+        assert_eq!(parse_duration("2w").unwrap(), Duration::weeks(2));
+        assert_eq!(
+            parse_duration("1d12h").unwrap(),
+            Duration::days(1) + Duration::hours(12)
+        );
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("abc").is_err());
+        assert!(parse_duration("1h30").is_err());
+        assert!(parse_duration("-5").is_err());
+        assert!(parse_duration("0").is_err());
+        assert!(parse_duration("0h").is_err());
+        assert!(parse_duration("5y").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration_overflow() {
+        for input in [
+            "9223372036854775807",
+            "9223372036854775807w",
+            "99999999999999999999s",
+            "15250284420311w",
+            "106751991167d106751991167d",
+        ] {
+            assert!(
+                matches!(parse_duration(input), Err(Error::Config(_))),
+                "{} should be rejected",
+                input
+            );
+        }
+
+        // Long enough to parse, but not to add to a date
+        let ttl = parse_duration("15250284w").unwrap();
+        assert!(matches!(
+            expires_after(Utc::now(), ttl),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_ttl_precedence() {
+        let mut config = Config::default();
+        assert_eq!(resolve_ttl(&config, None, None).unwrap(), None);
+
+        config.scratch.ttl = Some("7d".to_string());
+        config.scratch.profiles.insert(
+            "short".to_string(),
+            crate::config::ScratchProfile {
+                template: None,
+                services: Vec::new(),
+                env: Default::default(),
+                ttl: Some("1d".to_string()),
+                resources: Default::default(),
+                hooks: Default::default(),
+                seed_from: None,
+            },
+        );
+
+        assert_eq!(
+            resolve_ttl(&config, None, None).unwrap(),
+            Some(Duration::days(7))
+        );
+        assert_eq!(
+            resolve_ttl(&config, Some("short"), None).unwrap(),
+            Some(Duration::days(1))
+        );
+        assert_eq!(
+            resolve_ttl(&config, Some("short"), Some("2h")).unwrap(),
+            Some(Duration::hours(2))
+        );
+        assert_eq!(resolve_ttl(&config, None, Some("never")).unwrap(), None);
+    }
+}
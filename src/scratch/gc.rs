@@ -0,0 +1,428 @@
+//! Garbage collection of resources left behind by scratches
+//!
+//! A scratch is live when it has both a state store record and a directory
+//! in `releases_dir`. Everything else that looks like it belongs to a
+//! scratch is an orphan:
+//!
+//! - containers labeled `<prefix>.scratch=<name>`, or started by compose from
+//!   `releases_dir/<name>`, for a scratch that isn't live
+//! - compose volumes of the project of such a scratch
+//! - `scratch_*` postgres databases no live scratch records as its own,
+//!   other than template databases and those named by `seed_from`
+//! - directories in `releases_dir` with no state store record
+//! - state store records whose directory is gone
+//!
+//! Scratches that are locked by a running operation are left alone, since a
+//! scratch being created has a directory before it has a record.
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::docker::{ComposeVolume, ContainerStatus, DockerClient, COMPOSE_WORKING_DIR_LABEL};
+use crate::error::Result;
+use crate::services;
+use crate::store::StateStore;
+
+use super::ScratchLock;
+
+/// Prefix of the postgres databases created for scratches
+const DATABASE_PREFIX: &str = "scratch_";
+
+/// Kind of resource an orphan is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanKind {
+    Container,
+    Volume,
+    Database,
+    Directory,
+    Record,
+}
+
+impl std::fmt::Display for OrphanKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            OrphanKind::Container => "container",
+            OrphanKind::Volume => "volume",
+            OrphanKind::Database => "database",
+            OrphanKind::Directory => "directory",
+            OrphanKind::Record => "record",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+/// A resource that belongs to a scratch that no longer exists
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Orphan {
+    pub kind: OrphanKind,
+    /// Container name, volume name, database name or scratch name
+    pub name: String,
+    /// The scratch it belonged to
+    pub scratch: String,
+    /// Why it is considered orphaned
+    pub reason: String,
+    /// Docker ID, for containers
+    #[serde(skip)]
+    id: Option<String>,
+}
+
+/// Outcome of a garbage collection run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GcReport {
+    pub dry_run: bool,
+    /// Orphans found (and removed, unless this was a dry run)
+    pub orphans: Vec<Orphan>,
+    /// Orphans that couldn't be removed, and sources that couldn't be checked
+    pub errors: Vec<String>,
+}
+
+/// Everything that might belong to a scratch
+#[derive(Debug, Default)]
+struct Inventory {
+    records: BTreeSet<String>,
+    directories: BTreeSet<String>,
+    /// Containers with the scratch they belong to
+    containers: Vec<(ContainerStatus, String)>,
+    volumes: Vec<ComposeVolume>,
+    databases: Vec<String>,
+    /// Databases recorded by live scratches
+    live_databases: BTreeSet<String>,
+}
+
+/// Find orphaned resources, removing them unless `dry_run` is set
+pub async fn collect_garbage(
+    config: &Config,
+    docker: &DockerClient,
+    dry_run: bool,
+) -> Result<GcReport> {
+    let mut report = find_orphans(config, docker).await?;
+    if !dry_run {
+        let errors = remove_orphans(config, docker, &report.orphans).await;
+        report.errors.extend(errors);
+    }
+    report.dry_run = dry_run;
+    Ok(report)
+}
+
+/// Find orphaned resources without removing anything
+pub async fn find_orphans(config: &Config, docker: &DockerClient) -> Result<GcReport> {
+    let mut report = GcReport {
+        dry_run: true,
+        ..Default::default()
+    };
+    let store = StateStore::for_config(config)?;
+    let records = store.list()?;
+    let directories = list_directories(&config.server.releases_dir)?;
+
+    let live: BTreeSet<&str> = records
+        .iter()
+        .map(|r| r.name.as_str())
+        .filter(|name| directories.contains(*name))
+        .collect();
+    let live_databases = records
+        .iter()
+        .filter(|r| live.contains(r.name.as_str()))
+        .flat_map(|r| r.databases.values().flatten().cloned())
+        .chain(services::seed_databases(config))
+        .collect();
+
+    let scratch_label = format!("{}.scratch", config.docker.label_prefix);
+    let mut containers: Vec<(ContainerStatus, String)> = Vec::new();
+    for container in docker.list_scratch_containers(None).await? {
+        if let Some(scratch) = container.labels.get(&scratch_label).cloned() {
+            containers.push((container, scratch));
+        }
+    }
+    for container in docker.list_compose_containers().await? {
+        if containers.iter().any(|(c, _)| c.id == container.id) {
+            continue;
+        }
+        let scratch = container
+            .labels
+            .get(COMPOSE_WORKING_DIR_LABEL)
+            .map(Path::new)
+            .filter(|dir| dir.parent() == Some(config.server.releases_dir.as_path()))
+            .and_then(|dir| dir.file_name())
+            .map(|name| name.to_string_lossy().to_string());
+        if let Some(scratch) = scratch {
+            containers.push((container, scratch));
+        }
+    }
+
+    // Postgres may not be configured or running; the rest is still worth doing
+    let databases = if config.get_service("postgres").is_some() {
+        match services::list_databases(config).await {
+            Ok(databases) => databases,
+            Err(e) => {
+                report
+                    .errors
+                    .push(format!("Skipped databases: can't list them: {}", e));
+                Vec::new()
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    let inventory = Inventory {
+        records: records.iter().map(|r| r.name.clone()).collect(),
+        directories,
+        containers,
+        volumes: docker.list_compose_volumes().await?,
+        databases,
+        live_databases,
+    };
+
+    report.orphans = orphans_in(&inventory)
+        .into_iter()
+        .filter(|orphan| ScratchLock::check(config, &orphan.scratch).is_ok())
+        .collect();
+    Ok(report)
+}
+
+/// Remove orphans, returning a description of each one that couldn't be
+///
+/// Containers go first so nothing is still using the volumes, databases and
+/// directories removed after them.
+pub async fn remove_orphans(
+    config: &Config,
+    docker: &DockerClient,
+    orphans: &[Orphan],
+) -> Vec<String> {
+    let mut orphans: Vec<&Orphan> = orphans.iter().collect();
+    orphans.sort_by_key(|orphan| orphan.kind);
+
+    let mut errors = Vec::new();
+    for orphan in orphans {
+        let removed = match orphan.kind {
+            OrphanKind::Container => {
+                let id = orphan.id.as_deref().unwrap_or(&orphan.name);
+                docker.remove_container(id, true).await
+            }
+            OrphanKind::Volume => docker.remove_volume(&orphan.name).await,
+            OrphanKind::Database => services::drop_postgres_database(config, &orphan.name).await,
+            OrphanKind::Directory => {
+                fs::remove_dir_all(config.server.releases_dir.join(&orphan.name))
+                    .map_err(Into::into)
+            }
+            OrphanKind::Record => StateStore::for_config(config)
+                .and_then(|store| store.delete(&orphan.name).map(drop)),
+        };
+
+        match removed {
+            Ok(()) => tracing::info!("Removed orphaned {} {}", orphan.kind, orphan.name),
+            Err(e) => errors.push(format!(
+                "Failed to remove {} {}: {}",
+                orphan.kind, orphan.name, e
+            )),
+        }
+    }
+    errors
+}
+
+/// Work out which resources in an inventory are orphaned
+fn orphans_in(inventory: &Inventory) -> Vec<Orphan> {
+    let live: BTreeSet<&String> = inventory
+        .records
+        .intersection(&inventory.directories)
+        .collect();
+    let mut orphans = Vec::new();
+    let mut orphan = |kind, name: &str, scratch: &str, reason: String, id: Option<String>| {
+        orphans.push(Orphan {
+            kind,
+            name: name.to_string(),
+            scratch: scratch.to_string(),
+            reason,
+            id,
+        })
+    };
+
+    // Names of every scratch something refers to, to tell which compose
+    // projects are ours
+    let mut dead: BTreeSet<&str> = BTreeSet::new();
+
+    for (container, scratch) in &inventory.containers {
+        if !live.contains(scratch) {
+            dead.insert(scratch);
+            orphan(
+                OrphanKind::Container,
+                &container.name,
+                scratch,
+                format!("scratch '{}' doesn't exist", scratch),
+                Some(container.id.clone()),
+            );
+        }
+    }
+
+    for database in &inventory.databases {
+        if inventory.live_databases.contains(database) {
+            continue;
+        }
+        let scratch = database.strip_prefix(DATABASE_PREFIX).unwrap_or(database);
+        dead.insert(scratch);
+        orphan(
+            OrphanKind::Database,
+            database,
+            scratch,
+            "not used by any scratch".to_string(),
+            None,
+        );
+    }
+
+    for directory in &inventory.directories {
+        if !inventory.records.contains(directory) {
+            dead.insert(directory);
+            orphan(
+                OrphanKind::Directory,
+                directory,
+                directory,
+                "not in the state store".to_string(),
+                None,
+            );
+        }
+    }
+
+    for record in &inventory.records {
+        if !inventory.directories.contains(record) {
+            dead.insert(record);
+            orphan(
+                OrphanKind::Record,
+                record,
+                record,
+                "scratch directory is missing".to_string(),
+                None,
+            );
+        }
+    }
+
+    let live_projects: BTreeSet<String> =
+        live.iter().map(|name| compose_project_name(name)).collect();
+    for volume in &inventory.volumes {
+        if live_projects.contains(&volume.project) {
+            continue;
+        }
+        if let Some(scratch) = dead
+            .iter()
+            .find(|name| compose_project_name(name) == volume.project)
+        {
+            orphan(
+                OrphanKind::Volume,
+                &volume.name,
+                scratch,
+                format!("compose project '{}' is gone", volume.project),
+                None,
+            );
+        }
+    }
+
+    orphans.sort_by(|a, b| (a.kind, &a.name).cmp(&(b.kind, &b.name)));
+    orphans
+}
+
+/// Names of the subdirectories of the releases directory
+///
+/// Hidden directories (such as `.locks`) aren't scratches.
+fn list_directories(releases_dir: &Path) -> Result<BTreeSet<String>> {
+    let mut directories = BTreeSet::new();
+    if !releases_dir.exists() {
+        return Ok(directories);
+    }
+
+    for entry in fs::read_dir(releases_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type()?.is_dir() && !name.starts_with('.') {
+            directories.insert(name);
+        }
+    }
+    Ok(directories)
+}
+
+/// The project name docker compose derives from a directory name
+pub(super) fn compose_project_name(directory: &str) -> String {
+    directory
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect::<String>()
+        .trim_start_matches(['-', '_'])
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn names(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn container(name: &str) -> ContainerStatus {
+        ContainerStatus {
+            id: format!("{}-id", name),
+            name: name.to_string(),
+            image: "api".to_string(),
+            image_id: "sha256:api".to_string(),
+            state: "running".to_string(),
+            status: String::new(),
+            labels: HashMap::new(),
+        }
+    }
+
+    fn volume(name: &str, project: &str) -> ComposeVolume {
+        ComposeVolume {
+            name: name.to_string(),
+            project: project.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_orphans_in() {
+        let inventory = Inventory {
+            records: names(&["live", "no-dir"]),
+            directories: names(&["live", "Stray.Dir"]),
+            containers: vec![
+                (container("live-api"), "live".to_string()),
+                (container("gone-api"), "gone".to_string()),
+            ],
+            volumes: vec![
+                volume("live_data", "live"),
+                volume("gone_data", "gone"),
+                volume("stray_data", "straydir"),
+                volume("unrelated_data", "unrelated"),
+            ],
+            databases: vec!["scratch_live".to_string(), "scratch_old".to_string()],
+            live_databases: names(&["scratch_live"]),
+        };
+
+        let orphans = orphans_in(&inventory);
+        let found: Vec<(OrphanKind, &str, &str)> = orphans
+            .iter()
+            .map(|o| (o.kind, o.name.as_str(), o.scratch.as_str()))
+            .collect();
+
+        assert_eq!(
+            found,
+            [
+                (OrphanKind::Container, "gone-api", "gone"),
+                (OrphanKind::Volume, "gone_data", "gone"),
+                (OrphanKind::Volume, "stray_data", "Stray.Dir"),
+                (OrphanKind::Database, "scratch_old", "old"),
+                (OrphanKind::Directory, "Stray.Dir", "Stray.Dir"),
+                (OrphanKind::Record, "no-dir", "no-dir"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compose_project_name() {
+        assert_eq!(compose_project_name("feature-x"), "feature-x");
+        assert_eq!(compose_project_name("Feature.X"), "featurex");
+        assert_eq!(compose_project_name("_tmp"), "tmp");
+    }
+}
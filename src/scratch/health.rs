@@ -0,0 +1,169 @@
+//! Waiting for a scratch's services to become healthy
+//!
+//! `docker compose up -d` returns as soon as the containers are started, so
+//! a scratch whose api is crash-looping would otherwise be reported as
+//! created. After starting, every container of the scratch is polled until
+//! its healthcheck passes (or, without a healthcheck, it's running), it
+//! exits, or its service's `health_timeout` runs out.
+
+use futures_util::future::join_all;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::docker::{ContainerHealth, DockerClient};
+use crate::error::{Error, Result};
+
+use super::parse_duration;
+
+/// Number of log lines included for each service that didn't become healthy
+pub(super) const LOG_LINES: usize = 20;
+
+/// Readiness of one of a scratch's containers after waiting for it
+#[derive(Debug, Clone)]
+pub struct ServiceHealth {
+    pub service: String,
+    pub container: String,
+    pub health: ContainerHealth,
+    /// How long we waited
+    pub timeout: String,
+    /// The last lines the container logged
+    pub logs: Vec<String>,
+}
+
+/// Wait for every container of a scratch to become ready
+///
+/// Fails with [`Error::ScratchUnhealthy`], listing each service that didn't
+/// make it along with its last log lines.
+pub async fn wait_for_scratch_healthy(
+    config: &Config,
+    docker: &DockerClient,
+    name: &str,
+) -> Result<()> {
+    let service_label = format!("{}.service", config.docker.label_prefix);
+    let containers = docker.list_scratch_containers(Some(name)).await?;
+
+    let mut waits = Vec::new();
+    for container in &containers {
+        let service = container
+            .labels
+            .get(&service_label)
+            .cloned()
+            .unwrap_or_else(|| container.name.clone());
+        let timeout = health_timeout(config, &service);
+        let wait = parse_health_timeout(timeout)?;
+
+        waits.push(async move {
+            let health = docker.wait_for_ready(&container.id, wait).await;
+            (service, container, timeout, health)
+        });
+    }
+
+    let mut unhealthy = Vec::new();
+    for (service, container, timeout, health) in join_all(waits).await {
+        let health = health?;
+        if health.is_ready() {
+            tracing::debug!("Service {} of scratch '{}' is ready", service, name);
+            continue;
+        }
+
+        let logs = docker
+            .get_logs(&container.id, LOG_LINES)
+            .await
+            .unwrap_or_default();
+        unhealthy.push(ServiceHealth {
+            service,
+            container: container.name.clone(),
+            health,
+            timeout: timeout.to_string(),
+            logs,
+        });
+    }
+
+    if unhealthy.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::ScratchUnhealthy(
+            name.to_string(),
+            format_health_report(&unhealthy),
+        ))
+    }
+}
+
+/// How long to wait for a service: its own `health_timeout`, else the default
+pub(super) fn health_timeout<'a>(config: &'a Config, service: &str) -> &'a str {
+    config
+        .get_service(service)
+        .and_then(|s| s.health_timeout.as_deref())
+        .unwrap_or(&config.scratch.health_timeout)
+}
+
+/// Parse a health timeout for waiting on a container
+pub(super) fn parse_health_timeout(timeout: &str) -> Result<Duration> {
+    parse_duration(timeout)?
+        .to_std()
+        .map_err(|_| Error::Config(format!("Invalid health timeout: {}", timeout)))
+}
+
+/// Describe each unhealthy service and what it last logged
+pub(super) fn format_health_report(services: &[ServiceHealth]) -> String {
+    let mut report = Vec::new();
+    for service in services {
+        let waited = match service.health {
+            ContainerHealth::Failed(_) => String::new(),
+            _ => format!(" after {}", service.timeout),
+        };
+        report.push(format!(
+            "  {} ({}): {}{}",
+            service.service, service.container, service.health, waited
+        ));
+        for line in &service.logs {
+            report.push(format!("    | {}", line.trim_end()));
+        }
+    }
+    report.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_timeout_falls_back_to_default() {
+        let config: Config = toml::from_str(
+            "[services.api]\nimage = \"api\"\nhealth_timeout = \"5m\"\n\
+             [services.worker]\nimage = \"worker\"",
+        )
+        .unwrap();
+
+        assert_eq!(health_timeout(&config, "api"), "5m");
+        assert_eq!(health_timeout(&config, "worker"), "2m");
+        assert_eq!(health_timeout(&config, "unknown"), "2m");
+    }
+
+    #[test]
+    fn test_format_health_report() {
+        let report = format_health_report(&[
+            ServiceHealth {
+                service: "api".to_string(),
+                container: "feature-x-api-1".to_string(),
+                health: ContainerHealth::Waiting("restarting (exit code 1, 4 restarts)".into()),
+                timeout: "2m".to_string(),
+                logs: vec!["Error: DATABASE_URL not set\n".to_string()],
+            },
+            ServiceHealth {
+                service: "worker".to_string(),
+                container: "feature-x-worker-1".to_string(),
+                health: ContainerHealth::Failed("exited (exit code 2)".into()),
+                timeout: "2m".to_string(),
+                logs: vec![],
+            },
+        ]);
+
+        assert_eq!(
+            report,
+            "  api (feature-x-api-1): restarting (exit code 1, 4 restarts) after 2m\n\
+             \x20   | Error: DATABASE_URL not set\n\
+             \x20 worker (feature-x-worker-1): exited (exit code 2)"
+        );
+    }
+}
@@ -0,0 +1,418 @@
+//! Lifecycle hooks
+//!
+//! `[hooks]` in the config (and in a profile) lists commands to run at
+//! points of a scratch's lifecycle, e.g. migrations after it is created or
+//! a data export before it is deleted. A hook either runs a shell command on
+//! the host, in the scratch directory with the scratch's variables in its
+//! environment, or runs `exec_command` in one of the scratch's containers.
+//! Global hooks run before the profile's.
+//!
+//! Everything hooks print is appended to `logs/hooks.log` in the scratch
+//! directory.
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::Instant;
+use tokio::process::Command;
+
+use crate::config::{Config, Hook, HooksConfig};
+use crate::docker::{DockerClient, ExecOptions};
+use crate::error::{Error, Result};
+
+use super::{parse_duration, report, Scratch};
+
+/// Log of hook output, relative to the scratch directory
+pub const HOOK_LOG: &str = "logs/hooks.log";
+
+/// A point of a scratch's lifecycle that hooks run at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPoint {
+    PreCreate,
+    PostCreate,
+    PostStart,
+    PreDelete,
+    PostUpdate,
+}
+
+impl HookPoint {
+    pub const ALL: [HookPoint; 5] = [
+        HookPoint::PreCreate,
+        HookPoint::PostCreate,
+        HookPoint::PostStart,
+        HookPoint::PreDelete,
+        HookPoint::PostUpdate,
+    ];
+
+    /// Get the hook point as a string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookPoint::PreCreate => "pre_create",
+            HookPoint::PostCreate => "post_create",
+            HookPoint::PostStart => "post_start",
+            HookPoint::PreDelete => "pre_delete",
+            HookPoint::PostUpdate => "post_update",
+        }
+    }
+
+    /// The hooks of a `[hooks]` table for this point
+    pub fn hooks<'a>(&self, hooks: &'a HooksConfig) -> &'a [Hook] {
+        match self {
+            HookPoint::PreCreate => &hooks.pre_create,
+            HookPoint::PostCreate => &hooks.post_create,
+            HookPoint::PostStart => &hooks.post_start,
+            HookPoint::PreDelete => &hooks.pre_delete,
+            HookPoint::PostUpdate => &hooks.post_update,
+        }
+    }
+}
+
+impl fmt::Display for HookPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What a hook runs
+#[derive(Debug, PartialEq, Eq)]
+pub enum HookAction<'a> {
+    /// A shell command on the host
+    Host(&'a str),
+    /// A shell command in a service's container
+    Exec { service: &'a str, command: &'a str },
+}
+
+/// Work out what a hook runs, checking it's configured correctly
+pub fn hook_action<'a>(config: &Config, hook: &'a Hook) -> Result<HookAction<'a>> {
+    match (&hook.command, &hook.service, &hook.exec_command) {
+        (Some(command), None, None) => Ok(HookAction::Host(command)),
+        (None, Some(service), Some(command)) => {
+            if config.get_service(service).is_none() {
+                return Err(Error::ServiceNotFound(service.clone()));
+            }
+            Ok(HookAction::Exec { service, command })
+        }
+        (None, None, Some(_)) => Err(Error::Config(
+            "Hook with an exec_command needs a service".to_string(),
+        )),
+        _ => Err(Error::Config(
+            "Hook needs either a command or a service and exec_command".to_string(),
+        )),
+    }
+}
+
+/// The hooks that run for a scratch at a point: the global ones, then its profile's
+pub fn scratch_hooks<'a>(config: &'a Config, scratch: &Scratch, point: HookPoint) -> Vec<&'a Hook> {
+    let profile = scratch
+        .profile
+        .as_deref()
+        .and_then(|p| config.get_profile(p));
+
+    point
+        .hooks(&config.hooks)
+        .iter()
+        .chain(profile.into_iter().flat_map(|p| point.hooks(&p.hooks)))
+        .collect()
+}
+
+/// Run a scratch's hooks for a point of its lifecycle, in order
+///
+/// A hook that fails (or runs out of time) stops the rest and fails the
+/// operation, unless it sets `fail_on_error = false`. Hooks that exec into a
+/// container that isn't running are skipped.
+pub async fn run_hooks(
+    config: &Config,
+    docker: &DockerClient,
+    scratch: &Scratch,
+    point: HookPoint,
+) -> Result<()> {
+    let hooks = scratch_hooks(config, scratch, point);
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let scratch_dir = config.server.releases_dir.join(&scratch.name);
+    report("hooks", format!("Running {} hooks", point));
+    for hook in hooks {
+        let action = hook_action(config, hook)?;
+        if let HookAction::Exec { service, .. } = action {
+            let container = hook_container(config, scratch, service);
+            if !docker.container_running(&container).await.unwrap_or(false) {
+                tracing::warn!(
+                    "Skipping {} hook of scratch '{}': {} isn't running",
+                    point,
+                    scratch.name,
+                    container
+                );
+                continue;
+            }
+        }
+
+        let started = Instant::now();
+        let result = run_hook(config, docker, scratch, &scratch_dir, hook, &action).await;
+
+        let (output, failure) = match result {
+            Ok((output, 0)) => (output, None),
+            Ok((output, code)) => (output, Some(format!("exited with status {}", code))),
+            Err(e) => (String::new(), Some(e.to_string())),
+        };
+        let outcome = failure.as_deref().unwrap_or("succeeded");
+        if let Err(e) = append_hook_log(&scratch_dir, point, &action, &output, outcome, started) {
+            tracing::warn!(
+                "Failed to write hook log of scratch '{}': {}",
+                scratch.name,
+                e
+            );
+        }
+
+        let Some(failure) = failure else {
+            continue;
+        };
+        let details = format!("{}: {}", describe(&action), failure);
+        if hook.fail_on_error {
+            return Err(Error::HookFailed(point.to_string(), details));
+        }
+        tracing::warn!(
+            "{} hook of scratch '{}' failed: {}",
+            point,
+            scratch.name,
+            details
+        );
+    }
+
+    Ok(())
+}
+
+/// Run one hook, returning what it printed and its exit code
+///
+/// A host command that runs out of time is killed. Docker can't stop an
+/// exec, so an `exec_command` that runs out of time keeps running in its
+/// container; the hook only stops waiting for it.
+async fn run_hook(
+    config: &Config,
+    docker: &DockerClient,
+    scratch: &Scratch,
+    scratch_dir: &Path,
+    hook: &Hook,
+    action: &HookAction<'_>,
+) -> Result<(String, i64)> {
+    let timeout = parse_duration(&hook.timeout)?
+        .to_std()
+        .map_err(|_| Error::Config(format!("Invalid hook timeout: {}", hook.timeout)))?;
+    let vars = hook_vars(config, scratch);
+
+    let run = async {
+        match *action {
+            HookAction::Host(command) => {
+                tracing::info!("Running hook for scratch '{}': {}", scratch.name, command);
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .current_dir(scratch_dir)
+                    .envs(super::scratch_env(config, scratch))
+                    .envs(vars)
+                    .kill_on_drop(true)
+                    .output()
+                    .await?;
+                let mut printed = String::from_utf8_lossy(&output.stdout).into_owned();
+                printed.push_str(&String::from_utf8_lossy(&output.stderr));
+                Ok((printed, output.status.code().unwrap_or(-1).into()))
+            }
+            HookAction::Exec { service, command } => {
+                let container = hook_container(config, scratch, service);
+                tracing::info!("Running hook in {}: {}", container, command);
+                let options = ExecOptions {
+                    env: vars.iter().map(|(k, v)| format!("{}={}", k, v)).collect(),
+                    ..Default::default()
+                };
+                let output = docker
+                    .exec(&container, vec!["sh", "-c", command], options)
+                    .await?;
+                Ok((output.output, output.exit_code))
+            }
+        }
+    };
+
+    tokio::time::timeout(timeout, run)
+        .await
+        .map_err(|_| Error::Other(format!("timed out after {}", hook.timeout)))?
+}
+
+/// Variables describing the scratch, given to every hook
+pub fn hook_vars(config: &Config, scratch: &Scratch) -> Vec<(String, String)> {
+    let scratch_dir = config.server.releases_dir.join(&scratch.name);
+    let mut vars = vec![
+        ("SCRATCH_NAME".to_string(), scratch.name.clone()),
+        ("SCRATCH_BRANCH".to_string(), scratch.branch.clone()),
+        (
+            "SCRATCH_DIR".to_string(),
+            scratch_dir.to_string_lossy().into_owned(),
+        ),
+    ];
+    if let Some(profile) = &scratch.profile {
+        vars.push(("SCRATCH_PROFILE".to_string(), profile.clone()));
+    }
+    if let Some(db) = scratch
+        .databases
+        .get("postgres")
+        .and_then(|dbs| dbs.first())
+    {
+        vars.push(("SCRATCH_DATABASE".to_string(), db.clone()));
+    }
+    vars
+}
+
+/// Container a hook's `exec_command` runs in
+fn hook_container(config: &Config, scratch: &Scratch, service: &str) -> String {
+    match config.get_service(service) {
+        Some(svc) if svc.shared => format!("scratchpad-{}", service),
+        _ => format!("{}-{}", scratch.name, service),
+    }
+}
+
+fn describe(action: &HookAction<'_>) -> String {
+    match action {
+        HookAction::Host(command) => command.to_string(),
+        HookAction::Exec { service, command } => format!("{} (in {})", command, service),
+    }
+}
+
+/// Append the output of a hook run to the scratch's hook log
+fn append_hook_log(
+    scratch_dir: &Path,
+    point: HookPoint,
+    action: &HookAction<'_>,
+    output: &str,
+    outcome: &str,
+    started: Instant,
+) -> Result<()> {
+    let path = scratch_dir.join(HOOK_LOG);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut log = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(
+        log,
+        "[{}] {}: {}",
+        chrono::Utc::now().to_rfc3339(),
+        point,
+        describe(action)
+    )?;
+    for line in output.lines() {
+        writeln!(log, "  {}", line)?;
+    }
+    writeln!(
+        log,
+        "[{}] {} {} after {:.1}s",
+        chrono::Utc::now().to_rfc3339(),
+        point,
+        outcome,
+        started.elapsed().as_secs_f64()
+    )?;
+    Ok(())
+}
+
+/// The last `tail` lines of a scratch's hook log
+pub fn read_hook_log(config: &Config, name: &str, tail: usize) -> Result<Vec<String>> {
+    let scratch_dir = config.server.releases_dir.join(name);
+    if !scratch_dir.exists() {
+        return Err(Error::ScratchNotFound(name.to_string()));
+    }
+
+    let log = match fs::read_to_string(scratch_dir.join(HOOK_LOG)) {
+        Ok(log) => log,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let lines: Vec<&str> = log.lines().collect();
+    let start = lines.len().saturating_sub(tail);
+    Ok(lines[start..].iter().map(|l| l.to_string()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(releases_dir: &Path) -> Config {
+        let mut config: Config = toml::from_str(
+            "[services.api]\nimage = \"api\"\n\
+             [[hooks.post_create]]\ncommand = \"echo \\\"created $SCRATCH_NAME on $SCRATCH_BRANCH\\\"\"\n\
+             [[hooks.post_create]]\ncommand = \"echo oops >&2; exit 3\"\nfail_on_error = false\n\
+             [[hooks.pre_delete]]\ncommand = \"sleep 5\"\ntimeout = \"1s\"\n\
+             [scratch.profiles.qa.hooks]\n\
+             post_create = [{ service = \"api\", exec_command = \"bin/seed\" }]",
+        )
+        .unwrap();
+        config.server.releases_dir = releases_dir.to_path_buf();
+        config
+    }
+
+    #[test]
+    fn test_scratch_hooks_include_profile() {
+        let temp = tempfile::tempdir().unwrap();
+        let config = config(temp.path());
+        let mut scratch = Scratch::new("feat-x".into(), "feature/x".into(), "default".into());
+
+        assert_eq!(
+            scratch_hooks(&config, &scratch, HookPoint::PostCreate).len(),
+            2
+        );
+        scratch.profile = Some("qa".into());
+        let hooks = scratch_hooks(&config, &scratch, HookPoint::PostCreate);
+        assert_eq!(
+            hook_action(&config, hooks[2]).unwrap(),
+            HookAction::Exec {
+                service: "api",
+                command: "bin/seed"
+            }
+        );
+        assert!(scratch_hooks(&config, &scratch, HookPoint::PostStart).is_empty());
+    }
+
+    #[test]
+    fn test_hook_action_validation() {
+        let config = config(Path::new("/tmp"));
+        let hook = |toml_str: &str| toml::from_str::<Hook>(toml_str).unwrap();
+
+        assert!(hook_action(&config, &hook("exec_command = \"ls\"")).is_err());
+        assert!(hook_action(&config, &hook("command = \"ls\"\nexec_command = \"ls\"")).is_err());
+        assert!(matches!(
+            hook_action(&config, &hook("service = \"web\"\nexec_command = \"ls\"")),
+            Err(Error::ServiceNotFound(_))
+        ));
+        assert_eq!(
+            hook_action(&config, &hook("command = \"ls\"")).unwrap(),
+            HookAction::Host("ls")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_host_hooks() {
+        let temp = tempfile::tempdir().unwrap();
+        let config = config(temp.path());
+        let docker = DockerClient::new(config.docker.clone()).unwrap();
+        let scratch = Scratch::new("feat-x".into(), "feature/x".into(), "default".into());
+        fs::create_dir_all(temp.path().join("feat-x")).unwrap();
+
+        // The failing hook doesn't fail the operation
+        run_hooks(&config, &docker, &scratch, HookPoint::PostCreate)
+            .await
+            .unwrap();
+        let log = read_hook_log(&config, "feat-x", 100).unwrap();
+        assert!(log.contains(&"  created feat-x on feature/x".to_string()));
+        assert!(log.contains(&"  oops".to_string()));
+        assert!(log
+            .last()
+            .unwrap()
+            .contains("post_create exited with status 3"));
+
+        let err = run_hooks(&config, &docker, &scratch, HookPoint::PreDelete)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::HookFailed(point, _) if point == "pre_delete"));
+        assert_eq!(read_hook_log(&config, "feat-x", 1).unwrap().len(), 1);
+        assert!(read_hook_log(&config, "missing", 1).is_err());
+    }
+}
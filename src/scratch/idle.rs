@@ -0,0 +1,188 @@
+//! Idle tracking and auto-stop for scratches
+//!
+//! Request activity is reported by the nginx ingress and kept in memory by the
+//! [`ActivityTracker`]. The server periodically persists it to the state store
+//! and stops scratches that have been idle for longer than
+//! `scratch.idle_timeout`.
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::{Mutex, RwLock};
+
+use crate::config::Config;
+use crate::docker::DockerClient;
+use crate::error::{Error, Result};
+
+use super::{
+    list_scratches, load_scratch_config, parse_duration, save_scratch_config, stop_scratch,
+    ScratchLock,
+};
+
+/// In-memory record of the last request seen for each scratch
+#[derive(Default)]
+pub struct ActivityTracker {
+    last_seen: RwLock<HashMap<String, DateTime<Utc>>>,
+    waking: Mutex<HashSet<String>>,
+}
+
+impl ActivityTracker {
+    /// Create a new activity tracker
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a request for a scratch
+    pub async fn touch(&self, name: &str) {
+        self.last_seen
+            .write()
+            .await
+            .insert(name.to_string(), Utc::now());
+    }
+
+    /// Get the last request time recorded for a scratch
+    pub async fn last_seen(&self, name: &str) -> Option<DateTime<Utc>> {
+        self.last_seen.read().await.get(name).copied()
+    }
+
+    /// Mark a scratch as waking up, returning false if it already is
+    pub async fn begin_wake(&self, name: &str) -> bool {
+        self.waking.lock().await.insert(name.to_string())
+    }
+
+    /// Whether a scratch is being woken up
+    pub async fn is_waking(&self, name: &str) -> bool {
+        self.waking.lock().await.contains(name)
+    }
+
+    /// Clear the waking flag for a scratch
+    pub async fn end_wake(&self, name: &str) {
+        self.waking.lock().await.remove(name);
+    }
+}
+
+/// Get the configured idle timeout, if auto-stop is enabled
+pub fn resolve_idle_timeout(config: &Config) -> Result<Option<Duration>> {
+    config
+        .scratch
+        .idle_timeout
+        .as_deref()
+        .map(parse_duration)
+        .transpose()
+}
+
+/// Whether request activity is reported at all
+///
+/// Only the nginx ingress reports requests, and only when it can reach the
+/// server. Without that, every scratch would look idle since its creation.
+pub fn activity_tracked(config: &Config) -> bool {
+    config.nginx.enabled && config.nginx.server_upstream.is_some()
+}
+
+/// Persist tracked activity and stop scratches that have been idle too long
+///
+/// Returns the names of the scratches that were stopped.
+pub async fn stop_idle_scratches(
+    config: &Config,
+    docker: &DockerClient,
+    tracker: &ActivityTracker,
+) -> Result<Vec<String>> {
+    let idle_timeout = resolve_idle_timeout(config)?.filter(|_| activity_tracked(config));
+    let now = Utc::now();
+    let mut stopped = Vec::new();
+
+    for status in list_scratches(config, docker).await? {
+        // Hold the lock while flushing, so an operation in progress can't
+        // overwrite the activity or have its own changes overwritten
+        let scratch_config = {
+            let _lock = match ScratchLock::acquire(config, &status.name, "idle") {
+                Ok(lock) => lock,
+                Err(Error::ScratchBusy(..)) => continue,
+                Err(e) => {
+                    tracing::error!("Failed to lock scratch {}: {}", status.name, e);
+                    continue;
+                }
+            };
+            let Ok(mut scratch_config) = load_scratch_config(config, &status.name) else {
+                continue;
+            };
+
+            // Flush newer in-memory activity to the state store
+            if let Some(seen) = tracker.last_seen(&status.name).await {
+                if scratch_config
+                    .last_activity_at
+                    .is_none_or(|stored| seen > stored)
+                {
+                    scratch_config.last_activity_at = Some(seen);
+                    if let Err(e) = save_scratch_config(config, &scratch_config) {
+                        tracing::error!(
+                            "Failed to save activity of scratch {}: {}",
+                            status.name,
+                            e
+                        );
+                        continue;
+                    }
+                }
+            }
+            scratch_config
+        };
+
+        let Some(idle_timeout) = idle_timeout else {
+            continue;
+        };
+
+        if status.status != "running" && status.status != "partial" {
+            continue;
+        }
+
+        let last_activity = scratch_config
+            .last_activity_at
+            .unwrap_or(scratch_config.created_at);
+
+        if now - last_activity > idle_timeout {
+            tracing::info!(
+                "Scratch '{}' idle since {}, stopping",
+                status.name,
+                last_activity
+            );
+            match stop_scratch(config, docker, &status.name).await {
+                Ok(()) => stopped.push(status.name),
+                Err(e) => tracing::error!("Failed to stop idle scratch {}: {}", status.name, e),
+            }
+        }
+    }
+
+    Ok(stopped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_activity_tracker() {
+        let tracker = ActivityTracker::new();
+        assert!(tracker.last_seen("feature-x").await.is_none());
+
+        tracker.touch("feature-x").await;
+        assert!(tracker.last_seen("feature-x").await.is_some());
+
+        assert!(tracker.begin_wake("feature-x").await);
+        assert!(!tracker.begin_wake("feature-x").await);
+        assert!(tracker.is_waking("feature-x").await);
+        tracker.end_wake("feature-x").await;
+        assert!(!tracker.is_waking("feature-x").await);
+        assert!(tracker.begin_wake("feature-x").await);
+    }
+
+    #[test]
+    fn test_activity_tracked() {
+        let mut config = Config::default();
+        assert!(!activity_tracked(&config));
+
+        config.nginx.server_upstream = Some("host.docker.internal:3456".to_string());
+        assert!(activity_tracked(&config));
+
+        config.nginx.enabled = false;
+        assert!(!activity_tracked(&config));
+    }
+}
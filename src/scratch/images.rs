@@ -0,0 +1,138 @@
+//! Resolving the images a scratch's services run
+//!
+//! A per-scratch service's `image` can depend on the scratch, e.g.
+//! `myorg/api:{{ scratch.branch | sanitize }}` for images CI pushes per
+//! branch. When a service lists `fallback_images`, each candidate is pulled
+//! in turn and the first one available is used, so a branch CI hasn't built
+//! yet still runs `myorg/api:main`. The image picked is stored with the
+//! scratch and shown in its status.
+
+use crate::config::Config;
+use crate::docker::DockerClient;
+use crate::error::{Error, Result};
+
+use super::template::ValueRenderer;
+use super::{build_scratch_images, load_scratch_config, report, save_scratch_config, Scratch};
+
+/// Work out the image each per-scratch service runs, pulling as needed
+///
+/// Services built from source keep the image built for them.
+pub async fn resolve_scratch_images(
+    config: &Config,
+    docker: &DockerClient,
+    scratch: &mut Scratch,
+) -> Result<()> {
+    for service_name in scratch.services.clone() {
+        let Some(service) = config.get_service(&service_name) else {
+            continue;
+        };
+        if service.shared || service.build.is_some() {
+            continue;
+        }
+
+        let candidates = image_candidates(config, scratch, &service_name)?;
+        let image = if candidates.len() > 1 {
+            report("pull", format!("Resolving image for {}", service_name));
+            pick_image(docker, &service_name, &candidates).await?
+        } else if let Some(image) = candidates.into_iter().next() {
+            image
+        } else {
+            continue;
+        };
+        scratch.images.insert(service_name, image);
+    }
+
+    Ok(())
+}
+
+/// Resolve a stored scratch's images again and save them
+///
+/// Used when a scratch is updated, so a branch image that has been pushed
+/// since replaces the fallback, and services built from source are built
+/// again from the branch's latest commit.
+pub async fn refresh_scratch_images(
+    config: &Config,
+    docker: &DockerClient,
+    scratch: &mut Scratch,
+) -> Result<()> {
+    resolve_scratch_images(config, docker, scratch).await?;
+    let scratch_dir = config.server.releases_dir.join(&scratch.name);
+    build_scratch_images(config, docker, scratch, &scratch_dir).await?;
+
+    let mut scratch_config = load_scratch_config(config, &scratch.name)?;
+    scratch_config.images = scratch.images.clone();
+    save_scratch_config(config, &scratch_config)
+}
+
+/// The images a service may run, in order of preference
+pub fn image_candidates(
+    config: &Config,
+    scratch: &Scratch,
+    service_name: &str,
+) -> Result<Vec<String>> {
+    let service = config
+        .get_service(service_name)
+        .ok_or_else(|| Error::ServiceNotFound(service_name.to_string()))?;
+    let values = ValueRenderer::for_service(scratch, service_name, service)?;
+
+    let mut candidates = Vec::new();
+    for image in std::iter::once(&service.image).chain(&service.fallback_images) {
+        let image = values.render(image)?;
+        if !image.is_empty() && !candidates.contains(&image) {
+            candidates.push(image);
+        }
+    }
+    Ok(candidates)
+}
+
+/// Pull each candidate until one is available locally
+///
+/// Pulls go through the Docker API, which doesn't see the credentials of
+/// the docker CLI. If nothing can be pulled, the first candidate is used
+/// and left for `docker compose` to pull.
+async fn pick_image(docker: &DockerClient, service: &str, candidates: &[String]) -> Result<String> {
+    for image in candidates {
+        docker.pull_image_if_missing(image).await?;
+        if docker.image_exists(image).await {
+            if image != &candidates[0] {
+                tracing::warn!(
+                    "Image {} is not available, {} runs {} instead",
+                    candidates[0],
+                    service,
+                    image
+                );
+            }
+            return Ok(image.clone());
+        }
+    }
+
+    tracing::warn!(
+        "None of the images for {} could be pulled: {}",
+        service,
+        candidates.join(", ")
+    );
+    Ok(candidates[0].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_candidates() {
+        let config: Config = toml::from_str(
+            "[services.api]\nrepo = \"api\"\n\
+             image = \"myorg/api:{{ service.branch | sanitize }}\"\n\
+             fallback_images = [\"myorg/api:main\", \"myorg/api:{{ scratch.branch }}\"]",
+        )
+        .unwrap();
+        let mut scratch = Scratch::new("feat-x".into(), "main".into(), "default".into());
+        scratch.branches.insert("api".into(), "Feature/X".into());
+
+        assert_eq!(
+            image_candidates(&config, &scratch, "api").unwrap(),
+            ["myorg/api:feature-x", "myorg/api:main"]
+        );
+        assert!(image_candidates(&config, &scratch, "missing").is_err());
+    }
+}
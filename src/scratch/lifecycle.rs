@@ -1,26 +1,67 @@
 //! Scratch lifecycle management (create, start, stop, delete)
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::config::{Config, ScratchConfig};
+use crate::config::{Config, ScratchConfig, ScratchState};
+use crate::docker::ContainerStatus;
 use crate::docker::{ComposeFile, DockerClient};
 use crate::error::{Error, Result};
 use crate::nginx;
 use crate::services;
-
-use super::{Scratch, ScratchStatus};
+use crate::store::{self, StateStore};
+
+use super::{is_outdated, report, HookPoint, Scratch, ScratchLock, ScratchStatus};
+
+/// Options for creating a scratch
+#[derive(Debug, Clone, Default)]
+pub struct CreateScratchOptions {
+    /// Name for the scratch (defaults to the sanitized branch name)
+    pub name: Option<String>,
+    /// Branch per repository, for services that don't run the main branch
+    pub branches: BTreeMap<String, String>,
+    /// Profile from the config to take services, template and env from
+    pub profile: Option<String>,
+    /// Template override
+    pub template: Option<String>,
+    /// TTL override (e.g. "12h", "never")
+    pub ttl: Option<String>,
+    /// Per-scratch env overrides
+    pub env: HashMap<String, String>,
+    /// Who the scratch belongs to
+    pub owner: Option<String>,
+    /// What the scratch is for
+    pub description: Option<String>,
+    /// Labels to group the scratch by
+    pub tags: BTreeSet<String>,
+    /// Report success as soon as the containers are started, without
+    /// waiting for them to become healthy
+    pub no_wait: bool,
+}
 
 /// Create a new scratch environment
+///
+/// Creation is transactional: if any step fails, the steps completed so far
+/// are undone and the scratch is left behind marked as `failed` with the cause.
 pub async fn create_scratch(
     config: &Config,
     docker: &DockerClient,
     branch: &str,
-    name: Option<String>,
-    profile: Option<String>,
-    template: Option<String>,
+    options: CreateScratchOptions,
 ) -> Result<Scratch> {
+    let CreateScratchOptions {
+        name,
+        branches,
+        profile,
+        template,
+        ttl,
+        env,
+        owner,
+        description,
+        tags,
+        no_wait,
+    } = options;
     let scratch_name = name.unwrap_or_else(|| Scratch::sanitize_name(branch));
 
     // Validate name
@@ -37,12 +78,10 @@ pub async fn create_scratch(
     );
 
     // Check if scratch already exists
-    let releases_dir = &config.server.releases_dir;
-    let scratch_dir = releases_dir.join(&scratch_name);
-
-    if scratch_dir.exists() {
-        return Err(Error::ScratchAlreadyExists(scratch_name));
-    }
+    let scratch_dir = config.server.releases_dir.join(&scratch_name);
+    let _lock = ScratchLock::acquire(config, &scratch_name, "create")?;
+    ensure_scratch_available(config, &scratch_name)?;
+    super::check_quotas(config, owner.as_deref(), profile.as_deref())?;
 
     // Determine services to use
     let services = if let Some(profile_name) = &profile {
@@ -66,18 +105,181 @@ pub async fn create_scratch(
         .unwrap_or_else(|| config.scratch.template.clone());
     tracing::debug!("Using template: {}", template_name);
 
+    // Determine when the scratch expires
+    let ttl = super::resolve_ttl(config, profile.as_deref(), ttl.as_deref())?;
+
     // Create scratch instance
     let mut scratch = Scratch::new(
         scratch_name.clone(),
         branch.to_string(),
         template_name.clone(),
     );
-    scratch.services = services.clone();
+    scratch.branches = validate_branches(config, branches)?;
+    scratch.profile = profile;
+    scratch.owner = owner;
+    scratch.description = description;
+    scratch.tags = super::validate_tags(tags)?;
+    scratch.services = services;
+    scratch.env = env;
+    scratch.expires_at = ttl
+        .map(|ttl| super::expires_after(scratch.created_at, ttl))
+        .transpose()?;
+
+    let mut tx = CreateTransaction::new(config, docker, &scratch_dir);
+    if let Err(e) = provision_scratch(&mut tx, &mut scratch, None, !no_wait).await {
+        tx.fail(&scratch, &e).await;
+        return Err(e);
+    }
+
+    tracing::info!("Successfully created scratch: {}", scratch_name);
+    Ok(scratch)
+}
+
+/// Create a new scratch as a copy of an existing one
+///
+/// The new scratch gets the source's services, env and template, and its
+/// postgres database is created from the source's database so the data
+/// carries over. The branch defaults to the source's branch.
+///
+/// Copying a database requires that nobody is connected to it, so the
+/// source's open database connections are terminated.
+pub async fn clone_scratch(
+    config: &Config,
+    docker: &DockerClient,
+    source: &str,
+    name: &str,
+    branch: Option<String>,
+) -> Result<Scratch> {
+    let scratch_name = name.to_string();
+    if scratch_name.is_empty() {
+        return Err(Error::InvalidScratchName(
+            "Scratch name cannot be empty".to_string(),
+        ));
+    }
+    if scratch_name == source {
+        return Err(Error::InvalidScratchName(format!(
+            "Scratch '{}' cannot be cloned onto itself",
+            source
+        )));
+    }
+
+    let _source_lock = ScratchLock::acquire(config, source, "clone")?;
+    let source_config = load_scratch_config(config, source)?;
+
+    let scratch_dir = config.server.releases_dir.join(&scratch_name);
+    let _lock = ScratchLock::acquire(config, &scratch_name, "clone")?;
+    ensure_scratch_available(config, &scratch_name)?;
+    super::check_quotas(
+        config,
+        source_config.owner.as_deref(),
+        source_config.profile.as_deref(),
+    )?;
+
+    tracing::debug!("Cloning scratch '{}' to '{}'", source, scratch_name);
+
+    let mut scratch = Scratch::new(
+        scratch_name.clone(),
+        branch.unwrap_or_else(|| source_config.branch.clone()),
+        source_config.template.clone(),
+    );
+    scratch.branches = source_config.branches.clone();
+    scratch.profile = source_config.profile.clone();
+    scratch.services = source_config.services.clone();
+    scratch.env = source_config.env.clone();
+    scratch.owner = source_config.owner.clone();
+    scratch.description = source_config.description.clone();
+    scratch.tags = source_config.tags.clone();
+    let ttl = super::resolve_ttl(config, source_config.profile.as_deref(), None)?;
+    scratch.expires_at = ttl
+        .map(|ttl| super::expires_after(scratch.created_at, ttl))
+        .transpose()?;
+
+    let source_db = source_config
+        .databases
+        .get("postgres")
+        .and_then(|dbs| dbs.first());
+
+    let mut tx = CreateTransaction::new(config, docker, &scratch_dir);
+    let source_db = source_db.map(|db| db.as_str());
+    if let Err(e) = provision_scratch(&mut tx, &mut scratch, source_db, true).await {
+        tx.fail(&scratch, &e).await;
+        return Err(e);
+    }
+
+    tracing::info!(
+        "Successfully cloned scratch '{}' to '{}'",
+        source,
+        scratch_name
+    );
+    Ok(scratch)
+}
+
+/// Check that every repository given a branch is one a service is mapped to
+fn validate_branches(
+    config: &Config,
+    branches: BTreeMap<String, String>,
+) -> Result<BTreeMap<String, String>> {
+    let repos = config.repos();
+    if let Some(repo) = branches.keys().find(|repo| !repos.contains(repo.as_str())) {
+        let known = repos.into_iter().collect::<Vec<_>>().join(", ");
+        return Err(Error::Config(format!(
+            "No service is mapped to repository '{}' (known: {})",
+            repo,
+            if known.is_empty() { "none" } else { &known }
+        )));
+    }
+    Ok(branches)
+}
+
+/// Fail if a scratch name is taken
+///
+/// Leftovers of a failed create are cleared so the name can be reused.
+fn ensure_scratch_available(config: &Config, name: &str) -> Result<()> {
+    let store = StateStore::for_config(config)?;
+    let scratch_dir = config.server.releases_dir.join(name);
+
+    match store.get(name)? {
+        Some(scratch_config)
+            if scratch_config.state == ScratchState::Failed || scratch_config.error.is_some() =>
+        {
+            tracing::info!("Replacing failed scratch '{}'", name);
+            if scratch_dir.exists() {
+                fs::remove_dir_all(&scratch_dir)?;
+            }
+            store.delete(name)?;
+            Ok(())
+        }
+        Some(_) => Err(Error::ScratchAlreadyExists(name.to_string())),
+        // A directory nothing knows about is still not ours to overwrite
+        None if scratch_dir.exists() => Err(Error::ScratchAlreadyExists(name.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Provision everything a new scratch needs, recording each step in `tx`
+///
+/// When `source_db` is given the scratch's database is cloned from it
+/// instead of being created empty. With `wait_healthy` the scratch's
+/// containers must become healthy before it counts as created.
+async fn provision_scratch(
+    tx: &mut CreateTransaction<'_>,
+    scratch: &mut Scratch,
+    source_db: Option<&str>,
+    wait_healthy: bool,
+) -> Result<()> {
+    let (config, docker) = (tx.config, tx.docker);
+    let scratch_dir = tx.scratch_dir.clone();
 
     // Create directory structure
     tracing::debug!("Creating directory structure at {}", scratch_dir.display());
+    tx.record(CreateStep::Directory);
     create_scratch_directories(&scratch_dir)?;
 
+    // Record the scratch straight away so it shows up as creating
+    let mut scratch_config = to_scratch_config(scratch);
+    scratch_config.state = ScratchState::Creating;
+    save_scratch_config(config, &scratch_config)?;
+
     // Ensure network exists
     tracing::debug!("Ensuring Docker network exists");
     docker.ensure_network().await?;
@@ -86,7 +288,7 @@ pub async fn create_scratch(
     let mut databases: HashMap<String, Vec<String>> = HashMap::new();
 
     // Ensure all shared services are running
-    for service_name in &services {
+    for service_name in &scratch.services {
         if let Some(service_config) = config.get_service(service_name) {
             if service_config.shared {
                 // For nginx, generate config before starting
@@ -99,10 +301,23 @@ pub async fn create_scratch(
                 services::ensure_shared_service_running(config, docker, service_name).await?;
 
                 // For postgres, also create a database
-                if service_name == "postgres" && service_config.auto_create_db {
-                    let db_name = format!("scratch_{}", scratch_name);
-                    tracing::debug!("Creating database: {}", db_name);
-                    services::create_postgres_database(config, &db_name).await?;
+                if service_name == "postgres"
+                    && (service_config.auto_create_db || source_db.is_some())
+                {
+                    let db_name = format!("scratch_{}", scratch.name);
+                    report("database", format!("Provisioning database {}", db_name));
+                    if let Some(source_db) = source_db {
+                        tracing::debug!("Cloning database {} to {}", source_db, db_name);
+                        services::clone_postgres_database(config, source_db, &db_name).await?;
+                    } else {
+                        tracing::debug!("Creating database: {}", db_name);
+                        let template =
+                            services::seed_template(config, docker, scratch.profile.as_deref())
+                                .await?;
+                        services::create_postgres_database(config, &db_name, template.as_deref())
+                            .await?;
+                    }
+                    tx.record(CreateStep::Database(db_name.clone()));
                     databases
                         .entry(service_name.clone())
                         .or_default()
@@ -113,43 +328,184 @@ pub async fn create_scratch(
     }
     scratch.databases = databases;
 
+    // Build the images of services built from source and pick the images
+    // of the others
+    tx.record(CreateStep::Images);
+    super::build_scratch_images(config, docker, scratch, &scratch_dir).await?;
+    super::resolve_scratch_images(config, docker, scratch).await?;
+
     // Render and save compose file
     tracing::debug!("Rendering docker-compose file");
-    let compose = render_compose_file(config, &scratch)?;
+    report("compose", "Rendering compose file");
+    let compose = render_compose_file(config, scratch)?;
     let compose_path = scratch_dir.join("compose.yml");
     tracing::debug!("Saving docker-compose file to {}", compose_path.display());
+    tx.record(CreateStep::ComposeFile);
     compose.save(&compose_path)?;
 
     // Save scratch config
     tracing::debug!("Saving scratch configuration");
-    let scratch_config = ScratchConfig {
-        name: scratch.name.clone(),
-        branch: scratch.branch.clone(),
-        template: scratch.template.clone(),
-        services: scratch.services.clone(),
-        databases: scratch.databases.clone(),
-        env: scratch.env.clone(),
-        created_at: scratch.created_at,
-    };
-    let config_path = scratch_dir.join(".scratchpad.toml");
-    let config_content =
-        toml::to_string_pretty(&scratch_config).map_err(|e| Error::Config(e.to_string()))?;
-    fs::write(&config_path, config_content)?;
+    scratch_config.databases = scratch.databases.clone();
+    scratch_config.images = scratch.images.clone();
+    save_scratch_config(config, &scratch_config)?;
+
+    super::run_hooks(config, docker, scratch, HookPoint::PreCreate).await?;
 
-    // Start the scratch (run docker compose up)
-    tracing::info!("Starting containers for scratch '{}'", scratch_name);
+    // Start the scratch (run docker compose up). Recorded first so that
+    // containers left over from a partial start are torn down too.
+    report("pull", "Pulling images");
+    pull_scratch_images(&scratch_dir).await;
+
+    tracing::info!("Starting containers for scratch '{}'", scratch.name);
+    report("up", "Starting containers");
+    tx.record(CreateStep::Containers);
     start_scratch_compose(&scratch_dir).await?;
 
+    if wait_healthy {
+        report("health", "Waiting for services to become healthy");
+        super::wait_for_scratch_healthy(config, docker, &scratch.name).await?;
+    }
+
+    scratch_config.state = ScratchState::Running;
+    save_scratch_config(config, &scratch_config)?;
+
     // Update nginx config
     if config.nginx.enabled {
         tracing::debug!("Regenerating nginx configuration");
+        report("nginx", "Updating nginx configuration");
+        tx.record(CreateStep::Nginx);
         nginx::regenerate_config(config, docker).await?;
         tracing::debug!("Reloading nginx");
         nginx::reload(config, docker).await?;
     }
 
-    tracing::info!("Successfully created scratch: {}", scratch_name);
-    Ok(scratch)
+    super::run_hooks(config, docker, scratch, HookPoint::PostCreate).await
+}
+
+/// A completed step of scratch creation
+enum CreateStep {
+    Directory,
+    Database(String),
+    Images,
+    ComposeFile,
+    Containers,
+    Nginx,
+}
+
+/// Completed steps of a scratch creation, so they can be undone on failure
+struct CreateTransaction<'a> {
+    config: &'a Config,
+    docker: &'a DockerClient,
+    scratch_dir: PathBuf,
+    steps: Vec<CreateStep>,
+}
+
+impl<'a> CreateTransaction<'a> {
+    fn new(config: &'a Config, docker: &'a DockerClient, scratch_dir: &Path) -> Self {
+        Self {
+            config,
+            docker,
+            scratch_dir: scratch_dir.to_path_buf(),
+            steps: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, step: CreateStep) {
+        self.steps.push(step);
+    }
+
+    /// Undo the completed steps in reverse order and record the failure
+    ///
+    /// Rollback is best effort: a step that can't be undone is logged and
+    /// the remaining steps are still attempted.
+    async fn fail(self, scratch: &Scratch, error: &Error) {
+        tracing::error!("Failed to create scratch '{}': {}", scratch.name, error);
+
+        let mut regenerate_nginx = false;
+        for step in self.steps.iter().rev() {
+            let result = match step {
+                // Regenerated last, once the scratch is marked as failed
+                CreateStep::Nginx => {
+                    regenerate_nginx = true;
+                    Ok(())
+                }
+                CreateStep::Containers => stop_scratch_compose(&self.scratch_dir).await,
+                CreateStep::ComposeFile => {
+                    let compose_path = self.scratch_dir.join("compose.yml");
+                    if compose_path.exists() {
+                        fs::remove_file(compose_path).map_err(Error::from)
+                    } else {
+                        Ok(())
+                    }
+                }
+                CreateStep::Images => {
+                    super::remove_scratch_images(self.config, self.docker, scratch).await
+                }
+                CreateStep::Database(db) => services::drop_postgres_database(self.config, db).await,
+                CreateStep::Directory => {
+                    if self.scratch_dir.exists() {
+                        fs::remove_dir_all(&self.scratch_dir).map_err(Error::from)
+                    } else {
+                        Ok(())
+                    }
+                }
+            };
+
+            if let Err(e) = result {
+                tracing::warn!("Rollback of scratch '{}' incomplete: {}", scratch.name, e);
+            }
+        }
+
+        // Leave a marker behind so the failure shows up in the scratch list
+        let mut scratch_config = to_scratch_config(scratch);
+        scratch_config.databases = HashMap::new();
+        scratch_config.images = BTreeMap::new();
+        scratch_config.state = ScratchState::Failed;
+        scratch_config.error = Some(error.to_string());
+        let recorded = fs::create_dir_all(&self.scratch_dir)
+            .map_err(Error::from)
+            .and_then(|_| save_scratch_config(self.config, &scratch_config));
+        if let Err(e) = recorded {
+            tracing::warn!(
+                "Failed to record failure of scratch '{}': {}",
+                scratch.name,
+                e
+            );
+        }
+
+        if regenerate_nginx {
+            let reloaded = match nginx::regenerate_config(self.config, self.docker).await {
+                Ok(()) => nginx::reload(self.config, self.docker).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = reloaded {
+                tracing::warn!("Failed to restore nginx config: {}", e);
+            }
+        }
+    }
+}
+
+/// Build the stored configuration for a scratch
+fn to_scratch_config(scratch: &Scratch) -> ScratchConfig {
+    ScratchConfig {
+        name: scratch.name.clone(),
+        branch: scratch.branch.clone(),
+        branches: scratch.branches.clone(),
+        template: scratch.template.clone(),
+        profile: scratch.profile.clone(),
+        services: scratch.services.clone(),
+        databases: scratch.databases.clone(),
+        env: scratch.env.clone(),
+        images: scratch.images.clone(),
+        created_at: scratch.created_at,
+        expires_at: scratch.expires_at,
+        last_activity_at: None,
+        state: ScratchState::default(),
+        owner: scratch.owner.clone(),
+        description: scratch.description.clone(),
+        tags: scratch.tags.clone(),
+        error: None,
+    }
 }
 
 /// Create the directory structure for a scratch
@@ -160,8 +516,28 @@ fn create_scratch_directories(scratch_dir: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Load the stored configuration of a scratch from the state store
+pub fn load_scratch_config(config: &Config, name: &str) -> Result<ScratchConfig> {
+    StateStore::for_config(config)?
+        .get(name)?
+        .ok_or_else(|| Error::ScratchNotFound(name.to_string()))
+}
+
+/// Save the configuration of a scratch to the state store
+///
+/// The scratch's `.scratchpad.toml` is written as well when `store.write_toml` is set.
+pub fn save_scratch_config(config: &Config, scratch_config: &ScratchConfig) -> Result<()> {
+    StateStore::for_config(config)?.put(scratch_config)?;
+
+    let scratch_dir = config.server.releases_dir.join(&scratch_config.name);
+    if config.store.write_toml && scratch_dir.exists() {
+        store::write_config_file(&scratch_dir, scratch_config)?;
+    }
+    Ok(())
+}
+
 /// Render the docker-compose file for a scratch
-fn render_compose_file(config: &Config, scratch: &Scratch) -> Result<ComposeFile> {
+pub(super) fn render_compose_file(config: &Config, scratch: &Scratch) -> Result<ComposeFile> {
     use super::template::render_template;
 
     let compose_content = render_template(config, scratch)?;
@@ -174,31 +550,24 @@ fn render_compose_file(config: &Config, scratch: &Scratch) -> Result<ComposeFile
     Ok(compose)
 }
 
-/// Start a scratch using docker compose
-async fn start_scratch_compose(scratch_dir: &Path) -> Result<()> {
-    use tokio::process::Command;
-
-    tracing::debug!("Running 'docker compose up' in {}", scratch_dir.display());
-    let output = Command::new("docker")
-        .args(["compose", "up", "-d"])
-        .current_dir(scratch_dir)
-        .output()
-        .await?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        tracing::error!("Docker compose up failed: {}", stderr);
-        return Err(Error::Other(format!("Failed to start scratch: {}", stderr)));
+/// Pull the images of a scratch ahead of `docker compose up`
+///
+/// Failures are only logged: images that can't be pulled may exist locally,
+/// and `docker compose up` reports anything that is really missing.
+async fn pull_scratch_images(scratch_dir: &Path) {
+    let args = ["pull", "--ignore-pull-failures", "--ignore-buildable"];
+    if let Err(e) = run_compose(scratch_dir, &args).await {
+        tracing::warn!("Continuing without pulling images: {}", e);
     }
+}
 
-    tracing::debug!("Docker compose up completed successfully");
-    Ok(())
+/// Start a scratch using docker compose
+async fn start_scratch_compose(scratch_dir: &Path) -> Result<()> {
+    run_compose(scratch_dir, &["up", "-d"]).await
 }
 
 /// Stop a scratch using docker compose
 async fn stop_scratch_compose(scratch_dir: &Path) -> Result<()> {
-    use tokio::process::Command;
-
     // Check if compose.yml exists - if not, nothing to stop
     let compose_path = scratch_dir.join("compose.yml");
     if !compose_path.exists() {
@@ -209,33 +578,64 @@ async fn stop_scratch_compose(scratch_dir: &Path) -> Result<()> {
         return Ok(());
     }
 
-    tracing::debug!("Running 'docker compose down' in {}", scratch_dir.display());
+    run_compose(scratch_dir, &["down"]).await
+}
+
+/// Run `docker compose` in a scratch directory
+pub(super) async fn run_compose(scratch_dir: &Path, args: &[&str]) -> Result<()> {
+    use tokio::process::Command;
+
+    tracing::debug!(
+        "Running 'docker compose {}' in {}",
+        args.join(" "),
+        scratch_dir.display()
+    );
     let output = Command::new("docker")
-        .args(["compose", "down"])
+        .arg("compose")
+        .args(args)
         .current_dir(scratch_dir)
         .output()
         .await?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        tracing::error!("Docker compose down failed: {}", stderr);
-        return Err(Error::Other(format!("Failed to stop scratch: {}", stderr)));
+        tracing::error!("Docker compose {} failed: {}", args.join(" "), stderr);
+        return Err(Error::Other(format!(
+            "docker compose {} failed: {}",
+            args.join(" "),
+            stderr
+        )));
     }
-
-    tracing::debug!("Docker compose down completed successfully");
     Ok(())
 }
 
 /// Start a stopped scratch
-pub async fn start_scratch(config: &Config, _docker: &DockerClient, name: &str) -> Result<()> {
+pub async fn start_scratch(config: &Config, docker: &DockerClient, name: &str) -> Result<()> {
     let scratch_dir = config.server.releases_dir.join(name);
 
     if !scratch_dir.exists() {
         return Err(Error::ScratchNotFound(name.to_string()));
     }
 
+    let _lock = ScratchLock::acquire(config, name, "start")?;
+    start_scratch_locked(config, name).await?;
+    run_post_start_hooks(config, docker, name).await
+}
+
+/// Start a scratch whose lock the caller already holds
+pub(super) async fn start_scratch_locked(config: &Config, name: &str) -> Result<()> {
+    let scratch_dir = config.server.releases_dir.join(name);
+
     tracing::info!("Starting scratch: {}", name);
+    report("up", "Starting containers");
     start_scratch_compose(&scratch_dir).await?;
+
+    // Starting counts as activity so the idle monitor doesn't stop it straight away
+    if let Ok(mut scratch_config) = load_scratch_config(config, name) {
+        scratch_config.state = ScratchState::Running;
+        scratch_config.last_activity_at = Some(chrono::Utc::now());
+        save_scratch_config(config, &scratch_config)?;
+    }
     tracing::info!("Successfully started scratch: {}", name);
     Ok(())
 }
@@ -248,106 +648,156 @@ pub async fn stop_scratch(config: &Config, _docker: &DockerClient, name: &str) -
         return Err(Error::ScratchNotFound(name.to_string()));
     }
 
+    let _lock = ScratchLock::acquire(config, name, "stop")?;
+    stop_scratch_locked(config, name).await
+}
+
+/// Stop a scratch whose lock the caller already holds
+pub(super) async fn stop_scratch_locked(config: &Config, name: &str) -> Result<()> {
+    let scratch_dir = config.server.releases_dir.join(name);
+
     tracing::info!("Stopping scratch: {}", name);
+    report("down", "Stopping containers");
     stop_scratch_compose(&scratch_dir).await?;
+
+    if let Ok(mut scratch_config) = load_scratch_config(config, name) {
+        scratch_config.state = ScratchState::Stopped;
+        save_scratch_config(config, &scratch_config)?;
+    }
     tracing::info!("Successfully stopped scratch: {}", name);
     Ok(())
 }
 
 /// Restart a scratch
 pub async fn restart_scratch(config: &Config, docker: &DockerClient, name: &str) -> Result<()> {
-    stop_scratch(config, docker, name).await?;
-    start_scratch(config, docker, name).await?;
-    Ok(())
+    if !config.server.releases_dir.join(name).exists() {
+        return Err(Error::ScratchNotFound(name.to_string()));
+    }
+
+    let _lock = ScratchLock::acquire(config, name, "restart")?;
+    stop_scratch_locked(config, name).await?;
+    start_scratch_locked(config, name).await?;
+    run_post_start_hooks(config, docker, name).await
 }
 
-/// Update a scratch environment (regenerate compose.yml from current config)
-pub async fn update_scratch(config: &Config, _docker: &DockerClient, name: &str) -> Result<()> {
-    let scratch_dir = config.server.releases_dir.join(name);
+/// Run the `post_start` hooks of a scratch that has just been started
+async fn run_post_start_hooks(config: &Config, docker: &DockerClient, name: &str) -> Result<()> {
+    let scratch = load_scratch(config, name)?;
+    super::run_hooks(config, docker, &scratch, HookPoint::PostStart).await
+}
 
-    if !scratch_dir.exists() {
-        return Err(Error::ScratchNotFound(name.to_string()));
-    }
+/// Load an existing scratch from its stored configuration
+pub fn load_scratch(config: &Config, name: &str) -> Result<Scratch> {
+    load_scratch_config(config, name).map(Scratch::from)
+}
 
-    // Load existing scratch config
-    let scratch_config_path = scratch_dir.join(".scratchpad.toml");
-    if !scratch_config_path.exists() {
-        return Err(Error::Config(format!(
-            "Scratch config not found at {}",
-            scratch_config_path.display()
-        )));
+impl From<ScratchConfig> for Scratch {
+    fn from(scratch_config: ScratchConfig) -> Self {
+        Scratch {
+            name: scratch_config.name,
+            branch: scratch_config.branch,
+            branches: scratch_config.branches,
+            template: scratch_config.template,
+            profile: scratch_config.profile,
+            owner: scratch_config.owner,
+            description: scratch_config.description,
+            tags: scratch_config.tags,
+            services: scratch_config.services,
+            databases: scratch_config.databases,
+            env: scratch_config.env,
+            images: scratch_config.images,
+            created_at: scratch_config.created_at,
+            expires_at: scratch_config.expires_at,
+        }
     }
+}
 
-    let content = fs::read_to_string(&scratch_config_path)?;
-    let scratch_config: ScratchConfig = toml::from_str(&content)
-        .map_err(|e| Error::Config(format!("Failed to parse scratch config: {}", e)))?;
-
-    // Rebuild the Scratch struct
-    let scratch = Scratch {
-        name: scratch_config.name.clone(),
-        branch: scratch_config.branch.clone(),
-        template: scratch_config.template.clone(),
-        services: scratch_config.services.clone(),
-        databases: scratch_config.databases.clone(),
-        env: scratch_config.env.clone(),
-        created_at: scratch_config.created_at,
-    };
+/// Update a scratch environment (regenerate compose.yml from current config)
+pub async fn update_scratch(config: &Config, docker: &DockerClient, name: &str) -> Result<()> {
+    let scratch_dir = config.server.releases_dir.join(name);
+    let _lock = ScratchLock::acquire(config, name, "update")?;
+    let mut scratch = load_scratch(config, name)?;
+    super::refresh_scratch_images(config, docker, &mut scratch).await?;
 
     // Re-render compose file with current global config
     tracing::debug!("Re-rendering compose file for scratch: {}", name);
+    report("compose", "Rendering compose file");
     let compose = render_compose_file(config, &scratch)?;
     let compose_path = scratch_dir.join("compose.yml");
     compose.save(&compose_path)?;
 
     tracing::info!("Updated scratch compose file: {}", name);
-    Ok(())
+    super::run_hooks(config, docker, &scratch, HookPoint::PostUpdate).await
 }
 
 /// Delete a scratch environment
+///
+/// A failing `pre_delete` hook stops the delete unless `force` is set, in
+/// which case it's only logged.
 pub async fn delete_scratch(
     config: &Config,
     docker: &DockerClient,
     name: &str,
-    _force: bool,
+    force: bool,
 ) -> Result<()> {
     let scratch_dir = config.server.releases_dir.join(name);
+    let store = StateStore::for_config(config)?;
+    let _lock = ScratchLock::acquire(config, name, "delete")?;
+    let stored = store.get(name)?;
 
-    if !scratch_dir.exists() {
+    if stored.is_none() && !scratch_dir.exists() {
         return Err(Error::ScratchNotFound(name.to_string()));
     }
 
     tracing::info!("Deleting scratch: {}", name);
 
-    // Load scratch config to find databases
-    let config_path = scratch_dir.join(".scratchpad.toml");
-    if config_path.exists() {
-        let content = fs::read_to_string(&config_path)?;
-        if let Ok(scratch_config) = toml::from_str::<ScratchConfig>(&content) {
-            // Drop databases
-            for (service, dbs) in &scratch_config.databases {
-                if service == "postgres" {
-                    for db in dbs {
-                        tracing::debug!("Dropping database: {}", db);
-                        if let Err(e) = services::drop_postgres_database(config, db).await {
-                            tracing::warn!("Failed to drop database {}: {}", db, e);
-                        }
+    if let Some(scratch_config) = &stored {
+        let scratch = Scratch::from(scratch_config.clone());
+        match super::run_hooks(config, docker, &scratch, HookPoint::PreDelete).await {
+            Err(e) if force => {
+                tracing::warn!("Deleting scratch '{}' anyway: {}", name, e);
+            }
+            result => result?,
+        }
+    }
+
+    // Drop databases
+    report("database", "Dropping databases");
+    if let Some(scratch_config) = &stored {
+        for (service, dbs) in &scratch_config.databases {
+            if service == "postgres" {
+                for db in dbs {
+                    tracing::debug!("Dropping database: {}", db);
+                    if let Err(e) = services::drop_postgres_database(config, db).await {
+                        tracing::warn!("Failed to drop database {}: {}", db, e);
                     }
                 }
             }
         }
     }
 
-    // Stop containers
-    tracing::debug!("Stopping containers");
-    stop_scratch_compose(&scratch_dir).await?;
-
-    // Remove directory
-    tracing::debug!("Removing scratch directory");
-    fs::remove_dir_all(&scratch_dir)?;
+    if scratch_dir.exists() {
+        // Stop containers
+        tracing::debug!("Stopping containers");
+        report("down", "Stopping containers");
+        stop_scratch_compose(&scratch_dir).await?;
+
+        // Remove directory (including the checked out sources)
+        tracing::debug!("Removing scratch directory");
+        fs::remove_dir_all(&scratch_dir)?;
+    }
+    if let Some(scratch_config) = stored {
+        let scratch = Scratch::from(scratch_config);
+        if let Err(e) = super::remove_scratch_images(config, docker, &scratch).await {
+            tracing::warn!("Failed to remove images of scratch '{}': {}", name, e);
+        }
+    }
+    store.delete(name)?;
 
     // Update nginx config
     if config.nginx.enabled {
         tracing::debug!("Regenerating nginx configuration");
+        report("nginx", "Updating nginx configuration");
         nginx::regenerate_config(config, docker).await?;
         tracing::debug!("Reloading nginx");
         nginx::reload(config, docker).await?;
@@ -359,90 +809,137 @@ pub async fn delete_scratch(
 
 /// List all scratches with their status
 pub async fn list_scratches(config: &Config, docker: &DockerClient) -> Result<Vec<ScratchStatus>> {
-    let releases_dir = &config.server.releases_dir;
-
-    if !releases_dir.exists() {
+    let scratches = StateStore::for_config(config)?.list()?;
+    if scratches.is_empty() {
         return Ok(Vec::new());
     }
 
-    let mut scratches = Vec::new();
-
     // Get container statuses
     let containers = docker.list_scratch_containers(None).await?;
 
-    // Read all scratch directories
-    for entry in fs::read_dir(releases_dir)? {
-        let entry = entry?;
-        if !entry.file_type()?.is_dir() {
-            continue;
-        }
+    Ok(scratches
+        .into_iter()
+        .map(|scratch_config| build_status(config, scratch_config, &containers))
+        .collect())
+}
 
-        let name = entry.file_name().to_string_lossy().to_string();
-        let config_path = entry.path().join(".scratchpad.toml");
-
-        let mut status = ScratchStatus::new(name.clone(), name.clone());
-
-        // Load config if exists
-        if config_path.exists() {
-            if let Ok(content) = fs::read_to_string(&config_path) {
-                if let Ok(scratch_config) = toml::from_str::<ScratchConfig>(&content) {
-                    status.branch = scratch_config.branch;
-                    status.created_at = Some(scratch_config.created_at);
-                    status.databases = scratch_config
-                        .databases
-                        .values()
-                        .flatten()
-                        .cloned()
-                        .collect();
-                }
-            }
-        }
+/// Get status of a single scratch
+pub async fn get_scratch_status(
+    config: &Config,
+    docker: &DockerClient,
+    name: &str,
+) -> Result<ScratchStatus> {
+    let scratch_config = load_scratch_config(config, name)?;
+    let containers = docker.list_scratch_containers(Some(name)).await?;
+    Ok(build_status(config, scratch_config, &containers))
+}
 
-        // Get service statuses from containers
-        for container in &containers {
-            if let Some(scratch_name) = container
-                .labels
-                .get(&format!("{}.scratch", config.docker.label_prefix))
-            {
-                if scratch_name == &name {
-                    if let Some(service_name) = container
-                        .labels
-                        .get(&format!("{}.service", config.docker.label_prefix))
-                    {
-                        status
-                            .services
-                            .insert(service_name.clone(), container.state.clone());
-                    }
-                }
+/// Combine a stored scratch with the state of its containers
+fn build_status(
+    config: &Config,
+    scratch_config: ScratchConfig,
+    containers: &[ContainerStatus],
+) -> ScratchStatus {
+    let outdated = is_outdated(config, &scratch_config);
+    let name = scratch_config.name;
+    let mut status = ScratchStatus::new(name.clone(), scratch_config.branch);
+    status.outdated = outdated;
+    status.branches = scratch_config.branches;
+    status.images = scratch_config.images;
+    status.created_at = Some(scratch_config.created_at);
+    status.expires_at = scratch_config.expires_at;
+    status.last_activity_at = scratch_config.last_activity_at;
+    status.error = scratch_config.error;
+    status.owner = scratch_config.owner;
+    status.description = scratch_config.description;
+    status.tags = scratch_config.tags;
+    status.databases = scratch_config
+        .databases
+        .values()
+        .flatten()
+        .cloned()
+        .collect();
+
+    // Get service statuses from containers
+    let scratch_label = format!("{}.scratch", config.docker.label_prefix);
+    let service_label = format!("{}.service", config.docker.label_prefix);
+    for container in containers {
+        if container.labels.get(&scratch_label) == Some(&name) {
+            if let Some(service_name) = container.labels.get(&service_label) {
+                status
+                    .services
+                    .insert(service_name.clone(), container.state.clone());
             }
         }
+    }
 
-        // Calculate overall status
-        status.calculate_status();
-
-        // Set URL
-        if config.nginx.enabled {
-            status.url = Some(format!("http://{}.{}", name, config.nginx.domain));
-        }
-
-        scratches.push(status);
+    // Calculate overall status
+    status.calculate_status();
+    if scratch_config.state == ScratchState::Creating && status.services.is_empty() {
+        status.status = "creating".to_string();
     }
 
-    // Sort by name
-    scratches.sort_by(|a, b| a.name.cmp(&b.name));
+    // Set URL
+    if config.nginx.enabled {
+        status.url = Some(format!("http://{}.{}", name, config.nginx.domain));
+    }
 
-    Ok(scratches)
+    status
 }
 
-/// Get status of a single scratch
-pub async fn get_scratch_status(
-    config: &Config,
-    docker: &DockerClient,
-    name: &str,
-) -> Result<ScratchStatus> {
-    let scratches = list_scratches(config, docker).await?;
-    scratches
-        .into_iter()
-        .find(|s| s.name == name)
-        .ok_or_else(|| Error::ScratchNotFound(name.to_string()))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ensure_scratch_available_replaces_failed_scratch() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.server.releases_dir = temp.path().to_path_buf();
+        let scratch_dir = temp.path().join("feature-x");
+        assert!(ensure_scratch_available(&config, "feature-x").is_ok());
+
+        // A directory without a record is left alone
+        fs::create_dir_all(&scratch_dir).unwrap();
+        assert!(matches!(
+            ensure_scratch_available(&config, "feature-x"),
+            Err(Error::ScratchAlreadyExists(_))
+        ));
+
+        let scratch = Scratch::new(
+            "feature-x".to_string(),
+            "feature/x".to_string(),
+            "default".to_string(),
+        );
+        save_scratch_config(&config, &to_scratch_config(&scratch)).unwrap();
+        assert!(scratch_dir.join(".scratchpad.toml").exists());
+        assert!(matches!(
+            ensure_scratch_available(&config, "feature-x"),
+            Err(Error::ScratchAlreadyExists(_))
+        ));
+
+        let mut failed = to_scratch_config(&scratch);
+        failed.state = ScratchState::Failed;
+        failed.error = Some("docker compose up failed".to_string());
+        save_scratch_config(&config, &failed).unwrap();
+        assert!(ensure_scratch_available(&config, "feature-x").is_ok());
+        assert!(!scratch_dir.exists());
+        assert!(matches!(
+            load_scratch_config(&config, "feature-x"),
+            Err(Error::ScratchNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_clone_scratch_onto_itself_is_rejected() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.server.releases_dir = temp.path().to_path_buf();
+        let docker = DockerClient::new(config.docker.clone()).unwrap();
+
+        let result = clone_scratch(&config, &docker, "feature-x", "feature-x", None).await;
+        assert!(matches!(result, Err(Error::InvalidScratchName(_))));
+        // Rejected before locking, so no lock file is left behind
+        assert!(!temp.path().join(".locks").exists());
+    }
 }
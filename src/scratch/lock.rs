@@ -0,0 +1,259 @@
+//! Per-scratch operation locks
+//!
+//! Operations that change a scratch (create, delete, start, stop, ...) hold a
+//! lock file for the scratch while they run, so the server, webhooks and CLI
+//! invocations can't mutate the same scratch at once. Lock files live in
+//! `releases_dir/.locks/<name>.lock` rather than the scratch directory itself,
+//! since create runs before that directory exists and delete removes it.
+//!
+//! A lock is stale, and taken over, when the process that wrote it is no
+//! longer running or it is older than [`MAX_LOCK_AGE`].
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+
+/// Locks older than this are considered abandoned even if their process lives on
+pub const MAX_LOCK_AGE: Duration = Duration::hours(6);
+
+/// Directory under the releases dir holding the lock files
+const LOCKS_DIR: &str = ".locks";
+
+/// Keeps temp and stale lock files of concurrent acquires in one process apart
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Contents of a lock file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct LockInfo {
+    pid: u32,
+    operation: String,
+    acquired_at: DateTime<Utc>,
+}
+
+/// A held lock on a scratch, released when dropped
+#[derive(Debug)]
+pub struct ScratchLock {
+    path: PathBuf,
+    info: LockInfo,
+}
+
+impl ScratchLock {
+    /// Lock a scratch for an operation
+    ///
+    /// Fails with [`Error::ScratchBusy`] if another live operation holds it.
+    pub fn acquire(config: &Config, name: &str, operation: &str) -> Result<Self> {
+        let path = lock_path(config, name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let info = LockInfo {
+            pid: std::process::id(),
+            operation: operation.to_string(),
+            acquired_at: Utc::now(),
+        };
+
+        // Write the lock info to a temp file and hard link it into place, so
+        // the lock file never exists without its contents
+        let content = toml::to_string(&info).map_err(|e| Error::Other(e.to_string()))?;
+        let temp = unique_path(&path, "tmp");
+        fs::write(&temp, content)?;
+        let result = link_lock(&temp, &path, name);
+        let _ = fs::remove_file(&temp);
+
+        result?;
+        tracing::debug!("Locked scratch '{}' for {}", name, operation);
+        Ok(Self { path, info })
+    }
+
+    /// Fail with [`Error::ScratchBusy`] if a scratch is currently locked
+    ///
+    /// Used to reject conflicting requests up front; it doesn't take the lock.
+    pub fn check(config: &Config, name: &str) -> Result<()> {
+        match read_lock(&lock_path(config, name)) {
+            Some(holder) if !is_stale(&holder) => {
+                Err(Error::ScratchBusy(name.to_string(), holder.operation))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for ScratchLock {
+    fn drop(&mut self) {
+        // A lock taken over as stale belongs to someone else now
+        if read_lock(&self.path).as_ref() != Some(&self.info) {
+            tracing::warn!(
+                "Lock {} was taken over, leaving it in place",
+                self.path.display()
+            );
+            return;
+        }
+        if let Err(e) = remove_lock(&self.path) {
+            tracing::warn!("Failed to release lock {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Link a written lock file into place, clearing a stale lock once
+fn link_lock(temp: &Path, path: &Path, name: &str) -> Result<()> {
+    for _ in 0..2 {
+        match fs::hard_link(temp, path) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => match read_lock(path) {
+                Some(holder) if !is_stale(&holder) => {
+                    return Err(Error::ScratchBusy(name.to_string(), holder.operation));
+                }
+                _ => {
+                    tracing::warn!("Removing stale lock on scratch '{}'", name);
+                    clear_stale_lock(path, name)?;
+                }
+            },
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Err(Error::ScratchBusy(name.to_string(), "unknown".to_string()))
+}
+
+/// Move a stale lock out of the way and delete it
+///
+/// The lock is renamed first, so a live lock that replaced the stale one
+/// since it was read is never deleted in place; if the renamed file turns
+/// out to be live, it is put back.
+fn clear_stale_lock(path: &Path, name: &str) -> Result<()> {
+    let stale = unique_path(path, "stale");
+    match fs::rename(path, &stale) {
+        Ok(()) => {}
+        // Someone else cleared it first
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    }
+
+    let result = match read_lock(&stale) {
+        Some(holder) if !is_stale(&holder) => {
+            // Put it back unless yet another lock has been taken meanwhile
+            let _ = fs::hard_link(&stale, path);
+            Err(Error::ScratchBusy(name.to_string(), holder.operation))
+        }
+        _ => Ok(()),
+    };
+    remove_lock(&stale)?;
+    result
+}
+
+/// A path next to a lock file that no other acquire uses
+fn unique_path(path: &Path, suffix: &str) -> PathBuf {
+    path.with_extension(format!(
+        "lock.{}-{}.{}",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed),
+        suffix
+    ))
+}
+
+fn lock_path(config: &Config, name: &str) -> PathBuf {
+    config
+        .server
+        .releases_dir
+        .join(LOCKS_DIR)
+        .join(format!("{}.lock", name))
+}
+
+/// Read a lock file; `None` if it is missing or unreadable
+fn read_lock(path: &Path) -> Option<LockInfo> {
+    let content = fs::read_to_string(path).ok()?;
+    toml::from_str(&content).ok()
+}
+
+fn remove_lock(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn is_stale(holder: &LockInfo) -> bool {
+    Utc::now() - holder.acquired_at > MAX_LOCK_AGE || !process_alive(holder.pid)
+}
+
+/// Check whether a process is still running
+fn process_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+
+    let proc = Path::new("/proc");
+    if proc.exists() {
+        return proc.join(pid.to_string()).exists();
+    }
+
+    // No procfs (e.g. macOS): ask kill whether the pid exists
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_conflicts_until_released() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.server.releases_dir = temp.path().to_path_buf();
+
+        let lock = ScratchLock::acquire(&config, "feature-x", "delete").unwrap();
+        assert!(matches!(
+            ScratchLock::acquire(&config, "feature-x", "create"),
+            Err(Error::ScratchBusy(ref name, ref op)) if name == "feature-x" && op == "delete"
+        ));
+        assert!(ScratchLock::check(&config, "feature-x").is_err());
+        assert!(ScratchLock::check(&config, "other").is_ok());
+
+        drop(lock);
+        assert!(ScratchLock::check(&config, "feature-x").is_ok());
+        assert!(ScratchLock::acquire(&config, "feature-x", "create").is_ok());
+    }
+
+    #[test]
+    fn test_stale_lock_is_taken_over() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.server.releases_dir = temp.path().to_path_buf();
+
+        let path = lock_path(&config, "feature-x");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let old = LockInfo {
+            pid: std::process::id(),
+            operation: "create".to_string(),
+            acquired_at: Utc::now() - MAX_LOCK_AGE - Duration::minutes(1),
+        };
+        fs::write(&path, toml::to_string(&old).unwrap()).unwrap();
+        assert!(ScratchLock::check(&config, "feature-x").is_ok());
+
+        let lock = ScratchLock::acquire(&config, "feature-x", "delete").unwrap();
+        assert_eq!(read_lock(&path).unwrap().operation, "delete");
+        // Only the new lock is left, the stale one was moved away and deleted
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+
+        // A holder whose lock was taken over in turn doesn't release the new one
+        let mut newer = LockInfo {
+            operation: "restart".to_string(),
+            ..old
+        };
+        newer.acquired_at = Utc::now();
+        fs::write(&path, toml::to_string(&newer).unwrap()).unwrap();
+        drop(lock);
+        assert_eq!(read_lock(&path).unwrap(), newer);
+    }
+}
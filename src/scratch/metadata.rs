@@ -0,0 +1,159 @@
+//! Scratch metadata (owner, description, tags) and list filters
+
+use serde::Deserialize;
+use std::collections::BTreeSet;
+
+use crate::config::{Config, ScratchConfig};
+use crate::error::{Error, Result};
+
+use super::{load_scratch_config, save_scratch_config, ScratchLock, ScratchStatus};
+
+/// Criteria for narrowing down a list of scratches
+///
+/// Unset criteria match everything; a scratch must carry every listed tag.
+/// The branch matches a scratch's main branch or any of its repos' branches.
+#[derive(Debug, Clone, Default)]
+pub struct ScratchFilter {
+    pub owner: Option<String>,
+    pub tags: Vec<String>,
+    pub status: Option<String>,
+    pub branch: Option<String>,
+}
+
+impl ScratchFilter {
+    /// Whether a scratch matches the filter
+    pub fn matches(&self, scratch: &ScratchStatus) -> bool {
+        self.owner
+            .as_ref()
+            .is_none_or(|owner| scratch.owner.as_ref() == Some(owner))
+            && self.tags.iter().all(|tag| scratch.tags.contains(tag))
+            && self
+                .status
+                .as_ref()
+                .is_none_or(|status| &scratch.status == status)
+            && self.branch.as_ref().is_none_or(|branch| {
+                &scratch.branch == branch || scratch.branches.values().any(|b| b == branch)
+            })
+    }
+}
+
+/// Changes to a scratch's metadata; unset fields are left alone
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ScratchMetadataUpdate {
+    /// New owner, or an empty string to clear it
+    pub owner: Option<String>,
+    /// New description, or an empty string to clear it
+    pub description: Option<String>,
+    /// Replacement set of tags
+    pub tags: Option<BTreeSet<String>>,
+}
+
+/// Check and normalize a set of tags
+///
+/// Tags are trimmed; they can't be empty or contain whitespace or commas.
+pub fn validate_tags(tags: impl IntoIterator<Item = String>) -> Result<BTreeSet<String>> {
+    tags.into_iter()
+        .map(|tag| {
+            let tag = tag.trim();
+            if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ',') {
+                Err(Error::Other(format!("Invalid tag: '{}'", tag)))
+            } else {
+                Ok(tag.to_string())
+            }
+        })
+        .collect()
+}
+
+/// Apply a metadata update to a scratch and persist it
+pub fn update_scratch_metadata(
+    config: &Config,
+    name: &str,
+    update: ScratchMetadataUpdate,
+) -> Result<ScratchConfig> {
+    let _lock = ScratchLock::acquire(config, name, "metadata")?;
+    let mut scratch_config = load_scratch_config(config, name)?;
+
+    if let Some(owner) = update.owner {
+        scratch_config.owner = non_empty(owner);
+    }
+    if let Some(description) = update.description {
+        scratch_config.description = non_empty(description);
+    }
+    if let Some(tags) = update.tags {
+        scratch_config.tags = validate_tags(tags)?;
+    }
+    save_scratch_config(config, &scratch_config)?;
+
+    Ok(scratch_config)
+}
+
+fn non_empty(value: String) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(name: &str, owner: Option<&str>, tags: &[&str], status: &str) -> ScratchStatus {
+        let mut scratch = ScratchStatus::new(name.to_string(), format!("feature/{}", name));
+        scratch.owner = owner.map(str::to_string);
+        scratch.tags = tags.iter().map(|t| t.to_string()).collect();
+        scratch.status = status.to_string();
+        scratch
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let a = status("a", Some("alice"), &["demo", "qa"], "running");
+        let b = status("b", None, &["qa"], "stopped");
+
+        assert!(ScratchFilter::default().matches(&a));
+        assert!(ScratchFilter::default().matches(&b));
+
+        let by_owner = ScratchFilter {
+            owner: Some("alice".to_string()),
+            ..Default::default()
+        };
+        assert!(by_owner.matches(&a));
+        assert!(!by_owner.matches(&b));
+
+        let by_tags = ScratchFilter {
+            tags: vec!["qa".to_string(), "demo".to_string()],
+            ..Default::default()
+        };
+        assert!(by_tags.matches(&a));
+        assert!(!by_tags.matches(&b));
+
+        let by_status_and_branch = ScratchFilter {
+            status: Some("stopped".to_string()),
+            branch: Some("feature/b".to_string()),
+            ..Default::default()
+        };
+        assert!(!by_status_and_branch.matches(&a));
+        assert!(by_status_and_branch.matches(&b));
+
+        let mut c = status("c", None, &[], "running");
+        c.branches
+            .insert("web".to_string(), "feature/b".to_string());
+        let by_branch = ScratchFilter {
+            branch: Some("feature/b".to_string()),
+            ..Default::default()
+        };
+        assert!(by_branch.matches(&c));
+    }
+
+    #[test]
+    fn test_validate_tags() {
+        let tags = validate_tags([" qa ".to_string(), "demo".to_string(), "qa".to_string()]);
+        assert_eq!(
+            tags.unwrap().into_iter().collect::<Vec<_>>(),
+            ["demo", "qa"]
+        );
+
+        assert!(validate_tags(["".to_string()]).is_err());
+        assert!(validate_tags(["two words".to_string()]).is_err());
+        assert!(validate_tags(["a,b".to_string()]).is_err());
+    }
+}
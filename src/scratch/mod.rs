@@ -1,25 +1,76 @@
 //! Scratch environment management
 
+mod build;
+mod bulk;
+mod drift;
+mod env;
+mod expiry;
+mod gc;
+mod health;
+mod hooks;
+mod idle;
+mod images;
 mod lifecycle;
+mod lock;
+mod metadata;
+mod progress;
+mod quota;
+mod rolling;
+mod snapshot;
+mod stats;
 mod status;
 mod template;
+mod watch;
 
+pub use build::*;
+pub use bulk::*;
+pub use drift::*;
+pub use env::*;
+pub use expiry::*;
+pub use gc::*;
+pub use health::*;
+pub use hooks::*;
+pub use idle::*;
+pub use images::*;
 pub use lifecycle::*;
+pub use lock::*;
+pub use metadata::*;
+pub use progress::*;
+pub use quota::*;
+pub use rolling::*;
+pub use snapshot::*;
+pub use stats::*;
 pub use status::*;
+pub use template::*;
+pub use watch::*;
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::error::{Error, Result};
 
 /// Represents a scratch environment
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Scratch {
     pub name: String,
     pub branch: String,
+    /// Branch per repository, for services that don't run `branch`
+    #[serde(default)]
+    pub branches: BTreeMap<String, String>,
     pub template: String,
+    pub profile: Option<String>,
+    pub owner: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
     pub services: Vec<String>,
     pub databases: HashMap<String, Vec<String>>,
     pub env: HashMap<String, String>,
+    /// Image each per-scratch service runs, as resolved or built
+    #[serde(default)]
+    pub images: BTreeMap<String, String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl Scratch {
@@ -28,11 +79,75 @@ impl Scratch {
         Self {
             name,
             branch,
+            branches: BTreeMap::new(),
             template,
+            profile: None,
+            owner: None,
+            description: None,
+            tags: BTreeSet::new(),
             services: Vec::new(),
             databases: HashMap::new(),
             env: HashMap::new(),
+            images: BTreeMap::new(),
             created_at: chrono::Utc::now(),
+            expires_at: None,
+        }
+    }
+
+    /// The branch a service of the given repository runs
+    ///
+    /// Services without a repository, or whose repository has no branch of
+    /// its own, run the scratch's main branch.
+    pub fn branch_for(&self, repo: Option<&str>) -> &str {
+        repo.and_then(|repo| self.branches.get(repo))
+            .unwrap_or(&self.branch)
+    }
+
+    /// Split `--branch` values into the main branch and a branch per repository
+    ///
+    /// Values are either a plain branch or `repo=branch`. A plain branch is
+    /// the main branch; without one, the first repository's branch is.
+    pub fn parse_branch_args(args: &[String]) -> Result<(String, BTreeMap<String, String>)> {
+        let mut main = None;
+        let mut first = None;
+        let mut branches = BTreeMap::new();
+
+        for arg in args {
+            match arg.split_once('=') {
+                Some((repo, branch)) => {
+                    let (repo, branch) = (repo.trim(), branch.trim());
+                    if repo.is_empty() || branch.is_empty() {
+                        return Err(Error::Other(format!(
+                            "Invalid branch '{}': expected REPO=BRANCH",
+                            arg
+                        )));
+                    }
+                    if branches
+                        .insert(repo.to_string(), branch.to_string())
+                        .is_some()
+                    {
+                        return Err(Error::Other(format!(
+                            "Branch for repository '{}' given more than once",
+                            repo
+                        )));
+                    }
+                    first.get_or_insert_with(|| branch.to_string());
+                }
+                None if main.is_some() => {
+                    return Err(Error::Other(
+                        "Only one branch can be given without a repository".to_string(),
+                    ));
+                }
+                None if arg.trim().is_empty() => {
+                    return Err(Error::Other("Branch cannot be empty".to_string()));
+                }
+                None => main = Some(arg.trim().to_string()),
+            }
+        }
+
+        match main.or(first) {
+            Some(main) => Ok((main, branches)),
+            None => Err(Error::Other("No branch given".to_string())),
         }
     }
 
@@ -91,4 +206,41 @@ mod tests {
         assert_eq!(Scratch::sanitize_name("--test--"), "test");
         assert_eq!(Scratch::sanitize_name("hello world!"), "hello-world");
     }
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_branch_args() {
+        let (main, branches) = Scratch::parse_branch_args(&args(&["feature/x"])).unwrap();
+        assert_eq!(main, "feature/x");
+        assert!(branches.is_empty());
+
+        let (main, branches) =
+            Scratch::parse_branch_args(&args(&["web=feat-x", "api=main"])).unwrap();
+        assert_eq!(main, "feat-x");
+        assert_eq!(branches["web"], "feat-x");
+        assert_eq!(branches["api"], "main");
+
+        let (main, branches) =
+            Scratch::parse_branch_args(&args(&["api=feat-x", "release"])).unwrap();
+        assert_eq!(main, "release");
+        assert_eq!(branches.len(), 1);
+
+        assert!(Scratch::parse_branch_args(&[]).is_err());
+        assert!(Scratch::parse_branch_args(&args(&["a", "b"])).is_err());
+        assert!(Scratch::parse_branch_args(&args(&["api=x", "api=y"])).is_err());
+        assert!(Scratch::parse_branch_args(&args(&["api="])).is_err());
+    }
+
+    #[test]
+    fn test_branch_for() {
+        let mut scratch = Scratch::new("x".into(), "feature/x".into(), "default".into());
+        scratch.branches.insert("web".into(), "main".into());
+
+        assert_eq!(scratch.branch_for(Some("web")), "main");
+        assert_eq!(scratch.branch_for(Some("api")), "feature/x");
+        assert_eq!(scratch.branch_for(None), "feature/x");
+    }
 }
@@ -0,0 +1,61 @@
+//! Progress reporting for long-running scratch operations
+//!
+//! Lifecycle functions call [`report`] as they move from one step to the next
+//! (pulling images, provisioning databases, `docker compose up`, nginx).
+//! Reports go to the reporter installed for the current task with
+//! [`with_progress`] and are dropped when there is none, as on the CLI.
+
+use std::future::Future;
+use tokio::sync::mpsc;
+
+tokio::task_local! {
+    static REPORTER: ProgressReporter;
+}
+
+/// A step reached by a scratch operation
+#[derive(Debug, Clone)]
+pub struct ProgressStep {
+    pub step: String,
+    pub message: String,
+}
+
+/// Where progress of the current task is sent
+pub type ProgressReporter = mpsc::UnboundedSender<ProgressStep>;
+
+/// Run a future with progress reports sent to `reporter`
+pub async fn with_progress<F: Future>(reporter: ProgressReporter, fut: F) -> F::Output {
+    REPORTER.scope(reporter, fut).await
+}
+
+/// Report that the current operation has reached a step
+pub fn report(step: &str, message: impl Into<String>) {
+    let _ = REPORTER.try_with(|reporter| {
+        let _ = reporter.send(ProgressStep {
+            step: step.to_string(),
+            message: message.into(),
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_report_goes_to_installed_reporter() {
+        // No reporter installed, nothing happens
+        report("pull", "Pulling images");
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        with_progress(tx, async {
+            report("pull", "Pulling images");
+            report("up", "Starting containers");
+        })
+        .await;
+
+        let steps: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|p| p.step)
+            .collect();
+        assert_eq!(steps, ["pull", "up"]);
+    }
+}
@@ -0,0 +1,329 @@
+//! Resource quotas for scratches
+//!
+//! Quotas cap the number of scratches in total, per owner and per profile,
+//! and the memory reserved by running scratches. They are checked before a
+//! scratch is created. Failed scratches don't count at all; stopped ones
+//! count towards the scratch limits but not towards memory.
+//!
+//! A scratch reserves the `mem_reservation` (or `mem_limit`) of each of its
+//! services, or `quotas.scratch_memory` when none of them set one.
+
+use std::collections::BTreeMap;
+
+use crate::config::{format_memory, parse_memory, Config, ScratchConfig, ScratchState};
+use crate::error::{Error, Result};
+use crate::store::StateStore;
+
+/// What the existing scratches use of each quota
+#[derive(Debug, Clone, Default)]
+pub struct QuotaUsage {
+    pub scratches: usize,
+    pub per_owner: BTreeMap<String, usize>,
+    pub per_profile: BTreeMap<String, usize>,
+    /// Memory reserved by running scratches, in bytes
+    pub memory: u64,
+}
+
+impl QuotaUsage {
+    /// Measure usage across every scratch in the state store
+    pub fn load(config: &Config) -> Result<Self> {
+        Self::from_scratches(config, &StateStore::for_config(config)?.list()?)
+    }
+
+    /// Measure usage across the given scratches
+    pub fn from_scratches(config: &Config, scratches: &[ScratchConfig]) -> Result<Self> {
+        let mut usage = Self::default();
+
+        for scratch in scratches {
+            if scratch.state == ScratchState::Failed {
+                continue;
+            }
+
+            usage.scratches += 1;
+            if let Some(owner) = &scratch.owner {
+                *usage.per_owner.entry(owner.clone()).or_default() += 1;
+            }
+            if let Some(profile) = &scratch.profile {
+                *usage.per_profile.entry(profile.clone()).or_default() += 1;
+            }
+            if scratch.state != ScratchState::Stopped {
+                usage.memory +=
+                    scratch_memory(config, &scratch.services, scratch.profile.as_deref())?;
+            }
+        }
+
+        Ok(usage)
+    }
+}
+
+/// Usage of a single quota against its limit
+#[derive(Debug, Clone)]
+pub struct QuotaItem {
+    pub name: String,
+    pub used: u64,
+    pub limit: u64,
+    /// Whether `used` and `limit` are bytes of memory rather than counts
+    pub memory: bool,
+}
+
+impl QuotaItem {
+    /// Whether the limit has been reached
+    pub fn is_full(&self) -> bool {
+        self.used >= self.limit
+    }
+
+    /// Usage as a percentage of the limit
+    pub fn percent(&self) -> u64 {
+        (self.used * 100).checked_div(self.limit).unwrap_or(100)
+    }
+
+    /// Usage formatted as "used / limit"
+    pub fn display(&self) -> String {
+        if self.memory {
+            format!(
+                "{} / {}",
+                format_memory(self.used),
+                format_memory(self.limit)
+            )
+        } else {
+            format!("{} / {}", self.used, self.limit)
+        }
+    }
+}
+
+/// Current usage of every configured quota
+///
+/// Per-owner usage is listed for each owner that has scratches.
+pub fn quota_report(config: &Config) -> Result<Vec<QuotaItem>> {
+    let quotas = &config.quotas;
+    let usage = QuotaUsage::load(config)?;
+    let mut items = Vec::new();
+
+    if let Some(limit) = quotas.max_scratches {
+        items.push(count_item("scratches".to_string(), usage.scratches, limit));
+    }
+    if let Some(limit) = quotas.max_per_owner {
+        for (owner, used) in &usage.per_owner {
+            items.push(count_item(format!("owner {}", owner), *used, limit));
+        }
+    }
+    let mut profiles: Vec<_> = quotas.max_per_profile.iter().collect();
+    profiles.sort();
+    for (profile, limit) in profiles {
+        let used = usage.per_profile.get(profile).copied().unwrap_or(0);
+        items.push(count_item(format!("profile {}", profile), used, *limit));
+    }
+    if let Some(limit) = max_memory(config)? {
+        items.push(QuotaItem {
+            name: "memory".to_string(),
+            used: usage.memory,
+            limit,
+            memory: true,
+        });
+    }
+
+    Ok(items)
+}
+
+/// Fail with [`Error::QuotaExceeded`] if another scratch for this owner and
+/// profile would go over a quota
+pub fn check_quotas(config: &Config, owner: Option<&str>, profile: Option<&str>) -> Result<()> {
+    let quotas = &config.quotas;
+    let usage = QuotaUsage::load(config)?;
+
+    if let Some(limit) = quotas.max_scratches {
+        if usage.scratches >= limit {
+            return Err(Error::QuotaExceeded(format!(
+                "{} of {} scratches in use",
+                usage.scratches, limit
+            )));
+        }
+    }
+
+    if let (Some(owner), Some(limit)) = (owner, quotas.max_per_owner) {
+        let used = usage.per_owner.get(owner).copied().unwrap_or(0);
+        if used >= limit {
+            return Err(Error::QuotaExceeded(format!(
+                "'{}' already has {} of {} scratches",
+                owner, used, limit
+            )));
+        }
+    }
+
+    if let Some(profile) = profile {
+        if let Some(&limit) = quotas.max_per_profile.get(profile) {
+            let used = usage.per_profile.get(profile).copied().unwrap_or(0);
+            if used >= limit {
+                return Err(Error::QuotaExceeded(format!(
+                    "profile '{}' already has {} of {} scratches",
+                    profile, used, limit
+                )));
+            }
+        }
+    }
+
+    if let Some(limit) = max_memory(config)? {
+        let services = match profile.and_then(|p| config.get_profile(p)) {
+            Some(profile) => &profile.services,
+            None => &config.scratch.services,
+        };
+        let needed = scratch_memory(config, services, profile)?;
+        if usage.memory + needed > limit {
+            return Err(Error::QuotaExceeded(format!(
+                "reserving {} would exceed the {} memory limit ({} in use)",
+                format_memory(needed),
+                format_memory(limit),
+                format_memory(usage.memory)
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn count_item(name: String, used: usize, limit: usize) -> QuotaItem {
+    QuotaItem {
+        name,
+        used: used as u64,
+        limit: limit as u64,
+        memory: false,
+    }
+}
+
+fn max_memory(config: &Config) -> Result<Option<u64>> {
+    config
+        .quotas
+        .max_memory
+        .as_deref()
+        .map(parse_memory)
+        .transpose()
+}
+
+/// Memory a scratch with these services reserves against the memory quota
+fn scratch_memory(config: &Config, services: &[String], profile: Option<&str>) -> Result<u64> {
+    let mut total = 0;
+    let mut any_reserved = false;
+    for service in services.iter().filter_map(|s| config.get_service(s)) {
+        if service.shared {
+            continue;
+        }
+        if let Some(memory) = config
+            .service_resources(service, profile)
+            .reserved_memory()?
+        {
+            total += memory;
+            any_reserved = true;
+        }
+    }
+
+    if any_reserved {
+        return Ok(total);
+    }
+    config
+        .quotas
+        .scratch_memory
+        .as_deref()
+        .map(parse_memory)
+        .transpose()
+        .map(|memory| memory.unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_config(name: &str, owner: &str, state: ScratchState) -> ScratchConfig {
+        let mut scratch: ScratchConfig = toml::from_str(&format!(
+            "name = \"{}\"\nbranch = \"main\"\ntemplate = \"default\"\nprofile = \"qa\"\n\
+             created_at = \"2024-01-01T00:00:00Z\"\nservices = []\ndatabases = {{}}\nenv = {{}}",
+            name
+        ))
+        .unwrap();
+        scratch.owner = Some(owner.to_string());
+        scratch.state = state;
+        scratch
+    }
+
+    #[test]
+    fn test_usage_skips_failed_and_stopped_memory() {
+        let mut config = Config::default();
+        config.quotas.scratch_memory = Some("1g".to_string());
+
+        let usage = QuotaUsage::from_scratches(
+            &config,
+            &[
+                scratch_config("a", "alice", ScratchState::Running),
+                scratch_config("b", "alice", ScratchState::Stopped),
+                scratch_config("c", "bob", ScratchState::Failed),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(usage.scratches, 2);
+        assert_eq!(usage.per_owner.get("alice"), Some(&2));
+        assert_eq!(usage.per_owner.get("bob"), None);
+        assert_eq!(usage.per_profile.get("qa"), Some(&2));
+        assert_eq!(usage.memory, 1 << 30);
+    }
+
+    #[test]
+    fn test_memory_comes_from_service_reservations() {
+        let mut config: Config = toml::from_str(
+            "[services.api]\nimage = \"api\"\nmem_reservation = \"256m\"\n\
+             [services.worker]\nimage = \"worker\"\n\
+             [services.postgres]\nimage = \"postgres\"\nshared = true\nmem_limit = \"4g\"\n\
+             [scratch.profiles.qa.resources]\nmem_limit = \"512m\"",
+        )
+        .unwrap();
+        config.quotas.scratch_memory = Some("1g".to_string());
+
+        let services = ["api", "worker", "postgres"].map(String::from);
+        assert_eq!(
+            scratch_memory(&config, &services, Some("qa")).unwrap(),
+            768 << 20
+        );
+        assert_eq!(scratch_memory(&config, &services, None).unwrap(), 256 << 20);
+        // Nothing reserved by the services, so the flat per-scratch figure applies
+        assert_eq!(
+            scratch_memory(&config, &services[1..], None).unwrap(),
+            1 << 30
+        );
+    }
+
+    #[test]
+    fn test_check_quotas() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.server.releases_dir = temp.path().to_path_buf();
+        let store = StateStore::for_config(&config).unwrap();
+        store
+            .put(&scratch_config("a", "alice", ScratchState::Running))
+            .unwrap();
+
+        assert!(check_quotas(&config, Some("alice"), Some("qa")).is_ok());
+
+        config.quotas.max_per_owner = Some(1);
+        assert!(matches!(
+            check_quotas(&config, Some("alice"), None),
+            Err(Error::QuotaExceeded(_))
+        ));
+        assert!(check_quotas(&config, Some("bob"), None).is_ok());
+
+        config.quotas.max_per_owner = None;
+        config.quotas.max_per_profile.insert("qa".to_string(), 1);
+        assert!(check_quotas(&config, None, Some("qa")).is_err());
+        assert!(check_quotas(&config, None, Some("demo")).is_ok());
+
+        config.quotas.max_per_profile.clear();
+        config.quotas.max_memory = Some("3g".to_string());
+        config.quotas.scratch_memory = Some("2g".to_string());
+        assert!(check_quotas(&config, None, None).is_err());
+
+        config.quotas.max_memory = None;
+        config.quotas.max_scratches = Some(1);
+        let report = quota_report(&config).unwrap();
+        assert_eq!(report.len(), 1);
+        assert!(report[0].is_full());
+        assert_eq!(report[0].display(), "1 / 1");
+    }
+}
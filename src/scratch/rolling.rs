@@ -0,0 +1,402 @@
+//! Rolling updates of a scratch without dropping requests
+//!
+//! A plain `update --restart` stops every container before starting the new
+//! ones, so the scratch is unreachable in between. A rolling update keeps the
+//! ingress service answering:
+//!
+//! 1. every other service is recreated in place (nothing routes to them from
+//!    outside the scratch)
+//! 2. the new ingress container is started next to the old one as
+//!    `<scratch>-<service>-next`, from `compose.next.yml` under its own
+//!    compose project
+//! 3. once its healthcheck passes, traffic is switched to it: static nginx
+//!    configs point the scratch's upstream at it, dynamic configs rely on it
+//!    carrying the ingress name as a DNS alias and the old container leaving
+//!    the network
+//! 4. the ingress service is recreated in the scratch's own project, traffic
+//!    switches back once it's healthy and the `-next` container is removed
+//!
+//! Ingress services without a healthcheck can't be switched to safely, so
+//! scratches without one fall back to a full restart.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config::{Config, ScratchState};
+use crate::docker::{ComposeFile, ContainerHealth, DockerClient};
+use crate::error::{Error, Result};
+use crate::nginx;
+
+use super::{
+    compose_project_name, format_health_report, health_timeout, load_scratch, load_scratch_config,
+    parse_health_timeout, render_compose_file, report, run_compose, run_hooks, save_scratch_config,
+    start_scratch_locked, stop_scratch_locked, wait_for_scratch_healthy, HookPoint, ScratchLock,
+    ServiceHealth, LOG_LINES,
+};
+
+/// Compose file of the temporary ingress container, in the scratch directory
+const NEXT_COMPOSE_FILE: &str = "compose.next.yml";
+
+/// Suffix of the temporary ingress container and its compose project
+const NEXT_SUFFIX: &str = "next";
+
+/// Re-render a scratch's compose file and apply it without downtime
+pub async fn rolling_update_scratch(
+    config: &Config,
+    docker: &DockerClient,
+    name: &str,
+) -> Result<()> {
+    let scratch_dir = config.server.releases_dir.join(name);
+    let _lock = ScratchLock::acquire(config, name, "update")?;
+    let mut scratch = load_scratch(config, name)?;
+    super::refresh_scratch_images(config, docker, &mut scratch).await?;
+
+    report("compose", "Rendering compose file");
+    let compose = render_compose_file(config, &scratch)?;
+    compose.save(&scratch_dir.join("compose.yml"))?;
+
+    let Some(ingress) = rollable_ingress(config, docker, name, &compose).await? else {
+        tracing::info!(
+            "Scratch '{}' has no running ingress with a healthcheck, restarting it",
+            name
+        );
+        stop_scratch_locked(config, name).await?;
+        start_scratch_locked(config, name).await?;
+        return run_hooks(config, docker, &scratch, HookPoint::PostUpdate).await;
+    };
+
+    tracing::info!("Rolling update of scratch: {}", name);
+    let others: Vec<&str> = compose
+        .services
+        .keys()
+        .map(String::as_str)
+        .filter(|service| *service != ingress.service)
+        .collect();
+    if !others.is_empty() {
+        report("up", "Recreating services");
+        let mut args = vec!["up", "-d", "--no-deps"];
+        args.extend(&others);
+        run_compose(&scratch_dir, &args).await?;
+    }
+
+    roll_ingress(config, docker, name, &scratch_dir, &compose, &ingress).await?;
+
+    report("health", "Waiting for services to become healthy");
+    wait_for_scratch_healthy(config, docker, name).await?;
+
+    if let Ok(mut scratch_config) = load_scratch_config(config, name) {
+        scratch_config.state = ScratchState::Running;
+        save_scratch_config(config, &scratch_config)?;
+    }
+    tracing::info!("Rolling update of scratch '{}' finished", name);
+    run_hooks(config, docker, &scratch, HookPoint::PostUpdate).await
+}
+
+/// The running ingress container of a scratch
+struct Ingress {
+    service: String,
+    /// Name of the running container
+    container: String,
+    id: String,
+}
+
+/// Find the ingress container to roll, if the scratch has one that can be
+/// rolled: nginx routes to it, it's running and it has a healthcheck
+async fn rollable_ingress(
+    config: &Config,
+    docker: &DockerClient,
+    name: &str,
+    compose: &ComposeFile,
+) -> Result<Option<Ingress>> {
+    let Some(service) = config.nginx.ingress_service.as_deref() else {
+        return Ok(None);
+    };
+    let has_healthcheck = compose
+        .services
+        .get(service)
+        .is_some_and(|s| s.healthcheck.is_some());
+    if !config.nginx.enabled || !has_healthcheck {
+        return Ok(None);
+    }
+
+    let service_label = format!("{}.service", config.docker.label_prefix);
+    let container = format!("{}-{}", name, service);
+    Ok(docker
+        .list_scratch_containers(Some(name))
+        .await?
+        .into_iter()
+        .find(|c| {
+            c.name == container
+                && c.state == "running"
+                && c.labels.get(&service_label).map(String::as_str) == Some(service)
+        })
+        .map(|c| Ingress {
+            service: service.to_string(),
+            container: c.name,
+            id: c.id,
+        }))
+}
+
+/// Replace the ingress container while a temporary one serves its traffic
+async fn roll_ingress(
+    config: &Config,
+    docker: &DockerClient,
+    name: &str,
+    scratch_dir: &Path,
+    compose: &ComposeFile,
+    ingress: &Ingress,
+) -> Result<()> {
+    let dynamic = config.nginx.dynamic.unwrap_or(true);
+    let next_project = format!("{}-{}", compose_project_name(name), NEXT_SUFFIX);
+    let next_container = format!("{}-{}", ingress.container, NEXT_SUFFIX);
+    let timeout = health_timeout(config, &ingress.service);
+    let wait = parse_health_timeout(timeout)?;
+
+    report(
+        "rolling",
+        format!("Starting {} next to {}", next_container, ingress.container),
+    );
+    let alias = dynamic.then_some(ingress.container.as_str());
+    let next = next_compose_file(
+        compose,
+        &ingress.service,
+        &compose_project_name(name),
+        &next_container,
+        alias,
+        &config.docker.network,
+    )?;
+    fs::write(
+        scratch_dir.join(NEXT_COMPOSE_FILE),
+        serde_yaml::to_string(&next)?,
+    )?;
+    let next_args = ["-p", next_project.as_str(), "-f", NEXT_COMPOSE_FILE];
+
+    let started = run_compose(scratch_dir, &[&next_args[..], &["up", "-d"]].concat()).await;
+    let health = match started {
+        Ok(()) => docker.wait_for_ready(&next_container, wait).await,
+        Err(e) => Err(e),
+    };
+    match health {
+        Ok(health) if health.is_ready() => {}
+        Ok(health) => {
+            let details =
+                unhealthy_report(docker, &ingress.service, &next_container, health, timeout).await;
+            remove_next(scratch_dir, &next_args).await;
+            return Err(Error::ScratchUnhealthy(name.to_string(), details));
+        }
+        Err(e) => {
+            remove_next(scratch_dir, &next_args).await;
+            return Err(e);
+        }
+    }
+
+    report("switch", format!("Routing traffic to {}", next_container));
+    if dynamic {
+        // The -next container answers to the ingress name as well, so taking
+        // the old one off the network leaves it as the only address
+        docker.disconnect_from_network(&ingress.id).await?;
+    } else {
+        let upstreams = HashMap::from([(name.to_string(), next_container.clone())]);
+        nginx::regenerate_config_with_upstreams(config, docker, &upstreams).await?;
+        nginx::reload(config, docker).await?;
+    }
+
+    // From here on the -next container keeps serving if anything fails, so
+    // the scratch stays reachable while the problem is looked into
+    report("up", format!("Recreating {}", ingress.container));
+    run_compose(
+        scratch_dir,
+        &[
+            "up",
+            "-d",
+            "--no-deps",
+            "--force-recreate",
+            &ingress.service,
+        ],
+    )
+    .await?;
+    let health = docker.wait_for_ready(&ingress.container, wait).await?;
+    if !health.is_ready() {
+        let mut details = unhealthy_report(
+            docker,
+            &ingress.service,
+            &ingress.container,
+            health,
+            timeout,
+        )
+        .await;
+        details.push_str(&format!(
+            "\n  Traffic is still served by {}",
+            next_container
+        ));
+        return Err(Error::ScratchUnhealthy(name.to_string(), details));
+    }
+
+    report(
+        "switch",
+        format!("Routing traffic to {}", ingress.container),
+    );
+    if !dynamic {
+        nginx::regenerate_config(config, docker).await?;
+        nginx::reload(config, docker).await?;
+    }
+    remove_next(scratch_dir, &next_args).await;
+    Ok(())
+}
+
+/// Build the compose file of the temporary ingress container
+///
+/// It runs the rendered ingress service under another container name and
+/// without host ports or dependencies, which belong to the real one. Volumes
+/// and networks declared by the scratch are referenced by the names the
+/// scratch's compose project gave them, so both containers share them.
+fn next_compose_file(
+    compose: &ComposeFile,
+    service: &str,
+    project: &str,
+    container_name: &str,
+    alias: Option<&str>,
+    network: &str,
+) -> Result<serde_yaml::Value> {
+    let mut next = compose.clone();
+    next.services.retain(|name, _| name == service);
+    let definition = next
+        .services
+        .get_mut(service)
+        .ok_or_else(|| Error::Other(format!("Service '{}' is not in compose.yml", service)))?;
+    definition.container_name = Some(container_name.to_string());
+    definition.ports.clear();
+    definition.depends_on.clear();
+
+    for (key, volume) in &mut next.volumes {
+        if volume.external != Some(true) {
+            volume.name = Some(volume.name.take().unwrap_or(format!("{}_{}", project, key)));
+            volume.external = Some(true);
+            volume.driver = None;
+        }
+    }
+    for (key, definition) in &mut next.networks {
+        if definition.external != Some(true) {
+            definition.name = Some(
+                definition
+                    .name
+                    .take()
+                    .unwrap_or(format!("{}_{}", project, key)),
+            );
+            definition.external = Some(true);
+            definition.driver = None;
+        }
+    }
+
+    let mut next = serde_yaml::to_value(&next)?;
+    if let Some(alias) = alias {
+        // Compose only takes aliases in the long form of `networks`
+        let networks = &mut next["services"][service]["networks"];
+        let mut long_form = serde_yaml::Mapping::new();
+        for name in networks.as_sequence().into_iter().flatten() {
+            let settings = if name.as_str() == Some(network) {
+                serde_yaml::to_value(HashMap::from([("aliases", [alias])]))?
+            } else {
+                serde_yaml::Value::Null
+            };
+            long_form.insert(name.clone(), settings);
+        }
+        *networks = serde_yaml::Value::Mapping(long_form);
+    }
+    Ok(next)
+}
+
+/// Describe a container that didn't become healthy, with its last log lines
+async fn unhealthy_report(
+    docker: &DockerClient,
+    service: &str,
+    container: &str,
+    health: ContainerHealth,
+    timeout: &str,
+) -> String {
+    let logs = docker
+        .get_logs(container, LOG_LINES)
+        .await
+        .unwrap_or_default();
+    format_health_report(&[ServiceHealth {
+        service: service.to_string(),
+        container: container.to_string(),
+        health,
+        timeout: timeout.to_string(),
+        logs,
+    }])
+}
+
+/// Take down the temporary ingress container and its compose file
+async fn remove_next(scratch_dir: &Path, next_args: &[&str]) {
+    if let Err(e) = run_compose(scratch_dir, &[next_args, &["down"]].concat()).await {
+        tracing::warn!("Failed to remove temporary ingress container: {}", e);
+    }
+    let _ = fs::remove_file(scratch_dir.join(NEXT_COMPOSE_FILE));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_compose_file() {
+        let compose: ComposeFile = serde_yaml::from_str(
+            "services:
+  api:
+    image: api:2
+    container_name: feature-x-api
+    ports: [\"8080:80\"]
+    depends_on: [worker]
+    volumes: [\"uploads:/uploads\"]
+    networks: [scratchpad-network]
+    healthcheck: { test: [\"CMD\", \"true\"] }
+  worker:
+    image: worker:2
+volumes:
+  uploads: {}
+networks:
+  scratchpad-network:
+    external: true
+",
+        )
+        .unwrap();
+
+        let next = next_compose_file(
+            &compose,
+            "api",
+            "feature-x",
+            "feature-x-api-next",
+            Some("feature-x-api"),
+            "scratchpad-network",
+        )
+        .unwrap();
+
+        let services = next["services"].as_mapping().unwrap();
+        assert_eq!(services.len(), 1);
+        let api = &next["services"]["api"];
+        assert_eq!(api["container_name"], "feature-x-api-next");
+        assert_eq!(api["image"], "api:2");
+        assert!(api.get("ports").is_none());
+        assert!(api.get("depends_on").is_none());
+        assert_eq!(
+            api["networks"]["scratchpad-network"]["aliases"][0],
+            "feature-x-api"
+        );
+        assert_eq!(next["volumes"]["uploads"]["name"], "feature-x_uploads");
+        assert_eq!(next["volumes"]["uploads"]["external"], true);
+
+        // Static nginx configs route by container name, so no alias
+        let next = next_compose_file(
+            &compose,
+            "api",
+            "feature-x",
+            "feature-x-api-next",
+            None,
+            "scratchpad-network",
+        )
+        .unwrap();
+        assert_eq!(next["services"]["api"]["networks"][0], "scratchpad-network");
+    }
+}
@@ -0,0 +1,400 @@
+//! Point-in-time snapshots of a scratch
+//!
+//! A snapshot lives in `releases_dir/<scratch>/snapshots/<snapshot>/` and holds:
+//! - `snapshot.toml` - metadata about the snapshot
+//! - `compose.yml` and `.scratchpad.toml` - the scratch definition
+//! - `data.tar.gz` - an archive of the scratch's `data/` directory
+//! - `db/<database>.sql` - a pg_dump of each of the scratch's databases
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, ScratchConfig};
+use crate::docker::DockerClient;
+use crate::error::{Error, Result};
+use crate::services;
+use crate::store;
+
+use super::lifecycle::{start_scratch_locked, stop_scratch_locked};
+use super::{load_scratch_config, save_scratch_config, ScratchLock};
+
+/// Metadata stored alongside each snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub name: String,
+    pub scratch: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub databases: Vec<String>,
+}
+
+/// Take a snapshot of a scratch's databases, data directory and config
+///
+/// The snapshot name defaults to the current UTC timestamp.
+pub async fn create_snapshot(
+    config: &Config,
+    docker: &DockerClient,
+    scratch: &str,
+    name: Option<String>,
+) -> Result<Snapshot> {
+    let scratch_dir = existing_scratch_dir(config, scratch)?;
+    let name = name.unwrap_or_else(|| Utc::now().format("%Y%m%d-%H%M%S").to_string());
+    validate_snapshot_name(&name)?;
+
+    let _lock = ScratchLock::acquire(config, scratch, "snapshot")?;
+    let snapshot_dir = scratch_dir.join("snapshots").join(&name);
+    if snapshot_dir.exists() {
+        return Err(Error::Other(format!(
+            "Snapshot '{}' already exists for scratch '{}'",
+            name, scratch
+        )));
+    }
+    let scratch_config = load_scratch_config(config, scratch)?;
+    let databases = snapshot_databases(&scratch_config)?;
+
+    tracing::info!("Creating snapshot '{}' of scratch '{}'", name, scratch);
+    fs::create_dir_all(snapshot_dir.join("db"))?;
+
+    let result = write_snapshot(config, docker, &scratch_dir, &snapshot_dir, &databases)
+        .await
+        .and_then(|_| store::write_config_file(&snapshot_dir, &scratch_config));
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&snapshot_dir);
+        return Err(e);
+    }
+
+    let snapshot = Snapshot {
+        name,
+        scratch: scratch.to_string(),
+        created_at: Utc::now(),
+        databases,
+    };
+    let metadata = toml::to_string_pretty(&snapshot).map_err(|e| Error::Config(e.to_string()))?;
+    fs::write(snapshot_dir.join("snapshot.toml"), metadata)?;
+
+    tracing::info!(
+        "Created snapshot '{}' of scratch '{}'",
+        snapshot.name,
+        scratch
+    );
+    Ok(snapshot)
+}
+
+/// Write the contents of a snapshot into its directory
+async fn write_snapshot(
+    config: &Config,
+    docker: &DockerClient,
+    scratch_dir: &Path,
+    snapshot_dir: &Path,
+    databases: &[String],
+) -> Result<()> {
+    for db in databases {
+        tracing::debug!("Dumping database: {}", db);
+        let dump = services::dump_postgres_database(config, docker, db).await?;
+        fs::write(snapshot_dir.join("db").join(format!("{}.sql", db)), dump)?;
+    }
+
+    tracing::debug!("Archiving data directory");
+    run_tar(&[
+        "-czf",
+        &snapshot_dir.join("data.tar.gz").to_string_lossy(),
+        "-C",
+        &scratch_dir.to_string_lossy(),
+        "data",
+    ])
+    .await?;
+
+    let compose_path = scratch_dir.join("compose.yml");
+    if compose_path.exists() {
+        fs::copy(&compose_path, snapshot_dir.join("compose.yml"))?;
+    }
+
+    Ok(())
+}
+
+/// List the snapshots of a scratch, oldest first
+pub fn list_snapshots(config: &Config, scratch: &str) -> Result<Vec<Snapshot>> {
+    let snapshots_dir = existing_scratch_dir(config, scratch)?.join("snapshots");
+
+    if !snapshots_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(&snapshots_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        match load_snapshot(&entry.path()) {
+            Ok(snapshot) => snapshots.push(snapshot),
+            Err(e) => tracing::warn!(
+                "Skipping invalid snapshot {}: {}",
+                entry.path().display(),
+                e
+            ),
+        }
+    }
+
+    snapshots.sort_by_key(|s| s.created_at);
+    Ok(snapshots)
+}
+
+/// Roll a scratch back to a snapshot
+///
+/// The scratch is stopped while its databases, data directory and compose file
+/// are replaced, then started again. The scratch's current expiry is kept.
+pub async fn restore_snapshot(
+    config: &Config,
+    docker: &DockerClient,
+    scratch: &str,
+    name: &str,
+) -> Result<()> {
+    let scratch_dir = existing_scratch_dir(config, scratch)?;
+    let snapshot_dir = snapshot_dir(&scratch_dir, name)?;
+    let snapshot = load_snapshot(&snapshot_dir)?;
+
+    let _lock = ScratchLock::acquire(config, scratch, "restore")?;
+    tracing::info!("Restoring scratch '{}' to snapshot '{}'", scratch, name);
+    stop_scratch_locked(config, scratch).await?;
+
+    // Bring the scratch back up even if the restore fails half way
+    if let Err(e) = restore_contents(
+        config,
+        docker,
+        scratch,
+        &scratch_dir,
+        &snapshot_dir,
+        &snapshot,
+    )
+    .await
+    {
+        if let Err(start_err) = start_scratch_locked(config, scratch).await {
+            tracing::warn!(
+                "Failed to restart scratch '{}' after a failed restore: {}",
+                scratch,
+                start_err
+            );
+        }
+        return Err(e);
+    }
+    start_scratch_locked(config, scratch).await?;
+
+    tracing::info!("Restored scratch '{}' to snapshot '{}'", scratch, name);
+    Ok(())
+}
+
+/// Replace a stopped scratch's databases, data directory and config with a snapshot's
+async fn restore_contents(
+    config: &Config,
+    docker: &DockerClient,
+    scratch: &str,
+    scratch_dir: &Path,
+    snapshot_dir: &Path,
+    snapshot: &Snapshot,
+) -> Result<()> {
+    for db in &snapshot.databases {
+        tracing::debug!("Restoring database: {}", db);
+        let dump = fs::read_to_string(snapshot_dir.join("db").join(format!("{}.sql", db)))?;
+        services::restore_postgres_database(config, docker, db, &dump).await?;
+    }
+
+    tracing::debug!("Restoring data directory");
+    let data_dir = scratch_dir.join("data");
+    if data_dir.exists() {
+        fs::remove_dir_all(&data_dir)?;
+    }
+    run_tar(&[
+        "-xzf",
+        &snapshot_dir.join("data.tar.gz").to_string_lossy(),
+        "-C",
+        &scratch_dir.to_string_lossy(),
+    ])
+    .await?;
+
+    let compose_path = snapshot_dir.join("compose.yml");
+    if compose_path.exists() {
+        fs::copy(&compose_path, scratch_dir.join("compose.yml"))?;
+    }
+
+    let current = load_scratch_config(config, scratch)?;
+    let mut restored = store::read_config_file(snapshot_dir)?;
+    restored.name = current.name;
+    restored.expires_at = current.expires_at;
+    restored.last_activity_at = current.last_activity_at;
+    save_scratch_config(config, &restored)
+}
+
+/// Delete a snapshot of a scratch
+pub fn delete_snapshot(config: &Config, scratch: &str, name: &str) -> Result<()> {
+    let scratch_dir = existing_scratch_dir(config, scratch)?;
+    let _lock = ScratchLock::acquire(config, scratch, "snapshot")?;
+    let snapshot_dir = snapshot_dir(&scratch_dir, name)?;
+
+    fs::remove_dir_all(&snapshot_dir)?;
+    tracing::info!("Deleted snapshot '{}' of scratch '{}'", name, scratch);
+    Ok(())
+}
+
+/// The databases of a scratch a snapshot dumps
+///
+/// Only postgres databases can be dumped, so a scratch with databases of
+/// another service can't be snapshotted rather than silently losing them.
+fn snapshot_databases(scratch_config: &ScratchConfig) -> Result<Vec<String>> {
+    let mut databases = Vec::new();
+    for (service, dbs) in &scratch_config.databases {
+        if dbs.is_empty() {
+            continue;
+        }
+        if service != "postgres" {
+            return Err(Error::Other(format!(
+                "Can't snapshot the {} databases of scratch '{}'",
+                service, scratch_config.name
+            )));
+        }
+        databases.extend(dbs.iter().cloned());
+    }
+    databases.sort();
+    Ok(databases)
+}
+
+/// Get the directory of a scratch, failing if it doesn't exist
+fn existing_scratch_dir(config: &Config, scratch: &str) -> Result<PathBuf> {
+    let scratch_dir = config.server.releases_dir.join(scratch);
+    if !scratch_dir.exists() {
+        return Err(Error::ScratchNotFound(scratch.to_string()));
+    }
+    Ok(scratch_dir)
+}
+
+/// Get the directory of an existing snapshot
+fn snapshot_dir(scratch_dir: &Path, name: &str) -> Result<PathBuf> {
+    validate_snapshot_name(name)?;
+    let snapshot_dir = scratch_dir.join("snapshots").join(name);
+    if !snapshot_dir.join("snapshot.toml").exists() {
+        return Err(Error::SnapshotNotFound(name.to_string()));
+    }
+    Ok(snapshot_dir)
+}
+
+/// Load the metadata of a snapshot
+fn load_snapshot(snapshot_dir: &Path) -> Result<Snapshot> {
+    let content = fs::read_to_string(snapshot_dir.join("snapshot.toml"))?;
+    Ok(toml::from_str(&content)?)
+}
+
+/// Snapshot names become directory names, so keep them simple
+fn validate_snapshot_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !name.starts_with('.');
+
+    if !valid {
+        return Err(Error::Other(format!("Invalid snapshot name: {}", name)));
+    }
+    Ok(())
+}
+
+/// Run tar with the given arguments
+async fn run_tar(args: &[&str]) -> Result<()> {
+    use tokio::process::Command;
+
+    let output = Command::new("tar").args(args).output().await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::Other(format!("tar failed: {}", stderr.trim())));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_snapshot_name() {
+        assert!(validate_snapshot_name("20240101-120000").is_ok());
+        assert!(validate_snapshot_name("before_migration.v2").is_ok());
+        assert!(validate_snapshot_name("").is_err());
+        assert!(validate_snapshot_name("../escape").is_err());
+        assert!(validate_snapshot_name(".hidden").is_err());
+        assert!(validate_snapshot_name("a/b").is_err());
+    }
+
+    #[test]
+    fn test_list_snapshots() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.server.releases_dir = temp.path().to_path_buf();
+
+        assert!(matches!(
+            list_snapshots(&config, "missing"),
+            Err(Error::ScratchNotFound(_))
+        ));
+
+        let scratch_dir = temp.path().join("feature-x");
+        fs::create_dir_all(&scratch_dir).unwrap();
+        assert!(list_snapshots(&config, "feature-x").unwrap().is_empty());
+
+        for (name, created_at) in [("second", 2), ("first", 1)] {
+            let dir = scratch_dir.join("snapshots").join(name);
+            fs::create_dir_all(&dir).unwrap();
+            let snapshot = Snapshot {
+                name: name.to_string(),
+                scratch: "feature-x".to_string(),
+                created_at: DateTime::from_timestamp(created_at, 0).unwrap(),
+                databases: vec!["scratch_feature_x".to_string()],
+            };
+            fs::write(
+                dir.join("snapshot.toml"),
+                toml::to_string(&snapshot).unwrap(),
+            )
+            .unwrap();
+        }
+
+        let snapshots = list_snapshots(&config, "feature-x").unwrap();
+        let names: Vec<_> = snapshots.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["first", "second"]);
+
+        // Not while another operation holds the scratch
+        let lock = ScratchLock::acquire(&config, "feature-x", "restore").unwrap();
+        assert!(matches!(
+            delete_snapshot(&config, "feature-x", "first"),
+            Err(Error::ScratchBusy(..))
+        ));
+        drop(lock);
+
+        delete_snapshot(&config, "feature-x", "first").unwrap();
+        assert_eq!(list_snapshots(&config, "feature-x").unwrap().len(), 1);
+        assert!(matches!(
+            delete_snapshot(&config, "feature-x", "first"),
+            Err(Error::SnapshotNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_snapshot_databases() {
+        let mut scratch_config: ScratchConfig = toml::from_str(
+            "name = \"feature-x\"\nbranch = \"main\"\ntemplate = \"default\"\n\
+             created_at = \"2024-01-01T00:00:00Z\"\nservices = []\nenv = {}\n\
+             databases = { postgres = [\"scratch_feature_x_b\", \"scratch_feature_x\"] }",
+        )
+        .unwrap();
+        assert_eq!(
+            snapshot_databases(&scratch_config).unwrap(),
+            ["scratch_feature_x", "scratch_feature_x_b"]
+        );
+
+        scratch_config
+            .databases
+            .insert("mysql".to_string(), vec!["feature_x".to_string()]);
+        assert!(snapshot_databases(&scratch_config).is_err());
+    }
+}
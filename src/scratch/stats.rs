@@ -0,0 +1,126 @@
+//! Live resource usage of scratches
+//!
+//! Usage is read from the Docker stats of every running container labeled
+//! with the scratch, summed per scratch and broken down per service.
+
+use chrono::{DateTime, Utc};
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+use crate::docker::{ContainerStatus, DockerClient, ResourceUsage};
+use crate::error::Result;
+
+use super::load_scratch_config;
+
+/// Resource usage of a scratch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScratchStats {
+    pub scratch: String,
+    /// Usage summed across all of the scratch's running containers
+    pub total: ResourceUsage,
+    /// Usage by service
+    pub services: BTreeMap<String, ResourceUsage>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ScratchStats {
+    fn new(scratch: &str) -> Self {
+        Self {
+            scratch: scratch.to_string(),
+            total: ResourceUsage::default(),
+            services: BTreeMap::new(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn add(&mut self, service: &str, usage: &ResourceUsage) {
+        self.total.add(usage);
+        self.services
+            .entry(service.to_string())
+            .or_default()
+            .add(usage);
+    }
+}
+
+/// Get the resource usage of a scratch
+pub async fn get_scratch_stats(
+    config: &Config,
+    docker: &DockerClient,
+    name: &str,
+) -> Result<ScratchStats> {
+    // Fails with ScratchNotFound for unknown scratches
+    load_scratch_config(config, name)?;
+    scratch_stats(docker, name).await
+}
+
+/// Get the resource usage of the containers labeled with a scratch name
+///
+/// Unlike [`get_scratch_stats`], this doesn't check that the scratch exists.
+pub async fn scratch_stats(docker: &DockerClient, name: &str) -> Result<ScratchStats> {
+    let containers = docker.list_scratch_containers(Some(name)).await?;
+    let mut stats = collect_stats(docker, containers).await?;
+    Ok(stats
+        .remove(name)
+        .unwrap_or_else(|| ScratchStats::new(name)))
+}
+
+/// Get the resource usage of every scratch with running containers
+pub async fn list_scratch_stats(docker: &DockerClient) -> Result<Vec<ScratchStats>> {
+    let containers = docker.list_scratch_containers(None).await?;
+    Ok(collect_stats(docker, containers)
+        .await?
+        .into_values()
+        .collect())
+}
+
+/// Read the stats of the running containers and group them by scratch
+async fn collect_stats(
+    docker: &DockerClient,
+    containers: Vec<ContainerStatus>,
+) -> Result<BTreeMap<String, ScratchStats>> {
+    let label_prefix = &docker.config().label_prefix;
+    let scratch_label = format!("{}.scratch", label_prefix);
+    let service_label = format!("{}.service", label_prefix);
+
+    let running: Vec<_> = containers
+        .into_iter()
+        .filter(|c| c.state == "running")
+        .collect();
+    let usages = join_all(running.iter().map(|c| docker.container_stats(&c.id))).await;
+
+    let mut stats: BTreeMap<String, ScratchStats> = BTreeMap::new();
+    for (container, usage) in running.iter().zip(usages) {
+        let Some(scratch) = container.labels.get(&scratch_label) else {
+            continue;
+        };
+        let usage = match usage {
+            Ok(usage) => usage,
+            // The container may have stopped since it was listed
+            Err(e) => {
+                tracing::debug!("No stats for container {}: {}", container.name, e);
+                continue;
+            }
+        };
+        let service = container
+            .labels
+            .get(&service_label)
+            .unwrap_or(&container.name);
+
+        stats
+            .entry(scratch.clone())
+            .or_insert_with(|| ScratchStats::new(scratch))
+            .add(service, &usage);
+    }
+
+    // Unlimited containers report the host's memory as their limit, so the
+    // sum can't usefully go above it
+    if let Some(host_memory) = docker.host_memory().await {
+        for scratch in stats.values_mut() {
+            scratch.total.memory_limit = scratch.total.memory_limit.min(host_memory);
+        }
+    }
+
+    Ok(stats)
+}
@@ -1,18 +1,36 @@
 //! Scratch status tracking
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 /// Status of a scratch environment
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScratchStatus {
     pub name: String,
     pub branch: String,
+    /// Branch per repository, for services that don't run `branch`
+    #[serde(default)]
+    pub branches: BTreeMap<String, String>,
     pub status: String,
+    /// Image each per-scratch service runs, as resolved or built
+    #[serde(default)]
+    pub images: BTreeMap<String, String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
     pub services: HashMap<String, String>,
     pub databases: Vec<String>,
     pub url: Option<String>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_activity_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub error: Option<String>,
+    /// The saved compose file differs from what the current config renders
+    #[serde(default)]
+    pub outdated: bool,
 }
 
 impl ScratchStatus {
@@ -21,16 +39,30 @@ impl ScratchStatus {
         Self {
             name,
             branch,
+            branches: BTreeMap::new(),
             status: "unknown".to_string(),
+            images: BTreeMap::new(),
+            owner: None,
+            description: None,
+            tags: BTreeSet::new(),
             services: HashMap::new(),
             databases: Vec::new(),
             url: None,
             created_at: None,
+            expires_at: None,
+            last_activity_at: None,
+            error: None,
+            outdated: false,
         }
     }
 
     /// Determine overall status from service statuses
     pub fn calculate_status(&mut self) {
+        if self.error.is_some() {
+            self.status = "failed".to_string();
+            return;
+        }
+
         if self.services.is_empty() {
             self.status = "stopped".to_string();
             return;
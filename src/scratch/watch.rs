@@ -0,0 +1,580 @@
+//! Redeploying scratches when an image tag they run is pushed again
+//!
+//! Scratches often run a moving tag like `myorg/api:feature-x` that CI pushes
+//! on every commit. The image watcher looks up the digest each running
+//! service's tag currently points to, either through the Docker daemon or
+//! straight from the registry, and compares it with the digests of the image
+//! the container was created from. Services whose tag has moved on are
+//! pulled and recreated, leaving the rest of the scratch untouched.
+//!
+//! Services built from source, shared services and images pinned by digest
+//! are never watched.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+
+use reqwest::header::{ACCEPT, WWW_AUTHENTICATE};
+use reqwest::{StatusCode, Url};
+
+use crate::config::{Config, WatchConfig};
+use crate::docker::DockerClient;
+use crate::error::{Error, Result};
+
+use super::{report, run_compose, wait_for_scratch_healthy, ScratchLock};
+
+/// Log of redeploys, relative to the scratch directory
+pub const REDEPLOY_LOG: &str = "logs/redeploys.log";
+
+/// Manifest types accepted when asking a registry for a tag's digest
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+/// Looks up the digest an image reference currently points to
+pub trait DigestResolver {
+    /// The digest of `image`, or `None` if the registry doesn't have it
+    fn digest(&self, image: &str) -> impl Future<Output = Result<Option<String>>> + Send;
+}
+
+/// Resolves digests through the Docker daemon, with its registry credentials
+pub struct DaemonDigestResolver<'a> {
+    docker: &'a DockerClient,
+}
+
+impl<'a> DaemonDigestResolver<'a> {
+    pub fn new(docker: &'a DockerClient) -> Self {
+        Self { docker }
+    }
+}
+
+impl DigestResolver for DaemonDigestResolver<'_> {
+    fn digest(&self, image: &str) -> impl Future<Output = Result<Option<String>>> + Send {
+        self.docker.registry_digest(image)
+    }
+}
+
+/// Resolves digests with the registry HTTP API
+///
+/// Only anonymous pulls are supported: a registry asking for a bearer token
+/// is sent through its token flow without credentials.
+pub struct RegistryDigestResolver {
+    client: reqwest::Client,
+    insecure_registries: Vec<String>,
+}
+
+impl RegistryDigestResolver {
+    pub fn new(watch: &WatchConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            insecure_registries: watch.insecure_registries.clone(),
+        }
+    }
+
+    /// URL of the manifest of an image reference
+    fn manifest_url(&self, image: &ImageReference) -> String {
+        let scheme = if self.insecure_registries.contains(&image.registry) {
+            "http"
+        } else {
+            "https"
+        };
+        format!(
+            "{}://{}/v2/{}/manifests/{}",
+            scheme,
+            image.host(),
+            image.repository,
+            image.tag
+        )
+    }
+
+    /// Fetch an anonymous token for the realm a registry pointed us to
+    async fn token(&self, challenge: &str) -> Result<String> {
+        let params = parse_challenge(challenge);
+        let realm = params
+            .get("realm")
+            .ok_or_else(|| Error::Other(format!("Unsupported auth challenge: {}", challenge)))?;
+        let query: Vec<(&str, &str)> = ["service", "scope"]
+            .into_iter()
+            .filter_map(|key| params.get(key).map(|value| (key, value.as_str())))
+            .collect();
+        let url = Url::parse_with_params(realm, &query)
+            .map_err(|e| Error::Other(format!("Invalid token realm {}: {}", realm, e)))?;
+
+        let body: serde_json::Value = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(registry_error)?
+            .json()
+            .await
+            .map_err(registry_error)?;
+        body.get("token")
+            .or_else(|| body.get("access_token"))
+            .and_then(|t| t.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| Error::Other("Registry token response has no token".to_string()))
+    }
+}
+
+impl DigestResolver for RegistryDigestResolver {
+    async fn digest(&self, image: &str) -> Result<Option<String>> {
+        let image = ImageReference::parse(image);
+        let url = self.manifest_url(&image);
+        let head = || self.client.head(&url).header(ACCEPT, MANIFEST_TYPES);
+
+        let mut response = head().send().await.map_err(registry_error)?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            let challenge = response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            let token = self.token(&challenge).await?;
+            response = head()
+                .bearer_auth(token)
+                .send()
+                .await
+                .map_err(registry_error)?;
+        }
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response.error_for_status().map_err(registry_error)?;
+        Ok(response
+            .headers()
+            .get("docker-content-digest")
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string))
+    }
+}
+
+fn registry_error(e: reqwest::Error) -> Error {
+    Error::Other(format!("Registry request failed: {}", e))
+}
+
+/// Parse the parameters of a `Bearer realm="...",service="..."` challenge
+fn parse_challenge(challenge: &str) -> HashMap<String, String> {
+    let params = challenge
+        .strip_prefix("Bearer ")
+        .or_else(|| challenge.strip_prefix("bearer "))
+        .unwrap_or_default();
+
+    let mut parsed = HashMap::new();
+    let mut rest = params.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim();
+        let (value, remainder) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        parsed.insert(key.to_string(), value.to_string());
+        rest = remainder.trim_start_matches(',').trim();
+    }
+    parsed
+}
+
+/// An image reference split into the parts the registry API needs
+#[derive(Debug, PartialEq, Eq)]
+struct ImageReference {
+    registry: String,
+    repository: String,
+    tag: String,
+}
+
+impl ImageReference {
+    /// Parse `[registry/]repository[:tag]`, the way the docker CLI does
+    fn parse(image: &str) -> Self {
+        let (registry, path) = match image.split_once('/') {
+            Some((first, rest))
+                if first.contains('.') || first.contains(':') || first == "localhost" =>
+            {
+                (first.to_string(), rest)
+            }
+            _ => ("docker.io".to_string(), image),
+        };
+
+        let (repository, tag) = match path.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+            _ => (path, "latest"),
+        };
+        let repository = if registry == "docker.io" && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository.to_string()
+        };
+
+        Self {
+            registry,
+            repository,
+            tag: tag.to_string(),
+        }
+    }
+
+    /// Host serving the registry API
+    fn host(&self) -> &str {
+        match self.registry.as_str() {
+            "docker.io" => "registry-1.docker.io",
+            registry => registry,
+        }
+    }
+}
+
+/// A service of a scratch whose image tag points to a new digest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageUpdate {
+    pub scratch: String,
+    pub services: Vec<String>,
+    pub image: String,
+    pub digest: String,
+}
+
+/// A running service the watcher keeps an eye on
+#[derive(Debug, Clone)]
+pub struct WatchedService {
+    pub scratch: String,
+    pub service: String,
+    pub image: String,
+    /// `repo@digest` references of the image the container runs
+    pub digests: Vec<String>,
+}
+
+/// Finds scratch services running an outdated image
+///
+/// A digest counts as redeployed once its redeploy has succeeded; until then
+/// it is picked up again on every check.
+#[derive(Debug, Default)]
+pub struct ImageWatcher {
+    /// Digest last redeployed per scratch and service
+    redeployed: HashMap<(String, String), String>,
+}
+
+impl ImageWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check the images of every running scratch service
+    pub async fn check(
+        &mut self,
+        config: &Config,
+        docker: &DockerClient,
+        resolver: &impl DigestResolver,
+    ) -> Result<Vec<ImageUpdate>> {
+        let watched = watched_services(config, docker).await?;
+        Ok(self.outdated(watched, resolver).await)
+    }
+
+    /// The services whose tag now points elsewhere, grouped by scratch and image
+    ///
+    /// Every image is resolved once, however many scratches run it.
+    pub async fn outdated(
+        &mut self,
+        watched: Vec<WatchedService>,
+        resolver: &impl DigestResolver,
+    ) -> Vec<ImageUpdate> {
+        let mut resolved: HashMap<String, Option<String>> = HashMap::new();
+        let mut updates: BTreeMap<(String, String), ImageUpdate> = BTreeMap::new();
+
+        for service in watched {
+            if !resolved.contains_key(&service.image) {
+                let digest = match resolver.digest(&service.image).await {
+                    Ok(digest) => digest,
+                    Err(e) => {
+                        tracing::warn!("Could not resolve digest of {}: {}", service.image, e);
+                        None
+                    }
+                };
+                resolved.insert(service.image.clone(), digest);
+            }
+            let Some(digest) = resolved[&service.image].clone() else {
+                continue;
+            };
+
+            let running = service
+                .digests
+                .iter()
+                .any(|d| d.rsplit_once('@').is_some_and(|(_, d)| d == digest));
+            let key = (service.scratch.clone(), service.service.clone());
+            if running || self.redeployed.get(&key) == Some(&digest) {
+                continue;
+            }
+
+            tracing::info!(
+                "Image {} of scratch '{}' ({}) has a new digest: {}",
+                service.image,
+                service.scratch,
+                service.service,
+                digest
+            );
+            updates
+                .entry((service.scratch.clone(), service.image.clone()))
+                .or_insert_with(|| ImageUpdate {
+                    scratch: service.scratch,
+                    services: Vec::new(),
+                    image: service.image,
+                    digest,
+                })
+                .services
+                .push(service.service);
+        }
+
+        updates.into_values().collect()
+    }
+
+    /// Record that an update's services now run its digest
+    pub fn mark_redeployed(&mut self, update: &ImageUpdate) {
+        for service in &update.services {
+            self.redeployed.insert(
+                (update.scratch.clone(), service.clone()),
+                update.digest.clone(),
+            );
+        }
+    }
+}
+
+/// The running per-scratch services the watcher can redeploy
+///
+/// Scratches with an operation in progress are skipped until the next pass.
+async fn watched_services(config: &Config, docker: &DockerClient) -> Result<Vec<WatchedService>> {
+    let scratch_label = format!("{}.scratch", config.docker.label_prefix);
+    let service_label = format!("{}.service", config.docker.label_prefix);
+
+    let mut watched = Vec::new();
+    for container in docker.list_scratch_containers(None).await? {
+        let (Some(scratch), Some(service)) = (
+            container.labels.get(&scratch_label),
+            container.labels.get(&service_label),
+        ) else {
+            continue;
+        };
+        let watchable = config
+            .get_service(service)
+            .is_some_and(|s| !s.shared && s.build.is_none());
+        if container.state != "running"
+            || !watchable
+            || container.image.contains('@')
+            || container.image.starts_with("sha256:")
+            || ScratchLock::check(config, scratch).is_err()
+        {
+            continue;
+        }
+
+        // Images that were never pulled have nothing to compare against
+        let digests = docker
+            .image_repo_digests(&container.image_id)
+            .await
+            .unwrap_or_default();
+        if digests.is_empty() {
+            continue;
+        }
+
+        watched.push(WatchedService {
+            scratch: scratch.clone(),
+            service: service.clone(),
+            image: container.image,
+            digests,
+        });
+    }
+    Ok(watched)
+}
+
+/// Append a redeploy and how it went to the scratch's redeploy log
+pub fn record_redeploy(
+    config: &Config,
+    update: &ImageUpdate,
+    job_id: &str,
+    outcome: &str,
+) -> Result<()> {
+    let scratch_dir = config.server.releases_dir.join(&update.scratch);
+    if !scratch_dir.exists() {
+        return Err(Error::ScratchNotFound(update.scratch.clone()));
+    }
+    let path = scratch_dir.join(REDEPLOY_LOG);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut log = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(
+        log,
+        "[{}] job {} {}: {} to {} ({})",
+        chrono::Utc::now().to_rfc3339(),
+        job_id,
+        outcome,
+        update.services.join(", "),
+        update.image,
+        update.digest
+    )?;
+    Ok(())
+}
+
+/// Pull the images of some of a scratch's services and recreate them
+pub async fn redeploy_services(
+    config: &Config,
+    docker: &DockerClient,
+    name: &str,
+    services: &[String],
+) -> Result<()> {
+    let scratch_dir = config.server.releases_dir.join(name);
+    let _lock = ScratchLock::acquire(config, name, "redeploy")?;
+    let services: Vec<&str> = services.iter().map(String::as_str).collect();
+
+    tracing::info!("Redeploying {} of scratch '{}'", services.join(", "), name);
+    report("pull", format!("Pulling {}", services.join(", ")));
+    run_compose(&scratch_dir, &[&["pull"], &services[..]].concat()).await?;
+
+    report("up", "Recreating services");
+    run_compose(
+        &scratch_dir,
+        &[&["up", "-d", "--no-deps"], &services[..]].concat(),
+    )
+    .await?;
+
+    report("health", "Waiting for services to become healthy");
+    wait_for_scratch_healthy(config, docker, name).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeResolver(HashMap<&'static str, &'static str>);
+
+    impl DigestResolver for FakeResolver {
+        async fn digest(&self, image: &str) -> Result<Option<String>> {
+            Ok(self.0.get(image).map(|d| d.to_string()))
+        }
+    }
+
+    fn service(scratch: &str, service: &str, image: &str, digest: &str) -> WatchedService {
+        WatchedService {
+            scratch: scratch.to_string(),
+            service: service.to_string(),
+            image: image.to_string(),
+            digests: vec![format!("myorg/api@{}", digest)],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_outdated_services() {
+        let resolver = FakeResolver(HashMap::from([
+            ("myorg/api:feat-x", "sha256:new"),
+            ("myorg/api:main", "sha256:main"),
+        ]));
+        let watched = vec![
+            service("feat-x", "api", "myorg/api:feat-x", "sha256:old"),
+            service("feat-x", "worker", "myorg/api:feat-x", "sha256:old"),
+            service("main", "api", "myorg/api:main", "sha256:main"),
+            service("gone", "api", "myorg/api:gone", "sha256:old"),
+        ];
+
+        let mut watcher = ImageWatcher::new();
+        let updates = watcher.outdated(watched.clone(), &resolver).await;
+        assert_eq!(
+            updates,
+            [ImageUpdate {
+                scratch: "feat-x".to_string(),
+                services: vec!["api".to_string(), "worker".to_string()],
+                image: "myorg/api:feat-x".to_string(),
+                digest: "sha256:new".to_string(),
+            }]
+        );
+
+        // Until its redeploy succeeds, the digest is picked up again
+        assert_eq!(watcher.outdated(watched.clone(), &resolver).await, updates);
+        watcher.mark_redeployed(&updates[0]);
+        assert!(watcher.outdated(watched, &resolver).await.is_empty());
+    }
+
+    #[test]
+    fn test_record_redeploy() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.server.releases_dir = temp.path().to_path_buf();
+        let update = ImageUpdate {
+            scratch: "feat-x".to_string(),
+            services: vec!["api".to_string(), "worker".to_string()],
+            image: "myorg/api:feat-x".to_string(),
+            digest: "sha256:new".to_string(),
+        };
+        assert!(record_redeploy(&config, &update, "job-1", "queued").is_err());
+
+        fs::create_dir_all(temp.path().join("feat-x")).unwrap();
+        record_redeploy(&config, &update, "job-1", "queued").unwrap();
+        record_redeploy(&config, &update, "job-1", "succeeded").unwrap();
+        let log = fs::read_to_string(temp.path().join("feat-x").join(REDEPLOY_LOG)).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(
+            lines[1].ends_with("job job-1 succeeded: api, worker to myorg/api:feat-x (sha256:new)")
+        );
+    }
+
+    #[test]
+    fn test_parse_image_reference() {
+        let parse = |image| {
+            let image = ImageReference::parse(image);
+            (image.host().to_string(), image.repository, image.tag)
+        };
+
+        assert_eq!(
+            parse("nginx"),
+            (
+                "registry-1.docker.io".into(),
+                "library/nginx".into(),
+                "latest".into()
+            )
+        );
+        assert_eq!(
+            parse("myorg/api:feat-x"),
+            (
+                "registry-1.docker.io".into(),
+                "myorg/api".into(),
+                "feat-x".into()
+            )
+        );
+        assert_eq!(
+            parse("localhost:5000/api"),
+            ("localhost:5000".into(), "api".into(), "latest".into())
+        );
+        assert_eq!(
+            parse("ghcr.io/myorg/api:1.2"),
+            ("ghcr.io".into(), "myorg/api".into(), "1.2".into())
+        );
+    }
+
+    #[test]
+    fn test_manifest_url_scheme() {
+        let watch = WatchConfig {
+            insecure_registries: vec!["localhost:5000".to_string()],
+            ..Default::default()
+        };
+        let resolver = RegistryDigestResolver::new(&watch);
+
+        assert_eq!(
+            resolver.manifest_url(&ImageReference::parse("localhost:5000/api:main")),
+            "http://localhost:5000/v2/api/manifests/main"
+        );
+        assert_eq!(
+            resolver.manifest_url(&ImageReference::parse("ghcr.io/myorg/api")),
+            "https://ghcr.io/v2/myorg/api/manifests/latest"
+        );
+    }
+
+    #[test]
+    fn test_parse_challenge() {
+        let params = parse_challenge(
+            "Bearer realm=\"https://auth.docker.io/token\",\
+             service=\"registry.docker.io\",scope=\"repository:library/nginx:pull\"",
+        );
+        assert_eq!(params["realm"], "https://auth.docker.io/token");
+        assert_eq!(params["service"], "registry.docker.io");
+        assert_eq!(params["scope"], "repository:library/nginx:pull");
+    }
+}
@@ -1,7 +1,9 @@
 //! Service provisioning (postgres, redis, kafka, etc.)
 
 mod postgres;
+mod seed;
 mod shared;
 
 pub use postgres::*;
+pub use seed::*;
 pub use shared::*;
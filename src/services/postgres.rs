@@ -1,10 +1,18 @@
 //! PostgreSQL database provisioning
 
+use tokio_postgres::Client;
+
 use crate::config::Config;
+use crate::docker::{DockerClient, ExecOptions};
 use crate::error::{Error, Result};
 
-/// Create a PostgreSQL database
-pub async fn create_postgres_database(config: &Config, db_name: &str) -> Result<()> {
+use super::TEMPLATE_DATABASE_PREFIX;
+
+/// How custom format dumps (`pg_dump -Fc`) start
+const CUSTOM_DUMP_MAGIC: &[u8] = b"PGDMP";
+
+/// Connect to the `postgres` maintenance database of the shared postgres service
+pub(super) async fn connect_admin(config: &Config) -> Result<Client> {
     let postgres_config = config
         .get_service("postgres")
         .ok_or_else(|| Error::ServiceNotFound("postgres".to_string()))?;
@@ -58,26 +66,67 @@ pub async fn create_postgres_database(config: &Config, db_name: &str) -> Result<
         }
     });
 
-    // Check if database exists
+    Ok(client)
+}
+
+/// Check that a database name is safe to interpolate into SQL
+pub(super) fn validate_db_name(db_name: &str) -> Result<()> {
+    if !db_name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(Error::Config(format!("Invalid database name: {}", db_name)));
+    }
+    Ok(())
+}
+
+/// Terminate all connections to a database
+pub(super) async fn terminate_connections(client: &Client, db_name: &str) {
+    let terminate_query = format!(
+        "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE datname = '{}'",
+        db_name
+    );
+    let _ = client.execute(&terminate_query, &[]).await;
+}
+
+/// Whether a database exists
+pub(super) async fn database_exists(client: &Client, db_name: &str) -> Result<bool> {
     let exists = client
         .query_one(
             "SELECT EXISTS(SELECT 1 FROM pg_database WHERE datname = $1)",
             &[&db_name],
         )
         .await?;
+    Ok(exists.get(0))
+}
 
-    let db_exists: bool = exists.get(0);
-
-    if !db_exists {
+/// Create a PostgreSQL database, optionally as a copy of a template database
+///
+/// Copying uses `CREATE DATABASE ... TEMPLATE`, which requires that nobody
+/// is connected to the template, so existing connections to it are
+/// terminated.
+pub async fn create_postgres_database(
+    config: &Config,
+    db_name: &str,
+    template: Option<&str>,
+) -> Result<()> {
+    let client = connect_admin(config).await?;
+
+    if !database_exists(&client, db_name).await? {
         // Create database (can't use parameterized query for CREATE DATABASE)
         // Validate db_name to prevent SQL injection
-        if !db_name.chars().all(|c| c.is_alphanumeric() || c == '_') {
-            return Err(Error::Config(format!("Invalid database name: {}", db_name)));
-        }
-
-        let query = format!("CREATE DATABASE {}", db_name);
+        validate_db_name(db_name)?;
+
+        let query = match template {
+            Some(template) => {
+                validate_db_name(template)?;
+                terminate_connections(&client, template).await;
+                format!("CREATE DATABASE {} TEMPLATE {}", db_name, template)
+            }
+            None => format!("CREATE DATABASE {}", db_name),
+        };
         client.execute(&query, &[]).await?;
-        tracing::info!("Created database: {}", db_name);
+        match template {
+            Some(template) => tracing::info!("Created database {} from {}", db_name, template),
+            None => tracing::info!("Created database: {}", db_name),
+        }
     } else {
         tracing::debug!("Database already exists: {}", db_name);
     }
@@ -85,69 +134,29 @@ pub async fn create_postgres_database(config: &Config, db_name: &str) -> Result<
     Ok(())
 }
 
-/// Drop a PostgreSQL database
-pub async fn drop_postgres_database(config: &Config, db_name: &str) -> Result<()> {
-    let postgres_config = config
-        .get_service("postgres")
-        .ok_or_else(|| Error::ServiceNotFound("postgres".to_string()))?;
-
-    let host = postgres_config
-        .connection
-        .as_ref()
-        .map(|c| c.host.as_str())
-        .unwrap_or("localhost");
-
-    let port = postgres_config
-        .connection
-        .as_ref()
-        .map(|c| c.port)
-        .unwrap_or(5432);
-
-    let user = postgres_config
-        .connection
-        .as_ref()
-        .map(|c| c.user.as_str())
-        .or_else(|| postgres_config.env.get("POSTGRES_USER").map(|s| s.as_str()))
-        .unwrap_or("postgres");
-
-    let password = postgres_config
-        .connection
-        .as_ref()
-        .map(|c| c.password.as_str())
-        .or_else(|| {
-            postgres_config
-                .env
-                .get("POSTGRES_PASSWORD")
-                .map(|s| s.as_str())
-        })
-        .unwrap_or("postgres");
-
-    let conn_string = format!(
-        "host={} port={} user={} password={} dbname=postgres",
-        host, port, user, password
-    );
+/// Create a PostgreSQL database as a copy of an existing one
+///
+/// Unlike [`create_postgres_database`], fails if the database already exists.
+pub async fn clone_postgres_database(config: &Config, source: &str, db_name: &str) -> Result<()> {
+    let client = connect_admin(config).await?;
+    if database_exists(&client, db_name).await? {
+        return Err(Error::Other(format!(
+            "Database already exists: {}",
+            db_name
+        )));
+    }
 
-    let (client, connection) = tokio_postgres::connect(&conn_string, tokio_postgres::NoTls)
-        .await
-        .map_err(Error::Database)?;
+    create_postgres_database(config, db_name, Some(source)).await
+}
 
-    tokio::spawn(async move {
-        if let Err(e) = connection.await {
-            tracing::error!("PostgreSQL connection error: {}", e);
-        }
-    });
+/// Drop a PostgreSQL database
+pub async fn drop_postgres_database(config: &Config, db_name: &str) -> Result<()> {
+    let client = connect_admin(config).await?;
 
-    // Validate db_name
-    if !db_name.chars().all(|c| c.is_alphanumeric() || c == '_') {
-        return Err(Error::Config(format!("Invalid database name: {}", db_name)));
-    }
+    validate_db_name(db_name)?;
 
     // Terminate connections to the database
-    let terminate_query = format!(
-        "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE datname = '{}'",
-        db_name
-    );
-    let _ = client.execute(&terminate_query, &[]).await;
+    terminate_connections(&client, db_name).await;
 
     // Drop database
     let query = format!("DROP DATABASE IF EXISTS {}", db_name);
@@ -157,66 +166,136 @@ pub async fn drop_postgres_database(config: &Config, db_name: &str) -> Result<()
     Ok(())
 }
 
-/// List all scratchpad databases
-#[allow(dead_code)]
-pub async fn list_databases(config: &Config) -> Result<Vec<String>> {
+/// Get the superuser name of the shared postgres service
+pub(super) fn postgres_user(config: &Config) -> Result<String> {
     let postgres_config = config
         .get_service("postgres")
         .ok_or_else(|| Error::ServiceNotFound("postgres".to_string()))?;
 
-    let host = postgres_config
+    Ok(postgres_config
         .connection
         .as_ref()
-        .map(|c| c.host.as_str())
-        .unwrap_or("localhost");
-
-    let port = postgres_config
-        .connection
-        .as_ref()
-        .map(|c| c.port)
-        .unwrap_or(5432);
+        .map(|c| c.user.clone())
+        .or_else(|| postgres_config.env.get("POSTGRES_USER").cloned())
+        .unwrap_or_else(|| "postgres".to_string()))
+}
 
-    let user = postgres_config
-        .connection
-        .as_ref()
-        .map(|c| c.user.as_str())
-        .or_else(|| postgres_config.env.get("POSTGRES_USER").map(|s| s.as_str()))
-        .unwrap_or("postgres");
+/// Dump a database to SQL by running pg_dump inside the shared postgres container
+pub async fn dump_postgres_database(
+    config: &Config,
+    docker: &DockerClient,
+    db_name: &str,
+) -> Result<String> {
+    validate_db_name(db_name)?;
+    let user = postgres_user(config)?;
+
+    // Keep pg_dump's warnings on stderr out of the dump
+    let options = ExecOptions {
+        stdout_only: true,
+        check_exit_code: true,
+        ..Default::default()
+    };
+    let output = docker
+        .exec(
+            "scratchpad-postgres",
+            vec!["pg_dump", "-U", &user, "--no-owner", db_name],
+            options,
+        )
+        .await
+        .map_err(|e| Error::Other(format!("pg_dump of {} failed: {}", db_name, e)))?
+        .output;
+
+    // Make sure the dump wasn't cut short
+    if !output.contains("PostgreSQL database dump complete") {
+        return Err(Error::Other(format!(
+            "pg_dump of {} ended before the dump was complete",
+            db_name
+        )));
+    }
 
-    let password = postgres_config
-        .connection
-        .as_ref()
-        .map(|c| c.password.as_str())
-        .or_else(|| {
-            postgres_config
-                .env
-                .get("POSTGRES_PASSWORD")
-                .map(|s| s.as_str())
-        })
-        .unwrap_or("postgres");
+    tracing::info!("Dumped database: {}", db_name);
+    Ok(output)
+}
 
-    let conn_string = format!(
-        "host={} port={} user={} password={} dbname=postgres",
-        host, port, user, password
-    );
+/// Replace the contents of a database with a SQL dump
+///
+/// The database is dropped and recreated before the dump is loaded with psql
+/// inside the shared postgres container.
+pub async fn restore_postgres_database(
+    config: &Config,
+    docker: &DockerClient,
+    db_name: &str,
+    dump: &str,
+) -> Result<()> {
+    validate_db_name(db_name)?;
+    let user = postgres_user(config)?;
+
+    drop_postgres_database(config, db_name).await?;
+    create_postgres_database(config, db_name, None).await?;
+    load_dump(docker, &user, db_name, dump.as_bytes()).await?;
+
+    tracing::info!("Restored database: {}", db_name);
+    Ok(())
+}
 
-    let (client, connection) = tokio_postgres::connect(&conn_string, tokio_postgres::NoTls)
-        .await
-        .map_err(Error::Database)?;
+/// Load a dump into a database inside the shared postgres container
+///
+/// Plain SQL dumps go through psql, custom format dumps (`pg_dump -Fc`)
+/// through pg_restore.
+pub(super) async fn load_dump(
+    docker: &DockerClient,
+    user: &str,
+    db_name: &str,
+    dump: &[u8],
+) -> Result<()> {
+    let cmd = if dump.starts_with(CUSTOM_DUMP_MAGIC) {
+        vec![
+            "pg_restore",
+            "-U",
+            user,
+            "-d",
+            db_name,
+            "--no-owner",
+            "--exit-on-error",
+        ]
+    } else {
+        vec![
+            "psql",
+            "-U",
+            user,
+            "-d",
+            db_name,
+            "-q",
+            "-v",
+            "ON_ERROR_STOP=1",
+        ]
+    };
+    let options = ExecOptions {
+        input: Some(dump),
+        check_exit_code: true,
+        ..Default::default()
+    };
+    docker.exec("scratchpad-postgres", cmd, options).await?;
+    Ok(())
+}
 
-    tokio::spawn(async move {
-        if let Err(e) = connection.await {
-            tracing::error!("PostgreSQL connection error: {}", e);
-        }
-    });
+/// List the databases created for scratches
+pub async fn list_databases(config: &Config) -> Result<Vec<String>> {
+    let client = connect_admin(config).await?;
 
+    // `_` is a wildcard in LIKE, so match the prefix exactly
     let rows = client
         .query(
-            "SELECT datname FROM pg_database WHERE datname LIKE 'scratch_%'",
+            "SELECT datname FROM pg_database WHERE left(datname, 8) = 'scratch_'",
             &[],
         )
         .await?;
 
-    let databases: Vec<String> = rows.iter().map(|row| row.get(0)).collect();
+    // Template databases are shared by scratches, not owned by one
+    let databases: Vec<String> = rows
+        .iter()
+        .map(|row| row.get::<_, String>(0))
+        .filter(|name| !name.starts_with(TEMPLATE_DATABASE_PREFIX))
+        .collect();
     Ok(databases)
 }
@@ -0,0 +1,371 @@
+//! Seeding scratch databases from a golden template
+//!
+//! Running migrations and seeds for every new scratch is slow. With
+//! `seed_from` set on the postgres service (or overridden by a profile), new
+//! scratch databases are copied with `CREATE DATABASE ... TEMPLATE` from:
+//!
+//! - the named database, when `seed_from` is a database name
+//! - `scratch_template_<profile>`, when `seed_from` is a dump file. The dump
+//!   is loaded into it the first time a scratch needs it, and again by
+//!   `scratchpad db template refresh`. Profiles without a `seed_from` of their
+//!   own share `scratch_template_default` with the postgres service. A
+//!   relative dump path is relative to the config file.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tokio_postgres::Client;
+
+use crate::config::Config;
+use crate::docker::DockerClient;
+use crate::error::{Error, Result};
+
+use super::postgres::{
+    connect_admin, database_exists, load_dump, postgres_user, terminate_connections,
+    validate_db_name,
+};
+
+/// Prefix of the template databases dumps are loaded into
+pub const TEMPLATE_DATABASE_PREFIX: &str = "scratch_template_";
+
+/// Where new scratch databases are copied from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeedSource {
+    /// An existing database
+    Database(String),
+    /// A dump file loaded into a template database
+    Dump(PathBuf),
+}
+
+impl SeedSource {
+    /// Parse a `seed_from` value: a plain identifier names a database,
+    /// anything else (e.g. `./seed.sql`) is a dump file
+    pub fn parse(seed_from: &str) -> Self {
+        let seed_from = seed_from.trim();
+        if validate_db_name(seed_from).is_ok() {
+            SeedSource::Database(seed_from.to_string())
+        } else {
+            SeedSource::Dump(PathBuf::from(seed_from))
+        }
+    }
+}
+
+/// Where the databases of scratches with a profile are seeded from, if anywhere
+pub fn seed_source(config: &Config, profile: Option<&str>) -> Option<SeedSource> {
+    own_seed_from(config, profile)
+        .or_else(|| {
+            config
+                .get_service("postgres")
+                .and_then(|s| s.seed_from.as_deref())
+        })
+        .filter(|seed_from| !seed_from.trim().is_empty())
+        .map(|seed_from| match SeedSource::parse(seed_from) {
+            SeedSource::Dump(dump) => match &config.base_dir {
+                Some(base_dir) if dump.is_relative() => SeedSource::Dump(base_dir.join(dump)),
+                _ => SeedSource::Dump(dump),
+            },
+            source => source,
+        })
+}
+
+/// Name of the template database a profile's dump is loaded into
+///
+/// Only profiles with a `seed_from` of their own get their own template;
+/// the others use the postgres service's.
+pub fn template_database_name(config: &Config, profile: Option<&str>) -> String {
+    let suffix: String = profile
+        .filter(|_| own_seed_from(config, profile).is_some())
+        .unwrap_or("default")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}{}", TEMPLATE_DATABASE_PREFIX, suffix)
+}
+
+/// Fail if two dumps would be loaded into the same template database
+///
+/// Profile names are lowercased and stripped of punctuation in template
+/// names, so e.g. `qa-full` and `QA_full` would overwrite each other's.
+pub fn check_template_databases(config: &Config) -> Result<()> {
+    let mut profiles: Vec<Option<&str>> = config
+        .scratch
+        .profiles
+        .keys()
+        .map(|name| Some(name.as_str()))
+        .collect();
+    profiles.sort();
+    profiles.insert(0, None);
+
+    let mut templates: HashMap<String, Option<&str>> = HashMap::new();
+    for profile in profiles {
+        if profile.is_some() && own_seed_from(config, profile).is_none() {
+            continue;
+        }
+        if !matches!(seed_source(config, profile), Some(SeedSource::Dump(_))) {
+            continue;
+        }
+
+        let name = template_database_name(config, profile);
+        if let Some(other) = templates.insert(name.clone(), profile) {
+            let describe = |profile: Option<&str>| match profile {
+                Some(profile) => format!("profile '{}'", profile),
+                None => "the postgres service".to_string(),
+            };
+            return Err(Error::Config(format!(
+                "The seed dumps of {} and {} would both be loaded into template database {}",
+                describe(other),
+                describe(profile),
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// A profile's own `seed_from`, if it sets one
+fn own_seed_from<'a>(config: &'a Config, profile: Option<&str>) -> Option<&'a str> {
+    profile
+        .and_then(|p| config.get_profile(p))
+        .and_then(|p| p.seed_from.as_deref())
+        .filter(|seed_from| !seed_from.trim().is_empty())
+}
+
+/// Databases named by `seed_from`, which must never be treated as leftovers
+pub fn seed_databases(config: &Config) -> Vec<String> {
+    let profiles = config.scratch.profiles.values().map(|p| &p.seed_from);
+    config
+        .get_service("postgres")
+        .map(|s| &s.seed_from)
+        .into_iter()
+        .chain(profiles)
+        .flatten()
+        .filter_map(|seed_from| match SeedSource::parse(seed_from) {
+            SeedSource::Database(name) => Some(name),
+            SeedSource::Dump(_) => None,
+        })
+        .collect()
+}
+
+/// The database to copy a new scratch database of a profile from
+///
+/// A dump is loaded into the profile's template database if that doesn't
+/// exist yet.
+pub async fn seed_template(
+    config: &Config,
+    docker: &DockerClient,
+    profile: Option<&str>,
+) -> Result<Option<String>> {
+    match seed_source(config, profile) {
+        None => Ok(None),
+        Some(SeedSource::Database(name)) => Ok(Some(name)),
+        Some(SeedSource::Dump(dump)) => {
+            let name = template_database_name(config, profile);
+            load_template_database(config, docker, &name, &dump, false).await?;
+            Ok(Some(name))
+        }
+    }
+}
+
+/// Rebuild a profile's template database from its dump
+///
+/// Returns the name of the template database. Existing scratches keep their
+/// data; only scratches created afterwards start from the new dump.
+pub async fn refresh_template_database(
+    config: &Config,
+    docker: &DockerClient,
+    profile: Option<&str>,
+) -> Result<String> {
+    match seed_source(config, profile) {
+        Some(SeedSource::Dump(dump)) => {
+            let name = template_database_name(config, profile);
+            load_template_database(config, docker, &name, &dump, true).await?;
+            Ok(name)
+        }
+        Some(SeedSource::Database(name)) => Err(Error::Config(format!(
+            "Scratch databases are seeded from database {}, not from a dump",
+            name
+        ))),
+        None => Err(Error::Config(
+            "No seed_from is configured for postgres or this profile".to_string(),
+        )),
+    }
+}
+
+/// Load a dump into a template database, unless it exists and `replace` is unset
+///
+/// A postgres advisory lock keeps concurrent creates (and the CLI) from
+/// loading the same template twice. The dump is loaded into
+/// `<name>_next` first, so a failed load leaves the old template in place.
+async fn load_template_database(
+    config: &Config,
+    docker: &DockerClient,
+    name: &str,
+    dump: &Path,
+    replace: bool,
+) -> Result<()> {
+    validate_db_name(name)?;
+    let client = connect_admin(config).await?;
+
+    client
+        .execute("SELECT pg_advisory_lock(hashtext($1)::bigint)", &[&name])
+        .await?;
+    let result = if replace || !database_exists(&client, name).await? {
+        load_template(config, docker, &client, name, dump).await
+    } else {
+        Ok(())
+    };
+    let _ = client
+        .execute("SELECT pg_advisory_unlock(hashtext($1)::bigint)", &[&name])
+        .await;
+    result
+}
+
+async fn load_template(
+    config: &Config,
+    docker: &DockerClient,
+    client: &Client,
+    name: &str,
+    dump: &Path,
+) -> Result<()> {
+    let contents = fs::read(dump)
+        .map_err(|e| Error::Config(format!("Can't read seed dump {}: {}", dump.display(), e)))?;
+    let user = postgres_user(config)?;
+    let staging = format!("{}_next", name);
+
+    tracing::info!("Loading {} into template database {}", dump.display(), name);
+    drop_database(client, &staging).await?;
+    client
+        .execute(&format!("CREATE DATABASE {}", staging), &[])
+        .await?;
+    if let Err(e) = load_dump(docker, &user, &staging, &contents).await {
+        let _ = drop_database(client, &staging).await;
+        return Err(e);
+    }
+
+    drop_database(client, name).await?;
+    client
+        .execute(
+            &format!("ALTER DATABASE {} RENAME TO {}", staging, name),
+            &[],
+        )
+        .await?;
+    tracing::info!("Template database {} is ready", name);
+    Ok(())
+}
+
+async fn drop_database(client: &Client, db_name: &str) -> Result<()> {
+    terminate_connections(client, db_name).await;
+    client
+        .execute(&format!("DROP DATABASE IF EXISTS {}", db_name), &[])
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed_source() {
+        let config: Config = toml::from_str(
+            "[services.postgres]\nimage = \"postgres:18\"\nseed_from = \"golden\"\n\
+             [scratch.profiles.qa]\nseed_from = \"./seeds/qa.sql\"\n\
+             [scratch.profiles.minimal]",
+        )
+        .unwrap();
+
+        assert_eq!(
+            seed_source(&config, None),
+            Some(SeedSource::Database("golden".into()))
+        );
+        assert_eq!(
+            seed_source(&config, Some("minimal")),
+            Some(SeedSource::Database("golden".into()))
+        );
+        assert_eq!(
+            seed_source(&config, Some("qa")),
+            Some(SeedSource::Dump("./seeds/qa.sql".into()))
+        );
+        assert_eq!(seed_databases(&config), ["golden"]);
+        assert_eq!(seed_source(&Config::default(), None), None);
+    }
+
+    #[test]
+    fn test_template_database_name() {
+        let config: Config = toml::from_str(
+            "[services.postgres]\nimage = \"postgres:18\"\nseed_from = \"./seed.sql\"\n\
+             [scratch.profiles.QA-full]\nseed_from = \"./seeds/qa.sql\"\n\
+             [scratch.profiles.minimal]",
+        )
+        .unwrap();
+
+        assert_eq!(
+            template_database_name(&config, None),
+            "scratch_template_default"
+        );
+        assert_eq!(
+            template_database_name(&config, Some("QA-full")),
+            "scratch_template_qa_full"
+        );
+        // Without a dump of its own, a profile shares the default template
+        assert_eq!(
+            template_database_name(&config, Some("minimal")),
+            "scratch_template_default"
+        );
+        assert!(check_template_databases(&config).is_ok());
+    }
+
+    #[test]
+    fn test_colliding_template_databases_are_rejected() {
+        let mut config: Config = toml::from_str(
+            "[services.postgres]\nimage = \"postgres:18\"\n\
+             [scratch.profiles.qa-full]\nseed_from = \"./seeds/a.sql\"\n\
+             [scratch.profiles.QA_full]\nseed_from = \"./seeds/b.sql\"",
+        )
+        .unwrap();
+        assert!(matches!(
+            check_template_databases(&config),
+            Err(Error::Config(_))
+        ));
+
+        // A profile named after the default template clashes with the service's dump
+        config.scratch.profiles.remove("QA_full");
+        config.scratch.profiles.insert(
+            "default".to_string(),
+            toml::from_str("seed_from = \"./seeds/b.sql\"").unwrap(),
+        );
+        assert!(check_template_databases(&config).is_ok());
+        config.services.get_mut("postgres").unwrap().seed_from = Some("./seed.sql".to_string());
+        assert!(check_template_databases(&config).is_err());
+
+        // Databases named by seed_from aren't loaded, so they never clash
+        config.services.get_mut("postgres").unwrap().seed_from = Some("golden".to_string());
+        assert!(check_template_databases(&config).is_ok());
+    }
+
+    #[test]
+    fn test_dump_path_is_relative_to_config_file() {
+        let mut config: Config = toml::from_str(
+            "[services.postgres]\nimage = \"postgres:18\"\nseed_from = \"./seed.sql\"\n\
+             [scratch.profiles.abs]\nseed_from = \"/srv/seed.sql\"",
+        )
+        .unwrap();
+        config.base_dir = Some(PathBuf::from("/etc/scratchpad"));
+
+        assert_eq!(
+            seed_source(&config, None),
+            Some(SeedSource::Dump("/etc/scratchpad/./seed.sql".into()))
+        );
+        assert_eq!(
+            seed_source(&config, Some("abs")),
+            Some(SeedSource::Dump("/srv/seed.sql".into()))
+        );
+    }
+}
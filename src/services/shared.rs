@@ -108,6 +108,7 @@ pub async fn ensure_shared_service_running(
             volumes,
             Some(&config.docker.network),
             service_config.healthcheck.as_deref(),
+            &service_config.resources,
         )
         .await?;
 
@@ -125,36 +126,20 @@ pub async fn ensure_shared_service_running(
 async fn wait_for_healthy(
     docker: &DockerClient,
     container_name: &str,
-    timeout_secs: u32,
+    timeout_secs: u64,
 ) -> Result<()> {
-    use tokio::time::{sleep, Duration};
-
-    for _ in 0..timeout_secs {
-        let info = docker
-            .inner()
-            .inspect_container(container_name, None)
-            .await?;
-
-        if let Some(state) = info.state {
-            if let Some(health) = state.health {
-                if health.status == Some(bollard::models::HealthStatusEnum::HEALTHY) {
-                    return Ok(());
-                }
-            } else {
-                // No healthcheck configured, just check if running
-                if state.running == Some(true) {
-                    return Ok(());
-                }
-            }
-        }
+    let health = docker
+        .wait_for_ready(container_name, std::time::Duration::from_secs(timeout_secs))
+        .await?;
 
-        sleep(Duration::from_secs(1)).await;
+    if health.is_ready() {
+        Ok(())
+    } else {
+        Err(Error::Other(format!(
+            "{} did not become healthy ({})",
+            container_name, health
+        )))
     }
-
-    Err(Error::Other(format!(
-        "Timeout waiting for {} to become healthy",
-        container_name
-    )))
 }
 
 /// Stop all shared services
@@ -0,0 +1,304 @@
+//! Embedded state store for scratches
+//!
+//! Scratches are recorded in a SQLite database (by default
+//! `releases_dir/scratchpad.db`) so listing and status lookups don't have to
+//! scan and parse every scratch directory. The first time a database is
+//! opened, scratches found in existing `.scratchpad.toml` files are imported.
+//! When `store.write_toml` is enabled the TOML files are kept up to date as
+//! well, for tools that still read them.
+
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::config::{Config, ScratchConfig};
+use crate::error::{Error, Result};
+
+/// Name of the per-scratch config file
+pub const SCRATCH_CONFIG_FILE: &str = ".scratchpad.toml";
+
+/// Meta key set once the releases directory has been imported
+const IMPORTED_KEY: &str = "releases_imported";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS scratches (
+    name TEXT PRIMARY KEY,
+    state TEXT NOT NULL,
+    owner TEXT,
+    branch TEXT NOT NULL,
+    databases TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT,
+    updated_at TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+/// SQLite backed record of every scratch
+pub struct StateStore {
+    conn: Mutex<Connection>,
+}
+
+impl StateStore {
+    /// Open (or create) a state store at the given path
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Get the shared state store for a config
+    ///
+    /// Stores are opened once per path and reused. Opening a store for the
+    /// first time imports any scratches already in the releases directory.
+    pub fn for_config(config: &Config) -> Result<Arc<StateStore>> {
+        static STORES: OnceLock<Mutex<HashMap<PathBuf, Arc<StateStore>>>> = OnceLock::new();
+
+        let path = config.store.resolve_path(&config.server.releases_dir);
+        let mut stores = STORES
+            .get_or_init(Default::default)
+            .lock()
+            .map_err(|_| Error::Other("State store registry poisoned".to_string()))?;
+
+        if let Some(store) = stores.get(&path) {
+            return Ok(store.clone());
+        }
+
+        let store = Arc::new(StateStore::open(&path)?);
+        if store.get_meta(IMPORTED_KEY)?.is_none() {
+            let imported = store.import_releases(&config.server.releases_dir)?;
+            if imported > 0 {
+                tracing::info!(
+                    "Imported {} scratch(es) from {} into the state store",
+                    imported,
+                    config.server.releases_dir.display()
+                );
+            }
+            store.set_meta(IMPORTED_KEY, &Utc::now().to_rfc3339())?;
+        }
+
+        stores.insert(path, store.clone());
+        Ok(store)
+    }
+
+    fn conn(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| Error::Other("State store connection poisoned".to_string()))
+    }
+
+    /// Get a scratch by name
+    pub fn get(&self, name: &str) -> Result<Option<ScratchConfig>> {
+        let data: Option<String> = self
+            .conn()?
+            .query_row(
+                "SELECT data FROM scratches WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        data.map(|d| serde_json::from_str(&d).map_err(Error::from))
+            .transpose()
+    }
+
+    /// List all scratches, sorted by name
+    pub fn list(&self) -> Result<Vec<ScratchConfig>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT data FROM scratches ORDER BY name")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+
+        let mut scratches = Vec::new();
+        for data in rows {
+            scratches.push(serde_json::from_str(&data?)?);
+        }
+        Ok(scratches)
+    }
+
+    /// Insert or replace a scratch
+    pub fn put(&self, scratch_config: &ScratchConfig) -> Result<()> {
+        let databases: Vec<&String> = scratch_config.databases.values().flatten().collect();
+
+        self.conn()?.execute(
+            "INSERT OR REPLACE INTO scratches
+                (name, state, owner, branch, databases, created_at, expires_at, updated_at, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                scratch_config.name,
+                scratch_config.state.as_str(),
+                scratch_config.owner,
+                scratch_config.branch,
+                serde_json::to_string(&databases)?,
+                scratch_config.created_at.to_rfc3339(),
+                scratch_config.expires_at.map(|e| e.to_rfc3339()),
+                Utc::now().to_rfc3339(),
+                serde_json::to_string(scratch_config)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Remove a scratch, returning whether it was recorded
+    pub fn delete(&self, name: &str) -> Result<bool> {
+        let removed = self
+            .conn()?
+            .execute("DELETE FROM scratches WHERE name = ?1", params![name])?;
+        Ok(removed > 0)
+    }
+
+    /// Import scratches from the `.scratchpad.toml` files in a releases directory
+    ///
+    /// Scratches already in the store are left alone. Returns the number imported.
+    pub fn import_releases(&self, releases_dir: &Path) -> Result<usize> {
+        if !releases_dir.exists() {
+            return Ok(0);
+        }
+
+        let mut imported = 0;
+        for entry in fs::read_dir(releases_dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || self.get(&name)?.is_some() {
+                continue;
+            }
+
+            match read_config_file(&entry.path()) {
+                Ok(mut scratch_config) => {
+                    // The directory name is what everything else keys on
+                    scratch_config.name = name;
+                    self.put(&scratch_config)?;
+                    imported += 1;
+                }
+                Err(e) => tracing::warn!("Skipping {}: {}", entry.path().display(), e),
+            }
+        }
+
+        Ok(imported)
+    }
+
+    fn get_meta(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn()?
+            .query_row(
+                "SELECT value FROM meta WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+}
+
+/// Read a scratch config file from a directory
+pub fn read_config_file(dir: &Path) -> Result<ScratchConfig> {
+    let config_path = dir.join(SCRATCH_CONFIG_FILE);
+    if !config_path.exists() {
+        return Err(Error::Config(format!(
+            "Scratch config not found at {}",
+            config_path.display()
+        )));
+    }
+
+    let content = fs::read_to_string(&config_path)?;
+    toml::from_str(&content)
+        .map_err(|e| Error::Config(format!("Failed to parse scratch config: {}", e)))
+}
+
+/// Write a scratch config file into a directory
+pub fn write_config_file(dir: &Path, scratch_config: &ScratchConfig) -> Result<()> {
+    let content =
+        toml::to_string_pretty(scratch_config).map_err(|e| Error::Config(e.to_string()))?;
+    fs::write(dir.join(SCRATCH_CONFIG_FILE), content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ScratchState;
+
+    fn scratch_config(name: &str) -> ScratchConfig {
+        toml::from_str(&format!(
+            "name = \"{}\"\nbranch = \"feature/{}\"\ntemplate = \"default\"\ncreated_at = \"2024-01-01T00:00:00Z\"\n\
+             services = []\ndatabases = {{}}\nenv = {{}}",
+            name, name
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_put_get_list_delete() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = StateStore::open(&temp.path().join("scratchpad.db")).unwrap();
+        assert!(store.get("b").unwrap().is_none());
+
+        let mut b = scratch_config("b");
+        b.state = ScratchState::Stopped;
+        b.owner = Some("alice".to_string());
+        store.put(&b).unwrap();
+        store.put(&scratch_config("a")).unwrap();
+
+        let loaded = store.get("b").unwrap().unwrap();
+        assert_eq!(loaded.state, ScratchState::Stopped);
+        assert_eq!(loaded.owner.as_deref(), Some("alice"));
+
+        let names: Vec<_> = store.list().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["a", "b"]);
+
+        assert!(store.delete("a").unwrap());
+        assert!(!store.delete("a").unwrap());
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_for_config_imports_releases_once() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.server.releases_dir = temp.path().join("releases");
+
+        let scratch_dir = config.server.releases_dir.join("feature-x");
+        fs::create_dir_all(&scratch_dir).unwrap();
+        write_config_file(&scratch_dir, &scratch_config("feature-x")).unwrap();
+        fs::create_dir_all(config.server.releases_dir.join("not-a-scratch")).unwrap();
+
+        let store = StateStore::for_config(&config).unwrap();
+        let scratches = store.list().unwrap();
+        assert_eq!(scratches.len(), 1);
+        assert_eq!(scratches[0].branch, "feature/feature-x");
+        assert!(config.server.releases_dir.join("scratchpad.db").exists());
+
+        // Reopening the same database doesn't import again
+        store.delete("feature-x").unwrap();
+        let reopened = StateStore::open(&config.server.releases_dir.join("scratchpad.db")).unwrap();
+        assert!(reopened.get_meta(IMPORTED_KEY).unwrap().is_some());
+        assert!(StateStore::for_config(&config)
+            .unwrap()
+            .get("feature-x")
+            .unwrap()
+            .is_none());
+    }
+}
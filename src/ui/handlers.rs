@@ -2,10 +2,14 @@
 
 use axum::{
     extract::{Path, State},
-    response::Html,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
 };
 
+use std::collections::BTreeSet;
+
 use crate::api::server::SharedState;
+use crate::config::ScratchState;
 use crate::scratch;
 
 /// Login page
@@ -118,7 +122,7 @@ pub async fn dashboard(State(state): State<SharedState>) -> Html<String> {
         .map(|s| {
             let status_class = match s.status.as_str() {
                 "running" => "text-green-500",
-                "stopped" => "text-red-500",
+                "stopped" | "failed" => "text-red-500",
                 _ => "text-yellow-500",
             };
 
@@ -131,17 +135,30 @@ pub async fn dashboard(State(state): State<SharedState>) -> Html<String> {
                 })
                 .collect();
 
+            let owner = s.owner.as_deref().map(html_escape).unwrap_or_default();
+            let tags = html_escape(&s.tags.iter().cloned().collect::<Vec<_>>().join(" "));
+            let mut meta: Vec<String> = Vec::new();
+            if !owner.is_empty() {
+                meta.push(format!("@{}", owner));
+            }
+            meta.extend(s.tags.iter().map(|t| format!("#{}", html_escape(t))));
+            if let Some(description) = &s.description {
+                meta.push(html_escape(description));
+            }
+
             format!(
                 r#"
-                <tr class="border-b border-gray-700 hover:bg-gray-800" data-name="{}" data-branch="{}" data-status="{}">
+                <tr class="border-b border-gray-700 hover:bg-gray-800" data-name="{}" data-branch="{}" data-status="{}" data-owner="{}" data-tags="{}">
                     <td class="px-4 py-3">
                         <a href="/scratches/{}" class="text-blue-400 hover:underline">{}</a>
+                        <div class="text-xs text-gray-400">{}</div>
                     </td>
                     <td class="px-4 py-3">{}</td>
                     <td class="px-4 py-3 {}">
                         <span class="inline-flex items-center px-2 py-1 rounded-full text-xs font-medium bg-gray-700">
                             {}
                         </span>
+                        {}
                     </td>
                     <td class="px-4 py-3 text-sm">{}</td>
                     <td class="px-4 py-3">
@@ -162,11 +179,19 @@ pub async fn dashboard(State(state): State<SharedState>) -> Html<String> {
                 s.name,
                 s.branch,
                 s.status,
+                owner,
+                tags,
                 s.name,
                 s.name,
+                meta.join(" · "),
                 s.branch,
                 status_class,
                 s.status,
+                if s.outdated {
+                    r#"<span class="inline-flex items-center px-2 py-1 rounded-full text-xs font-medium bg-orange-700 text-orange-100" title="compose.yml differs from the current config; update the scratch to apply it">outdated</span>"#
+                } else {
+                    ""
+                },
                 services,
                 s.url.as_deref().unwrap_or("#"),
                 s.url.as_deref().unwrap_or("-"),
@@ -180,6 +205,75 @@ pub async fn dashboard(State(state): State<SharedState>) -> Html<String> {
         })
         .collect();
 
+    // Chips for every owner and tag in use
+    let owners: BTreeSet<&str> = scratches
+        .iter()
+        .filter_map(|s| s.owner.as_deref())
+        .collect();
+    let tags: BTreeSet<&str> = scratches
+        .iter()
+        .flat_map(|s| s.tags.iter().map(String::as_str))
+        .collect();
+    let filter_chips: String = owners
+        .iter()
+        .map(|owner| ("owner", *owner, format!("@{}", owner)))
+        .chain(tags.iter().map(|tag| ("tag", *tag, format!("#{}", tag))))
+        .map(|(kind, value, label)| {
+            format!(
+                r#"<button class="px-3 py-1 text-xs bg-gray-700 hover:bg-gray-600 rounded-full filter-chip" data-kind="{}" data-value="{}">{}</button>"#,
+                kind,
+                html_escape(value),
+                html_escape(&label)
+            )
+        })
+        .collect();
+
+    // Usage against each configured quota
+    let quota_items = scratch::quota_report(&state.config).unwrap_or_default();
+    let quota_panel = if quota_items.is_empty() {
+        String::new()
+    } else {
+        let bars: String = quota_items
+            .iter()
+            .map(|item| {
+                let bar_class = if item.is_full() {
+                    "bg-red-500"
+                } else if item.percent() >= 80 {
+                    "bg-yellow-500"
+                } else {
+                    "bg-green-500"
+                };
+                format!(
+                    r#"
+                        <div>
+                            <div class="flex justify-between text-sm mb-1">
+                                <span>{}</span>
+                                <span class="text-gray-400">{}</span>
+                            </div>
+                            <div class="w-full bg-gray-700 rounded h-2">
+                                <div class="{} h-2 rounded" style="width: {}%"></div>
+                            </div>
+                        </div>"#,
+                    html_escape(&item.name),
+                    item.display(),
+                    bar_class,
+                    item.percent().min(100)
+                )
+            })
+            .collect();
+        format!(
+            r#"
+                <!-- Quota Usage -->
+                <div class="mb-6 bg-gray-800 rounded-lg p-4">
+                    <h3 class="text-lg font-semibold mb-3">Quotas</h3>
+                    <div class="grid grid-cols-1 md:grid-cols-2 gap-4">{}
+                    </div>
+                </div>
+"#,
+            bars
+        )
+    };
+
     let html = format!(
         r#"
 <!DOCTYPE html>
@@ -227,7 +321,7 @@ pub async fn dashboard(State(state): State<SharedState>) -> Html<String> {
                     <h2 class="text-3xl font-bold mb-2">Scratches</h2>
                     <p class="text-gray-400">Manage your scratch environments - {} total</p>
                 </header>
-
+{}
                 <!-- Search and Filter -->
                 <div class="mb-6 bg-gray-800 rounded-lg p-4">
                     <input 
@@ -241,6 +335,9 @@ pub async fn dashboard(State(state): State<SharedState>) -> Html<String> {
                         <button class="px-3 py-1 text-sm bg-gray-700 hover:bg-gray-600 rounded filter-btn" data-filter="running">Running</button>
                         <button class="px-3 py-1 text-sm bg-gray-700 hover:bg-gray-600 rounded filter-btn" data-filter="stopped">Stopped</button>
                     </div>
+                    <div class="mt-3 flex flex-wrap gap-2">
+                        {}
+                    </div>
                 </div>
 
                 <!-- Scratches Table -->
@@ -290,10 +387,13 @@ pub async fn dashboard(State(state): State<SharedState>) -> Html<String> {
         (function() {{
             const searchInput = document.getElementById('search-input');
             const filterBtns = document.querySelectorAll('.filter-btn');
+            const filterChips = document.querySelectorAll('.filter-chip');
             const tbody = document.getElementById('scratches-tbody');
             const emptyState = document.getElementById('empty-state');
             const logoutBtn = document.getElementById('logout-btn');
             let currentFilter = 'all';
+            let currentOwner = null;
+            const currentTags = new Set();
             let ws = null;
             let pingInterval = null;
 
@@ -351,6 +451,15 @@ pub async fn dashboard(State(state): State<SharedState>) -> Html<String> {
                         match = status === currentFilter;
                     }}
 
+                    // Owner and tag chips
+                    if (match && currentOwner !== null) {{
+                        match = row.dataset.owner === currentOwner;
+                    }}
+                    if (match && currentTags.size > 0) {{
+                        const tags = (row.dataset.tags || '').split(' ');
+                        match = [...currentTags].every(tag => tags.includes(tag));
+                    }}
+
                     row.style.display = match ? '' : 'none';
                     if (match) visibleCount++;
                 }});
@@ -447,6 +556,28 @@ pub async fn dashboard(State(state): State<SharedState>) -> Html<String> {
                 }});
             }});
 
+            filterChips.forEach(chip => {{
+                chip.addEventListener('click', () => {{
+                    const value = chip.dataset.value;
+                    if (chip.dataset.kind === 'owner') {{
+                        currentOwner = currentOwner === value ? null : value;
+                        filterChips.forEach(c => {{
+                            if (c.dataset.kind === 'owner') {{
+                                c.classList.toggle('bg-blue-600', c.dataset.value === currentOwner);
+                            }}
+                        }});
+                    }} else {{
+                        if (currentTags.has(value)) {{
+                            currentTags.delete(value);
+                        }} else {{
+                            currentTags.add(value);
+                        }}
+                        chip.classList.toggle('bg-blue-600', currentTags.has(value));
+                    }}
+                    filterTable();
+                }});
+            }});
+
             // Set initial active filter
             filterBtns[0].classList.add('bg-blue-600');
             
@@ -458,6 +589,8 @@ pub async fn dashboard(State(state): State<SharedState>) -> Html<String> {
 </html>
         "#,
         scratches.len(),
+        quota_panel,
+        filter_chips,
         scratch_rows
     );
 
@@ -554,6 +687,36 @@ pub async fn scratch_detail(
                 <h2 class="text-xl font-semibold mb-4">Access</h2>
                 <a href="{}" target="_blank" class="text-blue-400 hover:underline">{}</a>
             </div>
+
+            <div class="bg-gray-800 rounded-lg p-6 md:col-span-2">
+                <h2 class="text-xl font-semibold mb-4">Resources</h2>
+                <div class="grid grid-cols-2 gap-6 mb-4">
+                    <div>
+                        <div class="flex justify-between text-sm mb-1">
+                            <span>CPU</span><span id="stats-cpu" class="text-gray-400">-</span>
+                        </div>
+                        <div class="w-full bg-gray-700 rounded h-2">
+                            <div id="stats-cpu-bar" class="bg-blue-500 h-2 rounded" style="width: 0%"></div>
+                        </div>
+                    </div>
+                    <div>
+                        <div class="flex justify-between text-sm mb-1">
+                            <span>Memory</span><span id="stats-memory" class="text-gray-400">-</span>
+                        </div>
+                        <div class="w-full bg-gray-700 rounded h-2">
+                            <div id="stats-memory-bar" class="bg-green-500 h-2 rounded" style="width: 0%"></div>
+                        </div>
+                    </div>
+                </div>
+                <table class="w-full text-sm text-gray-300">
+                    <thead class="text-gray-400 text-left">
+                        <tr><th>Service</th><th>CPU</th><th>Memory</th><th>Net RX / TX</th><th>Block R / W</th></tr>
+                    </thead>
+                    <tbody id="stats-services">
+                        <tr><td colspan="5" class="text-gray-500 py-1">Waiting for stats...</td></tr>
+                    </tbody>
+                </table>
+            </div>
         </div>
 
         <div class="mt-8 bg-gray-800 rounded-lg p-6">
@@ -589,6 +752,60 @@ pub async fn scratch_detail(
                         container.scrollTop = container.scrollHeight;
                     }}
                     
+                    function formatBytes(bytes) {{
+                        const units = ['B', 'K', 'M', 'G', 'T'];
+                        let i = 0;
+                        while (bytes >= 1024 && i < units.length - 1) {{
+                            bytes /= 1024;
+                            i++;
+                        }}
+                        return `${{i === 0 ? bytes : bytes.toFixed(1)}}${{units[i]}}`;
+                    }}
+
+                    function displayStats(stats) {{
+                        const total = stats.total;
+                        const memPercent = total.memory_limit ? total.memory_used / total.memory_limit * 100 : 0;
+                        document.getElementById('stats-cpu').textContent = `${{total.cpu_percent.toFixed(1)}}%`;
+                        document.getElementById('stats-cpu-bar').style.width = `${{Math.min(total.cpu_percent, 100)}}%`;
+                        document.getElementById('stats-memory').textContent =
+                            `${{formatBytes(total.memory_used)}} / ${{formatBytes(total.memory_limit)}}`;
+                        document.getElementById('stats-memory-bar').style.width = `${{Math.min(memPercent, 100)}}%`;
+
+                        const tbody = document.getElementById('stats-services');
+                        tbody.innerHTML = '';
+                        const services = Object.entries(stats.services);
+                        if (services.length === 0) {{
+                            tbody.innerHTML = '<tr><td colspan="5" class="text-gray-500 py-1">No running containers</td></tr>';
+                        }}
+                        services.forEach(([service, usage]) => {{
+                            const row = document.createElement('tr');
+                            [
+                                service,
+                                `${{usage.cpu_percent.toFixed(1)}}%`,
+                                `${{formatBytes(usage.memory_used)}} / ${{formatBytes(usage.memory_limit)}}`,
+                                `${{formatBytes(usage.net_rx)}} / ${{formatBytes(usage.net_tx)}}`,
+                                `${{formatBytes(usage.block_read)}} / ${{formatBytes(usage.block_write)}}`,
+                            ].forEach(value => {{
+                                const cell = document.createElement('td');
+                                cell.className = 'py-1';
+                                cell.textContent = value;
+                                row.appendChild(cell);
+                            }});
+                            tbody.appendChild(row);
+                        }});
+                    }}
+
+                    function fetchInitialStats() {{
+                        fetch(`/api/scratches/${{scratchName}}/stats`)
+                            .then(r => r.json())
+                            .then(data => {{
+                                if (data.success && data.data) {{
+                                    displayStats(data.data);
+                                }}
+                            }})
+                            .catch(e => console.error('Failed to fetch stats:', e));
+                    }}
+
                     function fetchInitialLogs() {{
                         fetch(`/api/scratches/${{scratchName}}/logs?tail=200`)
                             .then(r => r.json())
@@ -614,10 +831,10 @@ pub async fn scratch_detail(
                         
                         ws.onopen = function() {{
                             console.log('WebSocket connected');
-                            // Subscribe to logs for this scratch
+                            // Subscribe to logs and resource usage for this scratch
                             const msg = {{
                                 type: 'Subscribe',
-                                channels: [`logs:${{scratchName}}`]
+                                channels: [`logs:${{scratchName}}`, `stats:${{scratchName}}`]
                             }};
                             ws.send(JSON.stringify(msg));
                             
@@ -635,6 +852,8 @@ pub async fn scratch_detail(
                             
                             if (msg.type === 'Log') {{
                                 displayLog(msg.timestamp, msg.service, msg.line);
+                            }} else if (msg.type === 'Stats') {{
+                                displayStats(msg.stats);
                             }} else if (msg.type === 'Subscribed') {{
                                 if (container.textContent.includes('Loading initial logs')) {{
                                     container.innerHTML = '<div class="text-gray-400">Waiting for logs...</div>';
@@ -662,8 +881,9 @@ pub async fn scratch_detail(
                         }};
                     }}
                     
-                    // Load initial logs and connect WebSocket
+                    // Load initial logs and stats, then connect WebSocket
                     fetchInitialLogs();
+                    fetchInitialStats();
                     setTimeout(connectWebSocket, 500);
                     
                     // Cleanup on page unload
@@ -1458,3 +1678,101 @@ pub async fn service_manager(State(state): State<SharedState>) -> Html<String> {
 
     Html(html)
 }
+
+/// Wake-up page for stopped scratches
+///
+/// Nginx sends requests here when a scratch's ingress is unreachable. The
+/// scratch is started in the background and the page refreshes until
+/// the scratch reports as running, at which point nginx routes to it again.
+/// Only scratches stopped by the user or for being idle are started; any
+/// other state gets an error page.
+pub async fn wake_scratch(State(state): State<SharedState>, Path(name): Path<String>) -> Response {
+    let state = state.read().await;
+
+    let status = match scratch::get_scratch_status(&state.config, &state.docker, &name).await {
+        Ok(status) => status,
+        Err(_) => {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("Scratch \"{}\" not found\n", name),
+            )
+                .into_response();
+        }
+    };
+
+    let stored_state = scratch::load_scratch_config(&state.config, &name)
+        .map(|scratch_config| scratch_config.state)
+        .ok();
+
+    let (code, refresh, title, message) = if status.status == "running" {
+        (
+            StatusCode::OK,
+            Some(1),
+            "Ready",
+            "Scratch is running, redirecting...",
+        )
+    } else if stored_state == Some(ScratchState::Stopped) || state.activity.is_waking(&name).await {
+        if state.activity.begin_wake(&name).await {
+            tracing::info!("Waking scratch '{}' on request", name);
+            let config = state.config.clone();
+            let docker = state.docker.clone();
+            let activity = state.activity.clone();
+            let scratch_name = name.clone();
+            tokio::spawn(async move {
+                if let Err(e) = scratch::start_scratch(&config, &docker, &scratch_name).await {
+                    tracing::error!("Failed to wake scratch {}: {}", scratch_name, e);
+                }
+                activity.end_wake(&scratch_name).await;
+            });
+        }
+        (StatusCode::OK, Some(3), "Waking up", "This scratch was stopped while idle and is starting again. This page will refresh automatically.")
+    } else {
+        // Only a stopped scratch is started here; one that failed or is
+        // still being created needs looking at instead
+        (StatusCode::SERVICE_UNAVAILABLE, None, "Unavailable", "This scratch isn't stopped, so it can't be started from here. Check its status in the dashboard.")
+    };
+
+    let html = format!(
+        r#"
+<!DOCTYPE html>
+<html lang="en" class="dark">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    {}
+    <title>{} - {}</title>
+    <script src="https://cdn.tailwindcss.com"></script>
+</head>
+<body class="bg-gray-900 text-gray-100 min-h-screen flex items-center justify-center">
+    <div class="w-full max-w-md">
+        <div class="bg-gray-800 rounded-lg shadow-xl p-8 text-center">
+            <h1 class="text-3xl font-bold mb-4">{}</h1>
+            <p class="text-lg mb-2">{}</p>
+            <p class="text-gray-400 mb-6">{}</p>
+            <span class="inline-flex items-center px-2 py-1 rounded-full text-xs font-medium bg-gray-700 text-yellow-500">{}</span>
+        </div>
+    </div>
+</body>
+</html>
+        "#,
+        refresh
+            .map(|seconds| format!(r#"<meta http-equiv="refresh" content="{}">"#, seconds))
+            .unwrap_or_default(),
+        title,
+        status.name,
+        title,
+        status.name,
+        message,
+        status.status
+    );
+
+    (code, Html(html)).into_response()
+}
+
+/// Escape text for use in HTML content and attributes
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -4,6 +4,8 @@
 //! Run with: cargo test --test api_crud_tests -- --test-threads=1 --nocapture
 //! (Single thread to avoid port conflicts)
 
+#![allow(clippy::clone_on_copy, clippy::needless_borrows_for_generic_args)]
+
 use scratchpad::api::run_server;
 use scratchpad::config::Config;
 use std::time::Duration;
@@ -5,6 +5,8 @@
 //! - Individual service start/stop (Phase 7)
 //! - Real-time status updates (Phase 9 - WebSocket)
 
+#![allow(clippy::unnecessary_operation)]
+
 use scratchpad::config::{Config, NginxConfig, NginxRouting, ServerConfig};
 use std::path::PathBuf;
 
@@ -85,6 +87,10 @@ fn test_config_with_services() {
     let postgres_config = ServiceConfig {
         image: "postgres:15".to_string(),
         shared: true,
+        fallback_images: vec![],
+        seed_from: None,
+        build: None,
+        repo: None,
         port: Some(5432),
         internal_port: None,
         env: {
@@ -94,8 +100,10 @@ fn test_config_with_services() {
         },
         volumes: vec![],
         healthcheck: Some("pg_isready -U postgres".to_string()),
+        health_timeout: None,
         auto_create_db: true,
         connection: None,
+        resources: Default::default(),
     };
 
     config
@@ -126,13 +134,19 @@ fn test_service_name_validation() {
             scratchpad::config::ServiceConfig {
                 image: format!("{}:latest", service),
                 shared: true,
+                fallback_images: vec![],
+                seed_from: None,
+                build: None,
+                repo: None,
                 port: None,
                 internal_port: None,
                 env: Default::default(),
                 volumes: vec![],
                 healthcheck: None,
+                health_timeout: None,
                 auto_create_db: false,
                 connection: None,
+                resources: Default::default(),
             },
         );
     }
@@ -155,6 +169,7 @@ fn test_config_server_config_update() {
         host: "127.0.0.1".to_string(),
         port: 8000,
         releases_dir: PathBuf::from("/custom/releases"),
+        templates_dir: PathBuf::from("/custom/templates"),
     };
 
     config.server = new_server_config;
@@ -184,6 +199,7 @@ fn test_config_nginx_config_update() {
         container: Some("nginx".to_string()),
         dynamic: None,
         ingress_service: None,
+        server_upstream: None,
     };
 
     config.nginx = new_nginx_config;
@@ -1,5 +1,7 @@
 //! Authentication and authorization tests
 
+#![allow(unused_variables)]
+
 use scratchpad::auth::{create_token, validate_token, Claims, SessionManager, User, UserRole};
 
 #[test]
@@ -4,8 +4,8 @@
 //! Run with: cargo test --test cli_tests
 //! Note: Some tests are marked #[ignore] for manual testing with real Docker
 
-use std::fs;
-use std::path::Path;
+use clap::Parser;
+use scratchpad::cli::{Cli, Commands, DbAction, DbTemplateAction, JobsAction, SnapshotAction};
 
 #[test]
 fn test_cli_init_creates_config_file() {
@@ -220,26 +220,422 @@ fn test_cli_logs_command() {
 #[test]
 fn test_cli_create_command_all_flags() {
     // Test create with all possible flags
-    let branch = "feature/test";
-    let name = Some("custom-name");
-    let profile = Some("production");
-    let template = Some("nodejs");
-
-    assert!(!branch.is_empty());
-    assert!(name.is_some());
-    assert!(profile.is_some());
-    assert!(template.is_some());
+    let cli = Cli::try_parse_from([
+        "scratchpad",
+        "create",
+        "--branch",
+        "feature/test",
+        "--name",
+        "custom-name",
+        "--profile",
+        "production",
+        "--template",
+        "nodejs",
+        "--ttl",
+        "7d",
+        "--env",
+        "LOG_LEVEL=debug",
+        "-e",
+        "FEATURE_FLAG=on",
+        "--env-file",
+        ".env.qa",
+        "--description",
+        "Checkout redesign demo",
+        "--tag",
+        "demo,qa",
+        "--tag",
+        "checkout",
+        "--no-wait",
+    ])
+    .expect("Failed to parse create command");
+
+    match cli.command {
+        Commands::Create {
+            branch,
+            name,
+            profile,
+            template,
+            ttl,
+            env,
+            env_file,
+            description,
+            tags,
+            no_wait,
+        } => {
+            assert_eq!(branch, ["feature/test"]);
+            assert_eq!(name.as_deref(), Some("custom-name"));
+            assert_eq!(profile.as_deref(), Some("production"));
+            assert_eq!(template.as_deref(), Some("nodejs"));
+            assert_eq!(ttl.as_deref(), Some("7d"));
+            assert_eq!(env, ["LOG_LEVEL=debug", "FEATURE_FLAG=on"]);
+            assert_eq!(env_file, Some(".env.qa".into()));
+            assert_eq!(description.as_deref(), Some("Checkout redesign demo"));
+            assert_eq!(tags, ["demo", "qa", "checkout"]);
+            assert!(no_wait);
+        }
+        _ => panic!("Expected create command"),
+    }
     println!("✓ CLI create with all flags");
 }
 
+#[test]
+fn test_cli_list_filters() {
+    let cli = Cli::try_parse_from([
+        "scratchpad",
+        "list",
+        "--owner",
+        "alice",
+        "--tag",
+        "demo,qa",
+        "--status",
+        "running",
+        "--branch",
+        "feature/checkout",
+    ])
+    .expect("Failed to parse list command");
+
+    match cli.command {
+        Commands::List {
+            owner,
+            tags,
+            status,
+            branch,
+            ..
+        } => {
+            assert_eq!(owner.as_deref(), Some("alice"));
+            assert_eq!(tags, ["demo", "qa"]);
+            assert_eq!(status.as_deref(), Some("running"));
+            assert_eq!(branch.as_deref(), Some("feature/checkout"));
+        }
+        _ => panic!("Expected list command"),
+    }
+    println!("✓ CLI list filters");
+}
+
+#[test]
+fn test_cli_stats_command() {
+    let cli = Cli::try_parse_from(["scratchpad", "stats", "feature-test", "--watch"])
+        .expect("Failed to parse stats command");
+    match cli.command {
+        Commands::Stats { name, watch } => {
+            assert_eq!(name.as_deref(), Some("feature-test"));
+            assert!(watch);
+        }
+        _ => panic!("Expected stats command"),
+    }
+
+    let cli = Cli::try_parse_from(["scratchpad", "stats"]).expect("Failed to parse stats");
+    assert!(matches!(
+        cli.command,
+        Commands::Stats {
+            name: None,
+            watch: false
+        }
+    ));
+    println!("✓ CLI stats command");
+}
+
+#[test]
+fn test_cli_bulk_command() {
+    let cli = Cli::try_parse_from([
+        "scratchpad",
+        "bulk",
+        "delete",
+        "feature-*",
+        "--status",
+        "stopped",
+        "--older-than",
+        "14d",
+        "--branch-regex",
+        "^feature/",
+        "-j",
+        "2",
+        "--dry-run",
+    ])
+    .expect("Failed to parse bulk command");
+
+    match cli.command {
+        Commands::Bulk {
+            action,
+            pattern,
+            status,
+            older_than,
+            branch_regex,
+            concurrency,
+            dry_run,
+            force,
+            ..
+        } => {
+            assert_eq!(action, scratchpad::scratch::BulkAction::Delete);
+            assert_eq!(pattern.as_deref(), Some("feature-*"));
+            assert_eq!(status.as_deref(), Some("stopped"));
+            assert_eq!(older_than.as_deref(), Some("14d"));
+            assert_eq!(branch_regex.as_deref(), Some("^feature/"));
+            assert_eq!(concurrency, 2);
+            assert!(dry_run);
+            assert!(!force);
+        }
+        _ => panic!("Expected bulk command"),
+    }
+
+    let cli = Cli::try_parse_from(["scratchpad", "bulk", "restart", "--tag", "qa"])
+        .expect("Failed to parse bulk restart");
+    match cli.command {
+        Commands::Bulk {
+            action,
+            pattern,
+            tags,
+            concurrency,
+            ..
+        } => {
+            assert_eq!(action, scratchpad::scratch::BulkAction::Restart);
+            assert!(pattern.is_none());
+            assert_eq!(tags, vec!["qa"]);
+            assert_eq!(concurrency, scratchpad::scratch::DEFAULT_BULK_CONCURRENCY);
+        }
+        _ => panic!("Expected bulk command"),
+    }
+
+    assert!(Cli::try_parse_from(["scratchpad", "bulk", "explode", "*"]).is_err());
+    println!("✓ CLI bulk command");
+}
+
+#[test]
+fn test_cli_update_rolling() {
+    let cli = Cli::try_parse_from(["scratchpad", "update", "feature-test", "--rolling"])
+        .expect("Failed to parse update --rolling");
+    match cli.command {
+        Commands::Update {
+            name,
+            restart,
+            rolling,
+            ..
+        } => {
+            assert_eq!(name, "feature-test");
+            assert!(!restart);
+            assert!(rolling);
+        }
+        _ => panic!("Expected update command"),
+    }
+    println!("✓ CLI update --rolling");
+}
+
+#[test]
+fn test_cli_diff_command() {
+    let cli = Cli::try_parse_from(["scratchpad", "diff", "feature-test"])
+        .expect("Failed to parse diff command");
+    match cli.command {
+        Commands::Diff { name, all } => {
+            assert_eq!(name.as_deref(), Some("feature-test"));
+            assert!(!all);
+        }
+        _ => panic!("Expected diff command"),
+    }
+
+    let cli = Cli::try_parse_from(["scratchpad", "diff", "--all"]).expect("Failed to parse diff");
+    assert!(matches!(
+        cli.command,
+        Commands::Diff {
+            name: None,
+            all: true
+        }
+    ));
+
+    assert!(Cli::try_parse_from(["scratchpad", "diff"]).is_err());
+    assert!(Cli::try_parse_from(["scratchpad", "diff", "feature-test", "--all"]).is_err());
+    println!("✓ CLI diff command");
+}
+
+#[test]
+fn test_cli_gc_command() {
+    let cli =
+        Cli::try_parse_from(["scratchpad", "gc", "--dry-run"]).expect("Failed to parse gc command");
+    assert!(matches!(
+        cli.command,
+        Commands::Gc {
+            dry_run: true,
+            force: false
+        }
+    ));
+
+    let cli = Cli::try_parse_from(["scratchpad", "gc", "-f"]).expect("Failed to parse gc -f");
+    assert!(matches!(
+        cli.command,
+        Commands::Gc {
+            dry_run: false,
+            force: true
+        }
+    ));
+    println!("✓ CLI gc command");
+}
+
+#[test]
+fn test_cli_extend_command() {
+    let cli = Cli::try_parse_from(["scratchpad", "extend", "feature-test", "3d"])
+        .expect("Failed to parse extend command");
+
+    match cli.command {
+        Commands::Extend { name, duration } => {
+            assert_eq!(name, "feature-test");
+            assert_eq!(duration, "3d");
+        }
+        _ => panic!("Expected extend command"),
+    }
+    println!("✓ CLI extend command");
+}
+
+#[test]
+fn test_cli_clone_command() {
+    let cli = Cli::try_parse_from([
+        "scratchpad",
+        "clone",
+        "feature-x",
+        "feature-x-qa",
+        "--branch",
+        "feature/qa",
+    ])
+    .expect("Failed to parse clone command");
+
+    match cli.command {
+        Commands::Clone {
+            source,
+            name,
+            branch,
+        } => {
+            assert_eq!(source, "feature-x");
+            assert_eq!(name, "feature-x-qa");
+            assert_eq!(branch.as_deref(), Some("feature/qa"));
+        }
+        _ => panic!("Expected clone command"),
+    }
+    println!("✓ CLI clone command");
+}
+
+#[test]
+fn test_cli_snapshot_commands() {
+    let cli = Cli::try_parse_from([
+        "scratchpad",
+        "snapshot",
+        "create",
+        "feature-x",
+        "--name",
+        "before-migration",
+    ])
+    .expect("Failed to parse snapshot create command");
+    assert!(matches!(
+        cli.command,
+        Commands::Snapshot {
+            action: SnapshotAction::Create { ref scratch, name: Some(ref name) }
+        } if scratch == "feature-x" && name == "before-migration"
+    ));
+
+    let cli = Cli::try_parse_from([
+        "scratchpad",
+        "snapshot",
+        "restore",
+        "feature-x",
+        "before-migration",
+        "--force",
+    ])
+    .expect("Failed to parse snapshot restore command");
+    assert!(matches!(
+        cli.command,
+        Commands::Snapshot {
+            action: SnapshotAction::Restore { force: true, .. }
+        }
+    ));
+    println!("✓ CLI snapshot commands");
+}
+
+#[test]
+fn test_cli_db_template_refresh() {
+    let cli = Cli::try_parse_from(["scratchpad", "db", "template", "refresh", "--profile", "qa"])
+        .expect("Failed to parse db template refresh command");
+    assert!(matches!(
+        cli.command,
+        Commands::Db {
+            action: DbAction::Template {
+                action: DbTemplateAction::Refresh { profile: Some(ref profile) }
+            }
+        } if profile == "qa"
+    ));
+
+    let cli = Cli::try_parse_from(["scratchpad", "db", "template", "refresh"])
+        .expect("Failed to parse db template refresh command");
+    assert!(matches!(
+        cli.command,
+        Commands::Db {
+            action: DbAction::Template {
+                action: DbTemplateAction::Refresh { profile: None }
+            }
+        }
+    ));
+    println!("✓ CLI db template refresh command");
+}
+
+#[test]
+fn test_cli_jobs_commands() {
+    let cli = Cli::try_parse_from([
+        "scratchpad",
+        "jobs",
+        "show",
+        "0b7d6a1e",
+        "--wait",
+        "--server",
+        "http://scratchpad:3456",
+    ])
+    .expect("Failed to parse jobs show command");
+    assert!(matches!(
+        cli.command,
+        Commands::Jobs {
+            server: Some(ref server),
+            action: JobsAction::Show { ref id, wait: true }
+        } if server == "http://scratchpad:3456" && id == "0b7d6a1e"
+    ));
+
+    let cli = Cli::try_parse_from(["scratchpad", "jobs", "list"])
+        .expect("Failed to parse jobs list command");
+    assert!(matches!(
+        cli.command,
+        Commands::Jobs {
+            action: JobsAction::List,
+            ..
+        }
+    ));
+    println!("✓ CLI jobs commands");
+}
+
 #[test]
 fn test_cli_create_command_minimal() {
     // Test create with just branch
-    let branch = "feature/test";
-    assert!(!branch.is_empty());
+    let cli = Cli::try_parse_from(["scratchpad", "create", "--branch", "feature/test"])
+        .expect("Failed to parse create command");
+    assert!(
+        matches!(cli.command, Commands::Create { ref branch, .. } if branch == &["feature/test"])
+    );
     println!("✓ CLI create with minimal flags");
 }
 
+#[test]
+fn test_cli_create_branch_per_repo() {
+    let cli = Cli::try_parse_from([
+        "scratchpad",
+        "create",
+        "--branch",
+        "api=feat-x",
+        "-b",
+        "web=main",
+    ])
+    .expect("Failed to parse create command");
+    assert!(matches!(
+        cli.command,
+        Commands::Create { ref branch, .. } if branch == &["api=feat-x", "web=main"]
+    ));
+
+    assert!(Cli::try_parse_from(["scratchpad", "create"]).is_err());
+    println!("✓ CLI create with a branch per repository");
+}
+
 #[test]
 fn test_cli_command_enum_variants() {
     // Test that all command variants are available
@@ -290,7 +686,7 @@ fn test_output_format_variants() {
     use scratchpad::cli::OutputFormat;
 
     // Test that all output formats can be used
-    let formats = vec![OutputFormat::Table, OutputFormat::Json, OutputFormat::Yaml];
+    let formats = [OutputFormat::Table, OutputFormat::Json, OutputFormat::Yaml];
 
     assert_eq!(formats.len(), 3);
     println!("✓ All output format variants available");
@@ -330,6 +726,7 @@ async fn test_cli_init_then_config_load() {
 }
 
 #[test]
+#[allow(clippy::if_same_then_else)]
 fn test_cli_create_name_options() {
     // Test various name option scenarios
     let test_cases = vec![
@@ -3,6 +3,12 @@
 //!
 //! Run with: cargo test --test concurrent_tests -- --ignored --test-threads=1
 
+#![allow(
+    clippy::clone_on_copy,
+    clippy::needless_borrows_for_generic_args,
+    clippy::useless_vec
+)]
+
 use scratchpad::api::run_server;
 use scratchpad::config::Config;
 use std::time::Duration;
@@ -55,4 +55,59 @@ mod tests {
             }
         }
     }
+
+    /// Run the docker CLI, returning what it printed
+    fn docker(args: &[&str]) -> String {
+        let output = std::process::Command::new("docker")
+            .args(args)
+            .output()
+            .expect("docker CLI");
+        assert!(output.status.success(), "docker {:?} failed", args);
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[tokio::test]
+    #[ignore] // Run with: cargo test -- --ignored
+    async fn test_registry_digest_resolver() {
+        use scratchpad::config::WatchConfig;
+        use scratchpad::scratch::{DigestResolver, RegistryDigestResolver};
+
+        let registry = "scratchpad-test-registry";
+        let _ = std::process::Command::new("docker")
+            .args(["rm", "-f", registry])
+            .output();
+        docker(&[
+            "run",
+            "-d",
+            "--name",
+            registry,
+            "-p",
+            "5055:5000",
+            "registry:2",
+        ]);
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+        let image = "localhost:5055/scratchpad-watch:main";
+        docker(&["pull", "busybox:latest"]);
+        docker(&["tag", "busybox:latest", image]);
+        docker(&["push", image]);
+        let pushed = docker(&["inspect", "--format", "{{index .RepoDigests 0}}", image]);
+
+        let resolver = RegistryDigestResolver::new(&WatchConfig {
+            insecure_registries: vec!["localhost:5055".to_string()],
+            ..Default::default()
+        });
+        let digest = resolver.digest(image).await.unwrap();
+        let missing = resolver
+            .digest("localhost:5055/scratchpad-watch:missing")
+            .await
+            .unwrap();
+
+        docker(&["rm", "-f", registry]);
+        assert_eq!(
+            digest.as_deref(),
+            pushed.rsplit_once('@').map(|(_, digest)| digest)
+        );
+        assert_eq!(missing, None);
+    }
 }
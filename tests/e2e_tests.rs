@@ -51,9 +51,10 @@ async fn test_e2e_scratch_creation_with_services() {
         &config,
         &docker,
         "feature-e2e",
-        Some(test_name.to_string()),
-        None,
-        None,
+        scratch::CreateScratchOptions {
+            name: Some(test_name.to_string()),
+            ..Default::default()
+        },
     )
     .await
     {
@@ -151,9 +152,10 @@ async fn test_e2e_multiple_scratches() {
             &config,
             &docker,
             &format!("branch-{}", i),
-            Some(name.to_string()),
-            None,
-            None,
+            scratch::CreateScratchOptions {
+                name: Some(name.to_string()),
+                ..Default::default()
+            },
         )
         .await
         {
@@ -245,9 +247,10 @@ async fn test_e2e_nginx_config_regeneration() {
             &config,
             &docker,
             &format!("nginx-branch-{}", i),
-            Some(name.to_string()),
-            None,
-            None,
+            scratch::CreateScratchOptions {
+                name: Some(name.to_string()),
+                ..Default::default()
+            },
         )
         .await
         {
@@ -300,7 +303,14 @@ async fn test_e2e_scratch_name_sanitization() {
     for (branch, expected_name) in test_cases {
         cleanup_scratches(&config, &[expected_name]);
 
-        match scratch::create_scratch(&config, &docker, branch, None, None, None).await {
+        match scratch::create_scratch(
+            &config,
+            &docker,
+            branch,
+            scratch::CreateScratchOptions::default(),
+        )
+        .await
+        {
             Ok(created) => {
                 assert_eq!(created.name, expected_name);
                 println!("✓ Branch '{}' → '{}'", branch, created.name);
@@ -227,6 +227,7 @@ fn test_config_toml_serialization() {
 // ============================================================================
 
 #[test]
+#[allow(clippy::const_is_empty)]
 fn test_error_env_interpolation_missing_var() {
     // Missing environment variables should use default or empty
     std::env::remove_var("NONEXISTENT_VAR_FOR_TEST");
@@ -332,6 +333,7 @@ fn test_boundary_dash_only_name() {
 // ============================================================================
 
 #[test]
+#[allow(clippy::manual_range_contains)]
 fn test_error_port_number_out_of_range() {
     // Port numbers should be 1-65535
     // This tests validation logic
@@ -2,6 +2,8 @@
 //!
 //! These tests require Docker to be running
 
+#![allow(unused_variables)]
+
 use scratchpad::config::{Config, DockerConfig};
 use scratchpad::docker::DockerClient;
 
@@ -48,9 +48,10 @@ async fn test_create_scratch() {
         &config,
         &docker,
         "test-branch",
-        Some(test_name.to_string()),
-        None,
-        None,
+        scratch::CreateScratchOptions {
+            name: Some(test_name.to_string()),
+            ..Default::default()
+        },
     )
     .await
     {
@@ -95,9 +96,10 @@ async fn test_list_scratches() {
             &config,
             &docker,
             &format!("branch-{}", i),
-            Some(name.to_string()),
-            None,
-            None,
+            scratch::CreateScratchOptions {
+                name: Some(name.to_string()),
+                ..Default::default()
+            },
         )
         .await
         {
@@ -150,9 +152,10 @@ async fn test_scratch_status() {
         &config,
         &docker,
         "status-branch",
-        Some(test_name.to_string()),
-        None,
-        None,
+        scratch::CreateScratchOptions {
+            name: Some(test_name.to_string()),
+            ..Default::default()
+        },
     )
     .await
     {
@@ -192,9 +195,10 @@ async fn test_start_stop_scratch() {
         &config,
         &docker,
         "feature-branch",
-        Some(test_name.to_string()),
-        None,
-        None,
+        scratch::CreateScratchOptions {
+            name: Some(test_name.to_string()),
+            ..Default::default()
+        },
     )
     .await
     {
@@ -240,9 +244,10 @@ async fn test_restart_scratch() {
         &config,
         &docker,
         "main-branch",
-        Some(test_name.to_string()),
-        None,
-        None,
+        scratch::CreateScratchOptions {
+            name: Some(test_name.to_string()),
+            ..Default::default()
+        },
     )
     .await
     {
@@ -280,9 +285,10 @@ async fn test_delete_scratch() {
         &config,
         &docker,
         "delete-branch",
-        Some(test_name.to_string()),
-        None,
-        None,
+        scratch::CreateScratchOptions {
+            name: Some(test_name.to_string()),
+            ..Default::default()
+        },
     )
     .await
     {
@@ -326,9 +332,10 @@ async fn test_cannot_create_duplicate_scratch() {
         &config,
         &docker,
         "branch-1",
-        Some(test_name.to_string()),
-        None,
-        None,
+        scratch::CreateScratchOptions {
+            name: Some(test_name.to_string()),
+            ..Default::default()
+        },
     )
     .await
     {
@@ -340,9 +347,10 @@ async fn test_cannot_create_duplicate_scratch() {
         &config,
         &docker,
         "branch-2",
-        Some(test_name.to_string()),
-        None,
-        None,
+        scratch::CreateScratchOptions {
+            name: Some(test_name.to_string()),
+            ..Default::default()
+        },
     )
     .await
     {
@@ -88,7 +88,7 @@ async fn test_postgres_database_creation() {
     // It's skipped by default as it requires actual database access
     let db_name = "scratchpad_test_db";
 
-    match services::create_postgres_database(&config, db_name).await {
+    match services::create_postgres_database(&config, db_name, None).await {
         Ok(()) => {
             println!("✓ Successfully created PostgreSQL database: {}", db_name);
         }
@@ -110,7 +110,7 @@ async fn test_postgres_database_deletion() {
     let db_name = "scratchpad_test_delete";
 
     // First create a database
-    if let Err(e) = services::create_postgres_database(&config, db_name).await {
+    if let Err(e) = services::create_postgres_database(&config, db_name, None).await {
         println!("⚠ Skipping: Could not create test database: {}", e);
         return;
     }
@@ -155,7 +155,7 @@ async fn test_database_name_validation() {
     let invalid_names = vec!["test-db", "test db", "test@db", "test.db"];
 
     for name in invalid_names {
-        match services::create_postgres_database(&config, name).await {
+        match services::create_postgres_database(&config, name, None).await {
             Ok(()) => println!("⚠ Should reject invalid name: {}", name),
             Err(_) => {
                 println!("✓ Correctly rejected invalid database name: {}", name);
@@ -173,7 +173,7 @@ async fn test_valid_database_names() {
     let valid_names = vec!["scratch_test_1", "scratch_test_2", "scratchpad_db"];
 
     for name in valid_names {
-        match services::create_postgres_database(&config, name).await {
+        match services::create_postgres_database(&config, name, None).await {
             Ok(()) => {
                 println!("✓ Successfully created database with valid name: {}", name);
                 // Cleanup